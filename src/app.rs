@@ -1,35 +1,67 @@
-use std::path::PathBuf;
-use std::rc::Rc;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
-use std::{io, thread};
+use std::{fs, io};
 
 use crossterm::event::*;
-use devicons::Theme;
-use ratatui::{DefaultTerminal, widgets::*};
+use ratatui::DefaultTerminal;
 
-use crate::app_properties::{AppMode, AppProperties, EditAction};
-use crate::manager::{self, *};
-use crate::offset_buffer::{self, OffsetBuffer};
+use crate::app_properties::{AppMode, AppProperties, EditAction, ViewMode};
+use crate::archive::{self, ArchiveDir};
+use crate::command::{self, BookmarkAction, Command, CompletionContext, Setting};
+use crate::database::{self, DatabaseBrowser};
+use crate::filter::{find_match, fuzzy_match};
+use crate::key_parser::{Action, KeyParser, Motion, Operator};
+use crate::macros::MacroRecorder;
+use crate::manager::*;
+use crate::preview::{Preview, data::Value};
+use crate::rename;
+use crate::sort::{SortMode, SortOptions};
 use crate::ui::*;
+use crate::xdg;
+
+/// Shown when something would change the archive that is browsed
+const READ_ONLY: &str = "The archive is read-only, x extracts";
+
+/// The last file action, repeated by '.'
+#[derive(Clone)]
+struct LastEdit {
+    action: EditAction,
+    value: String,
+    count: usize,
+}
 
 /// A struct representing the App. It holds state and handles user events.
 pub struct App<'a> {
     properties: AppProperties,
     ui: UI<'a>,
-    offset_buffer: OffsetBuffer,
+    key_parser: KeyParser,
+    macros: MacroRecorder,
+    last_edit: Option<LastEdit>,
+    find_origin: usize,
+    /// Set by R, the main loop opens these names in $EDITOR
+    editor_paths: Option<Vec<PathBuf>>,
+}
+
+impl Default for App<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<'a> App<'a> {
     /// Creates an instance of *App*
     pub fn new() -> Self {
-        let mut properties = AppProperties::new();
-        let mut app = Self {
-            ui: UI::new(&properties),
-            properties: properties,
-            offset_buffer: OffsetBuffer::new(),
-        };
+        let properties = AppProperties::new();
 
-        app
+        Self {
+            ui: UI::new(&properties),
+            properties,
+            key_parser: KeyParser::new(),
+            macros: MacroRecorder::new(),
+            last_edit: None,
+            find_origin: 0,
+            editor_paths: None,
+        }
     }
 
     /// Start the app. this is the main loop where *ui updates* and
@@ -38,32 +70,68 @@ impl<'a> App<'a> {
         let mut app = App::new();
         while !app.properties.exit {
             terminal.draw(|f| app.ui.draw(f, &mut app.properties))?;
+            app.ui.draw_graphics(terminal, &app.properties)?;
             app.correct_ml_state();
+            app.collect_jobs();
+            if app.key_parser.check_timeout() {
+                app.properties.pending_keys.clear();
+            }
 
-            if crossterm::event::poll(std::time::Duration::from_millis(50))? {
-                if let Event::Key(key) = crossterm::event::read()? {
-                    app.handle_key_event(&key)?;
-                }
-                //Makes sure cursor is set when searching, as there is no movement event to trigger
-                //this
+            if crossterm::event::poll(std::time::Duration::from_millis(50))?
+                && let Event::Key(key) = crossterm::event::read()?
+            {
+                app.handle_key_event(&key)?;
             }
+            if let Some(paths) = app.editor_paths.take() {
+                app.edit_names(terminal, &paths)?;
+            }
+            //Makes sure cursor is set when searching, as there is no movement event to trigger
+            //this
         }
+        app.ui.clear_graphics(terminal)?;
 
         Ok(())
     }
 
     /// Handles a key related event from the user
     pub fn handle_key_event(&mut self, key_event: &KeyEvent) -> io::Result<()> {
-        if self.properties.mode == AppMode::Normal && key_event.kind == KeyEventKind::Press {
-            self.handle_normal_mode(&key_event);
-        } else if self.properties.mode == AppMode::Search && key_event.kind == KeyEventKind::Press {
-            self.handle_search_mode(key_event);
-        } else if self.properties.mode == AppMode::Navigate && key_event.kind == KeyEventKind::Press
+        if key_event.kind != KeyEventKind::Press {
+            return Ok(());
+        }
+
+        self.properties.message = None;
+
+        // A plain q ends the recording, it isn't part of the macro
+        if self.macros.get_recording().is_some()
+            && self.properties.mode == AppMode::Normal
+            && self.key_parser.get_pending().is_empty()
+            && key_event.code == KeyCode::Char('q')
         {
-            self.handle_nav_mode(key_event);
-        } else if self.properties.mode == AppMode::Theme && key_event.kind == KeyEventKind::Press {
+            self.macros.stop();
+            self.properties.recording = None;
+            return Ok(());
+        }
+        self.macros.record(key_event);
+
+        if self.properties.mode == AppMode::Normal {
+            self.handle_normal_mode(key_event);
+        } else if self.properties.mode == AppMode::Search {
+            self.handle_search_mode(key_event);
+        } else if self.properties.mode == AppMode::Command {
+            self.handle_command_mode(key_event);
+        } else if self.properties.mode == AppMode::Theme {
             self.handle_theme_mode(key_event);
-        } else if key_event.kind == KeyEventKind::Press {
+        } else if self.properties.mode == AppMode::Filter {
+            self.handle_filter_mode(key_event);
+        } else if self.properties.mode == AppMode::Find {
+            self.handle_find_mode(key_event);
+        } else if self.properties.mode == AppMode::BulkRename {
+            self.handle_bulk_rename_mode(key_event);
+        } else if self.properties.mode == AppMode::View {
+            self.handle_view_mode(key_event);
+        } else if self.properties.mode == AppMode::Database {
+            self.handle_database_mode(key_event);
+        } else {
             self.handle_edit_mode(key_event);
         }
 
@@ -75,35 +143,34 @@ impl<'a> App<'a> {
 
     /// Handles normal mode keyevents, modifiers
     pub fn handle_normal_mode(&mut self, key_event: &KeyEvent) {
-        self.offset_buffer.buff_event(&key_event);
+        let action = self.key_parser.feed(key_event);
+        self.properties.pending_keys = self.key_parser.get_pending().to_string();
+
+        match action {
+            Action::Pending | Action::Cancelled => {}
+            Action::Move(motion) => self.apply_motion(motion),
+            Action::Operate(operator, count) => self.apply_operator(operator, count),
+            Action::Prefixed(prefix, code, count) => self.handle_prefixed_key(prefix, code, count),
+            Action::Key(key_event, count) => self.handle_normal_key(&key_event, count),
+        }
+    }
+
+    /// Handles single key normal mode commands
+    fn handle_normal_key(&mut self, key_event: &KeyEvent, count: Option<usize>) {
         match key_event.code {
-            KeyCode::Char('q') => {
+            KeyCode::Char('Q') => {
                 self.properties.exit = true;
                 self.properties.manager.shutdown();
             }
-            KeyCode::Down | KeyCode::Char('j') => {
-                if let Some(selected) = self.properties.main_list_state.selected() {
-                    let offset = self.offset_buffer.get_offset();
-                    let next = (selected + offset).min(
-                        self.properties
-                            .items
-                            .lock()
-                            .unwrap()
-                            .len()
-                            .saturating_sub(1),
-                    );
-                    self.generate_cursor(next);
-                    self.properties.main_list_state.select(Some(next));
-                }
-            }
-            KeyCode::Up | KeyCode::Char('k') => {
-                if let Some(selected) = self.properties.main_list_state.selected() {
-                    let offset = self.offset_buffer.get_offset();
-                    let prev = selected.saturating_sub(offset);
-                    self.generate_cursor(prev);
-                    self.properties.main_list_state.select(Some(prev));
-                }
+            KeyCode::Char('a' | 'r' | 'R' | 'm' | 'c' | 'C' | 'p' | '.')
+                if self.properties.archive.is_some() =>
+            {
+                self.properties.message = Some(String::from(READ_ONLY));
             }
+            KeyCode::Char('x') if self.properties.archive.is_some() => self.extract_members(count),
+            KeyCode::Char('x') => self.extract_archive(),
+            KeyCode::Char('C') => self.start_compress(count),
+            KeyCode::Char('.') => self.repeat_last_edit(),
             KeyCode::Enter | KeyCode::Char('l') => {
                 if let Some(selected) = self.properties.main_list_state.selected() {
                     if self
@@ -125,8 +192,25 @@ impl<'a> App<'a> {
                         .unwrap()
                         .clone();
 
-                    if new_path.is_dir() {
+                    let archive_items = self
+                        .properties
+                        .archive
+                        .as_mut()
+                        .filter(|archive| archive.is_dir(&new_path))
+                        .map(|archive| archive.enter(&new_path, selected));
+                    if let Some(items) = archive_items {
+                        self.drop_live_filter();
+                        self.set_archive_items(items, 0);
+                    } else if self.is_archive_file(&new_path) {
+                        // Nothing can open a file inside the archive, it gets shown instead
+                        self.open_viewer();
+                    } else if new_path.is_dir() {
                         self.change_dir(new_path);
+                    } else if new_path.is_file() && archive::Format::from_path(&new_path).is_some()
+                    {
+                        self.open_archive(new_path);
+                    } else if new_path.is_file() && database::is_database(&new_path) {
+                        self.open_database(&new_path);
                     } else if new_path.is_file() {
                         _ = open::that_detached(new_path);
                     }
@@ -139,18 +223,588 @@ impl<'a> App<'a> {
                 self.properties.mode = AppMode::Search;
             }
             KeyCode::Char(':') => {
-                self.properties.mode = AppMode::Navigate;
+                self.properties.mode = AppMode::Command;
             }
-            KeyCode::Char('a') => self.properties.mode = AppMode::Edit(EditAction::Create),
-            KeyCode::Char('d') => self.properties.mode = AppMode::Edit(EditAction::Delete),
-            KeyCode::Char('r') => self.properties.mode = AppMode::Edit(EditAction::Rename),
-            KeyCode::Char('m') => self.properties.mode = AppMode::Edit(EditAction::Move),
-            KeyCode::Char('c') => self.properties.mode = AppMode::Edit(EditAction::Copy),
+            KeyCode::Char('a') => self.start_edit(EditAction::Create),
+            KeyCode::Char('r') => self.start_rename(),
+            KeyCode::Char('R') => self.start_bulk_rename(count),
+            KeyCode::Char('m') => self.start_edit(EditAction::Move),
+            KeyCode::Char('c') => self.start_edit(EditAction::Copy),
+            KeyCode::Char('p') => self.paste(),
             KeyCode::Char('t') => self.properties.mode = AppMode::Theme,
+            KeyCode::Char('v')
+                if self
+                    .properties
+                    .cursor
+                    .0
+                    .as_ref()
+                    .is_some_and(|p| p.is_file() || self.is_archive_file(p)) =>
+            {
+                self.open_viewer();
+            }
+            KeyCode::Char('F') => self.properties.mode = AppMode::Filter,
+            KeyCode::Char('/') => {
+                self.find_origin = self.properties.main_list_state.selected().unwrap_or(0);
+                self.properties.find_input.clear();
+                self.properties.mode = AppMode::Find;
+            }
+            KeyCode::Char('n') => self.find_next(count.unwrap_or(1), true),
+            KeyCode::Char('N') => self.find_next(count.unwrap_or(1), false),
+            KeyCode::Esc => {
+                if self.properties.find_pattern.take().is_some() {
+                    self.ui.set_main_items(&self.properties);
+                }
+                self.clear_live_filter();
+            }
+            KeyCode::Tab => self.toggle_tree_item(),
             _ => {}
         }
     }
 
+    /// Opens the edit prompt for *action*, remembering the item it was opened on
+    fn start_edit(&mut self, action: EditAction) {
+        self.properties.edit_target = self.properties.cursor.0.clone();
+        self.properties.mode = AppMode::Edit(action);
+    }
+
+    /// Opens the rename prompt with the current name in it, the cursor in front of the extension
+    fn start_rename(&mut self) {
+        let Some(path) = self.properties.cursor.0.clone() else {
+            return;
+        };
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        self.properties.edit_input.set_value(&name);
+        if !path.is_dir() {
+            let stem = stem_len(&name);
+            self.properties
+                .edit_input
+                .handle(input::InputType::SetCursor(stem));
+        }
+        self.start_edit(EditAction::Rename);
+    }
+
+    /// Opens the names of *count* items from the cursor, or of the whole directory, in $EDITOR.
+    /// The editor runs from the main loop, which owns the terminal
+    fn start_bulk_rename(&mut self, count: Option<usize>) {
+        let paths = self.items_from_cursor(count);
+        if !paths.is_empty() {
+            self.editor_paths = Some(paths);
+        }
+    }
+
+    /// Returns *count* items starting at the cursor, or every item without a count
+    fn items_from_cursor(&self, count: Option<usize>) -> Vec<PathBuf> {
+        let selected = self.properties.main_list_state.selected().unwrap_or(0);
+        let items = self.properties.items.lock().unwrap();
+        match count {
+            Some(count) => items.iter().skip(selected).take(count).cloned().collect(),
+            None => items.clone(),
+        }
+    }
+
+    /// Opens the pattern rename prompt for *count* items from the cursor, or the whole directory
+    fn start_pattern_rename(&mut self, count: Option<usize>) {
+        let paths = self.items_from_cursor(count);
+        if !paths.is_empty() {
+            self.properties.targets = paths;
+            self.properties.renames.clear();
+            self.properties.rename_table_state.select(Some(0));
+            self.start_edit(EditAction::PatternRename);
+        }
+    }
+
+    /// Opens the compress prompt for *count* items from the cursor, with an archive name in it
+    fn start_compress(&mut self, count: Option<usize>) {
+        let paths = self.items_from_cursor(Some(count.unwrap_or(1)));
+        let name = match paths.as_slice() {
+            [path] => path.file_name(),
+            _ => self.properties.get_current_path().file_name(),
+        };
+        let Some(name) = name.map(|name| name.to_string_lossy().into_owned()) else {
+            return;
+        };
+        let value = format!("{name}.tar.gz");
+        self.properties.edit_input.set_value(&value);
+        self.properties
+            .edit_input
+            .handle(input::InputType::SetCursor(name.len()));
+        self.properties.targets = paths;
+        self.start_edit(EditAction::Compress);
+    }
+
+    /// Updates the before/after table while the pattern is typed, problems go to the status bar
+    fn preview_pattern_rename(&mut self) {
+        let value = self.properties.edit_input.get_value();
+        if value.is_empty() {
+            self.properties.renames.clear();
+            return;
+        }
+        match rename::Pattern::parse(value).and_then(|p| p.apply(&self.properties.targets)) {
+            Ok(renames) => self.properties.renames = renames,
+            Err(e) => {
+                self.properties.renames.clear();
+                self.properties.message = Some(e);
+            }
+        }
+    }
+
+    /// Suspends the terminal while the names of *paths* are edited in $EDITOR, then shows the
+    /// resulting renames for confirmation
+    fn edit_names(&mut self, terminal: &mut DefaultTerminal, paths: &[PathBuf]) -> io::Result<()> {
+        // Removed when it goes out of scope, however this returns
+        let file = match rename::write_names(paths) {
+            Ok(file) => file,
+            Err(e) => {
+                self.properties.message = Some(e);
+                return Ok(());
+            }
+        };
+
+        let editor = ["VISUAL", "EDITOR"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|editor| !editor.trim().is_empty())
+            .unwrap_or_else(|| String::from("vi"));
+        ratatui::restore();
+        // The editor variable may carry arguments, like "code --wait"
+        let mut args = editor.split_whitespace();
+        let status = std::process::Command::new(args.next().unwrap_or("vi"))
+            .args(args)
+            .arg(file.get_path())
+            .status();
+        // Resume the same terminal, init() would stack another panic hook on every run
+        crossterm::terminal::enable_raw_mode()?;
+        crossterm::execute!(io::stdout(), crossterm::terminal::EnterAlternateScreen)?;
+        terminal.clear()?;
+        self.ui.forget_graphics();
+
+        let renames = match status {
+            Ok(status) if status.success() => fs::read_to_string(file.get_path())
+                .map_err(|e| e.to_string())
+                .and_then(|text| rename::from_lines(paths, &text)),
+            Ok(status) => Err(format!("{editor} exited with {status}")),
+            Err(e) => Err(format!("{editor}: {e}")),
+        };
+        match renames {
+            Ok(renames) if renames.is_empty() => {
+                self.properties.message = Some(String::from("Nothing to rename"));
+            }
+            Ok(renames) => {
+                self.properties.renames = renames;
+                self.properties.rename_table_state.select(Some(0));
+                self.properties.mode = AppMode::BulkRename;
+            }
+            Err(e) => self.properties.message = Some(e),
+        }
+        Ok(())
+    }
+
+    /// Shows the preview of the cursor item fullscreen, from the top
+    fn open_viewer(&mut self) {
+        self.properties.viewer_scroll = 0;
+        self.properties.viewer_source = false;
+        self.properties.tree_expanded.clear();
+        self.properties.tree_cursor = 0;
+        self.properties.mode = AppMode::View;
+    }
+
+    /// Scrolls the fullscreen preview, q, v or Esc close it. The UI keeps the scroll in bounds
+    fn handle_view_mode(&mut self, key_event: &KeyEvent) {
+        // Data files show their key tree, Tab switches to the pretty-printed text
+        let preview = (self.properties.cursor.0.as_ref())
+            .and_then(|path| self.properties.previewer.get(path));
+        if let Some(Preview::Markdown(_)) = preview.as_deref()
+            && key_event.code == KeyCode::Tab
+        {
+            self.properties.markdown_source = !self.properties.markdown_source;
+            self.properties.viewer_scroll = 0;
+            return;
+        }
+        if let Some(Preview::Data(data)) = preview.as_deref() {
+            if key_event.code == KeyCode::Tab {
+                self.properties.viewer_source = !self.properties.viewer_source;
+                self.properties.viewer_scroll = 0;
+                return;
+            }
+            if let Ok(document) = &data.document
+                && !self.properties.viewer_source
+            {
+                self.handle_tree_key(document, key_event);
+                return;
+            }
+        }
+
+        let scroll = self.properties.viewer_scroll;
+        let page = self.properties.viewer_height.max(1);
+        let ctrl = key_event.modifiers == KeyModifiers::CONTROL;
+        self.properties.viewer_scroll = match key_event.code {
+            KeyCode::Char('q') | KeyCode::Char('v') | KeyCode::Esc => {
+                self.properties.mode = AppMode::Normal;
+                0
+            }
+            KeyCode::Char('d') if ctrl => scroll + page / 2,
+            KeyCode::Char('u') if ctrl => scroll.saturating_sub(page / 2),
+            KeyCode::Char('f') if ctrl => scroll + page,
+            KeyCode::Char('b') if ctrl => scroll.saturating_sub(page),
+            KeyCode::PageDown | KeyCode::Char(' ') => scroll + page,
+            KeyCode::PageUp => scroll.saturating_sub(page),
+            KeyCode::Down | KeyCode::Char('j') => scroll + 1,
+            KeyCode::Up | KeyCode::Char('k') => scroll.saturating_sub(1),
+            KeyCode::Home | KeyCode::Char('g') => 0,
+            KeyCode::End | KeyCode::Char('G') => usize::MAX,
+            _ => scroll,
+        };
+    }
+
+    /// Moves through the key tree of a data file in the viewer. l and h expand and collapse,
+    /// h on a collapsed node goes to its parent
+    fn handle_tree_key(&mut self, document: &Value, key_event: &KeyEvent) {
+        let rows = document.rows(&self.properties.tree_expanded);
+        let last = rows.len().saturating_sub(1);
+        let cursor = self.properties.tree_cursor.min(last);
+        let page = self.properties.viewer_height.max(1);
+        let ctrl = key_event.modifiers == KeyModifiers::CONTROL;
+        let Some(row) = rows.get(cursor) else {
+            if matches!(key_event.code, KeyCode::Char('q' | 'v') | KeyCode::Esc) {
+                self.properties.mode = AppMode::Normal;
+            }
+            return;
+        };
+
+        self.properties.tree_cursor = match key_event.code {
+            KeyCode::Char('q') | KeyCode::Char('v') | KeyCode::Esc => {
+                self.properties.mode = AppMode::Normal;
+                0
+            }
+            KeyCode::Char('d') if ctrl => cursor + page / 2,
+            KeyCode::Char('u') if ctrl => cursor.saturating_sub(page / 2),
+            KeyCode::Char('f') if ctrl => cursor + page,
+            KeyCode::Char('b') if ctrl => cursor.saturating_sub(page),
+            KeyCode::PageDown | KeyCode::Char(' ') => cursor + page,
+            KeyCode::PageUp => cursor.saturating_sub(page),
+            KeyCode::Down | KeyCode::Char('j') => cursor + 1,
+            KeyCode::Up | KeyCode::Char('k') => cursor.saturating_sub(1),
+            KeyCode::Home | KeyCode::Char('g') => 0,
+            KeyCode::End | KeyCode::Char('G') => last,
+            KeyCode::Enter if row.expanded => {
+                self.properties.tree_expanded.remove(&row.path);
+                cursor
+            }
+            KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => {
+                if row.value.is_container() {
+                    self.properties.tree_expanded.insert(row.path.clone());
+                }
+                cursor
+            }
+            KeyCode::Left | KeyCode::Char('h') if row.expanded => {
+                self.properties.tree_expanded.remove(&row.path);
+                cursor
+            }
+            KeyCode::Left | KeyCode::Char('h') => {
+                let parent = &row.path[..row.path.len().saturating_sub(1)];
+                rows.iter()
+                    .position(|other| other.path == parent)
+                    .unwrap_or(cursor)
+            }
+            _ => cursor,
+        }
+        .min(last);
+    }
+
+    /// Opens a database read-only in the table browser, on its first table
+    fn open_database(&mut self, path: &Path) {
+        match DatabaseBrowser::open(path) {
+            Ok(browser) => {
+                self.properties.database = Some(browser);
+                self.properties.viewer_scroll = 0;
+                self.properties.viewer_column = 0;
+                self.properties.mode = AppMode::Database;
+            }
+            Err(e) => self.properties.message = Some(e),
+        }
+    }
+
+    /// Pages through the rows of the browsed table, h and l scroll the columns and Tab switches
+    /// tables. q or Esc close the database. The UI keeps the scroll in bounds
+    fn handle_database_mode(&mut self, key_event: &KeyEvent) {
+        let Some(browser) = &mut self.properties.database else {
+            self.properties.mode = AppMode::Normal;
+            return;
+        };
+        let scroll = self.properties.viewer_scroll;
+        let column = self.properties.viewer_column;
+        let page = self.properties.viewer_height.max(1);
+        let ctrl = key_event.modifiers == KeyModifiers::CONTROL;
+        match key_event.code {
+            KeyCode::Char('q') | KeyCode::Esc => {
+                self.properties.database = None;
+                self.properties.mode = AppMode::Normal;
+            }
+            KeyCode::Tab | KeyCode::BackTab => {
+                browser.cycle_table(if key_event.code == KeyCode::Tab {
+                    1
+                } else {
+                    -1
+                });
+                self.properties.viewer_scroll = 0;
+                self.properties.viewer_column = 0;
+            }
+            KeyCode::Right | KeyCode::Char('l') => self.properties.viewer_column = column + 1,
+            KeyCode::Left | KeyCode::Char('h') => {
+                self.properties.viewer_column = column.saturating_sub(1);
+            }
+            KeyCode::Char('0') => self.properties.viewer_column = 0,
+            code => {
+                self.properties.viewer_scroll = match code {
+                    KeyCode::Char('d') if ctrl => scroll + page / 2,
+                    KeyCode::Char('u') if ctrl => scroll.saturating_sub(page / 2),
+                    KeyCode::Char('f') if ctrl => scroll + page,
+                    KeyCode::Char('b') if ctrl => scroll.saturating_sub(page),
+                    KeyCode::PageDown | KeyCode::Char(' ') => scroll + page,
+                    KeyCode::PageUp => scroll.saturating_sub(page),
+                    KeyCode::Down | KeyCode::Char('j') => scroll + 1,
+                    KeyCode::Up | KeyCode::Char('k') => scroll.saturating_sub(1),
+                    KeyCode::Home | KeyCode::Char('g') => 0,
+                    KeyCode::End | KeyCode::Char('G') => usize::MAX,
+                    _ => scroll,
+                }
+            }
+        }
+    }
+
+    /// Confirms (y, Enter) or drops (n, Esc) the planned renames. j/k scroll through them
+    fn handle_bulk_rename_mode(&mut self, key_event: &KeyEvent) {
+        match key_event.code {
+            KeyCode::Char('y') | KeyCode::Enter => {
+                let renames = std::mem::take(&mut self.properties.renames);
+                // Things may have changed while the list was up
+                let result = rename::check(&renames)
+                    .and_then(|_| self.properties.manager.bulk_rename(&renames));
+                self.properties.message = Some(match result {
+                    Ok(_) => format!("Renamed {} item(s)", renames.len()),
+                    Err(e) => e,
+                });
+                self.properties.mode = AppMode::Normal;
+                self.reload_dir();
+            }
+            KeyCode::Char('n') | KeyCode::Char('q') | KeyCode::Esc => {
+                self.properties.renames.clear();
+                self.properties.mode = AppMode::Normal;
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.properties.rename_table_state.select_next();
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.properties.rename_table_state.select_previous();
+            }
+            _ => {}
+        }
+    }
+
+    /// Selects a part of the name in the rename prompt: Alt-s the stem, Alt-e the extension,
+    /// Alt-a everything
+    fn select_name_part(&mut self, part: char) {
+        let input = &mut self.properties.edit_input;
+        let name = input.get_value().clone();
+        let len = name.chars().count();
+        let stem = stem_len(&name);
+        match part {
+            's' => input.select(0, stem),
+            // Past the dot, if there is an extension
+            'e' if stem < len => input.select(stem + 1, len),
+            'a' => input.select(0, len),
+            _ => {}
+        }
+    }
+
+    /// Warns while the typed rename target already exists
+    fn check_rename_target(&mut self) {
+        let Some(path) = &self.properties.edit_target else {
+            return;
+        };
+        let name = self.properties.edit_input.get_value();
+        let is_same = path
+            .file_name()
+            .is_some_and(|n| n.to_string_lossy() == *name);
+        if !name.is_empty() && !is_same && path.with_file_name(name).exists() {
+            self.properties.message = Some(format!("{name} already exists"));
+        }
+    }
+
+    /// Moves the main list cursor
+    fn apply_motion(&mut self, motion: Motion) {
+        let len = self.properties.items.lock().unwrap().len();
+        if len == 0 {
+            return;
+        }
+        let last = len - 1;
+        let selected = self.properties.main_list_state.selected().unwrap_or(0);
+        let height = self.properties.list_height.max(1);
+        let top = self.properties.main_list_state.offset();
+
+        let next = match motion {
+            Motion::Down(n) => selected.saturating_add(n),
+            Motion::Up(n) => selected.saturating_sub(n),
+            Motion::Line(n) => n.saturating_sub(1),
+            Motion::Bottom => last,
+            Motion::ScreenTop => top,
+            Motion::ScreenMiddle => top + last.saturating_sub(top).min(height - 1) / 2,
+            Motion::ScreenBottom => top + height - 1,
+            Motion::HalfPageDown => selected.saturating_add(height / 2),
+            Motion::HalfPageUp => selected.saturating_sub(height / 2),
+            Motion::PageDown => selected.saturating_add(height),
+            Motion::PageUp => selected.saturating_sub(height),
+        }
+        .min(last);
+
+        self.generate_cursor(next);
+        self.properties.main_list_state.select(Some(next));
+    }
+
+    /// Applies an operator to *count* items, starting at the cursor
+    fn apply_operator(&mut self, operator: Operator, count: usize) {
+        let selected = self.properties.main_list_state.selected().unwrap_or(0);
+        let targets: Vec<PathBuf> = self
+            .properties
+            .items
+            .lock()
+            .unwrap()
+            .iter()
+            .skip(selected)
+            .take(count)
+            .cloned()
+            .collect();
+        if targets.is_empty() {
+            return;
+        }
+
+        match operator {
+            Operator::Delete if self.properties.archive.is_some() => {
+                self.properties.message = Some(String::from(READ_ONLY));
+            }
+            Operator::Delete => {
+                self.properties.targets = targets;
+                self.start_edit(EditAction::Delete);
+            }
+            Operator::Yank => self.properties.yanked = targets,
+        }
+    }
+
+    /// Copies the yanked items into the current directory. Items yanked inside an archive get
+    /// extracted in the background, one job per archive
+    fn paste(&mut self) {
+        let dir = self.target_dir();
+        let mut extracts: Vec<(PathBuf, Vec<PathBuf>)> = Vec::new();
+        for path in self.properties.yanked.clone() {
+            if !path.exists()
+                && let Some((archive, inner)) = archive::split(&path)
+            {
+                match extracts.iter_mut().find(|(source, _)| *source == archive) {
+                    Some((_, members)) => members.push(inner),
+                    None => extracts.push((archive, vec![inner])),
+                }
+                continue;
+            }
+            _ = self
+                .properties
+                .manager
+                .move_fsitem(path, dir.clone(), MoveOption::Copy);
+        }
+        for (archive, members) in extracts {
+            self.properties
+                .manager
+                .extract_members(archive, members, dir.clone());
+        }
+        self.reload_dir();
+    }
+
+    /// Handles the second key of a two key sequence (ex: zl, zh)
+    fn handle_prefixed_key(&mut self, prefix: char, code: KeyCode, count: Option<usize>) {
+        match (prefix, code) {
+            ('f', KeyCode::Char(c)) => self.jump_to_char(c, count.unwrap_or(1)),
+            ('q', KeyCode::Char(register)) if register.is_ascii_alphanumeric() => {
+                self.macros.start(register);
+                self.properties.recording = Some(register);
+            }
+            ('@', KeyCode::Char(register)) => self.replay_macro(register, count.unwrap_or(1)),
+            ('z', KeyCode::Char('t')) => self.toggle_view_mode(ViewMode::Tree),
+            ('z', KeyCode::Char('f')) => {
+                self.properties.flat_depth = count;
+                self.toggle_view_mode(ViewMode::Flat);
+            }
+            ('z', KeyCode::Char('l')) => self.expand_tree_item(),
+            ('z', KeyCode::Char('h')) => self.collapse_tree_item(),
+            ('z', KeyCode::Char('.')) => {
+                let show = !self.properties.manager.get_filter().shows_hidden();
+                self.set_show_hidden(show);
+            }
+            ('z', KeyCode::Char('H')) => self.start_edit(EditAction::Hide),
+            ('z', KeyCode::Char('r')) => {
+                self.properties.markdown_source = !self.properties.markdown_source
+            }
+            ('g', KeyCode::Char('r')) if self.properties.archive.is_some() => {
+                self.properties.message = Some(String::from(READ_ONLY));
+            }
+            ('g', KeyCode::Char('r')) => self.start_pattern_rename(count),
+            ('z', KeyCode::Char('g')) => {
+                let only = self.properties.manager.get_filter().get_only();
+                let only = only.map(|p| p.as_str().to_string()).unwrap_or_default();
+                self.properties.edit_input.set_value(&only);
+                self.start_edit(EditAction::ShowOnly);
+            }
+            ('s', KeyCode::Char('r')) => self.update_sort(|sort| sort.reverse = !sort.reverse),
+            ('s', KeyCode::Char('d')) => {
+                self.update_sort(|sort| sort.dirs_first = !sort.dirs_first)
+            }
+            ('s', KeyCode::Char(c)) => {
+                let mode = match c.to_ascii_lowercase() {
+                    'n' => SortMode::Name,
+                    's' => SortMode::Size,
+                    'm' => SortMode::Modified,
+                    'c' => SortMode::Changed,
+                    'e' => SortMode::Extension,
+                    't' => SortMode::Type,
+                    _ => return,
+                };
+                // Uppercase sorts in reverse, like sS for largest first
+                let reverse = c.is_ascii_uppercase();
+                self.update_sort(|sort| {
+                    sort.mode = mode;
+                    sort.reverse = reverse;
+                });
+            }
+            _ => {}
+        }
+    }
+
+    /// Shows or hides dotfiles and reloads, keeping the cursor on the same item
+    fn set_show_hidden(&mut self, show: bool) {
+        self.properties
+            .manager
+            .get_filter_mut()
+            .set_show_hidden(show);
+        let cursor = self.properties.cursor.0.clone();
+        self.reload_dir();
+        if let Some(cursor) = cursor {
+            self.select_path(&cursor);
+        }
+    }
+
+    /// Changes how the current directory is sorted and reloads it, keeping the cursor on the
+    /// same item
+    fn update_sort(&mut self, update: impl FnOnce(&mut SortOptions)) {
+        let path = self.properties.get_current_path().clone();
+        let mut sort = self.properties.manager.get_sort(&path);
+        update(&mut sort);
+        self.properties.manager.set_sort(&path, sort);
+
+        let cursor = self.properties.cursor.0.clone();
+        self.reload_dir();
+        if let Some(cursor) = cursor {
+            self.select_path(&cursor);
+        }
+    }
+
     pub fn handle_theme_mode(&mut self, key_event: &KeyEvent) {
         match key_event.code {
             KeyCode::Down | KeyCode::Char('j') => {
@@ -185,240 +839,681 @@ impl<'a> App<'a> {
         }
     }
 
-    /// Handles search mode keyevents, modifiers
-    fn handle_search_mode(&mut self, key_event: &KeyEvent) {
-        match key_event.modifiers {
-            KeyModifiers::CONTROL => {
-                if let KeyCode::Char('h') = key_event.code {
-                    self.properties
-                        .search_input
-                        .handle(input::InputType::DeletePrevWord);
+    /// Handles filter mode keyevents. Every change to the term narrows the listing right away
+    fn handle_filter_mode(&mut self, key_event: &KeyEvent) {
+        match (key_event.modifiers, key_event.code) {
+            (_, KeyCode::Enter) => {
+                if self.properties.filter_input.get_value().is_empty() {
+                    self.clear_live_filter();
+                }
+                self.properties.mode = AppMode::Normal;
+            }
+            (_, KeyCode::Esc) => {
+                self.clear_live_filter();
+                self.properties.mode = AppMode::Normal;
+            }
+            (_, KeyCode::Down) => self.apply_motion(Motion::Down(1)),
+            (_, KeyCode::Up) => self.apply_motion(Motion::Up(1)),
+            _ => {
+                if self.properties.filter_input.handle_key(key_event) {
+                    self.apply_live_filter();
                 }
             }
-            _ => self.handle_searchkey_code(key_event),
         }
     }
 
-    /// Handles navigation mode keyevents, modifiers
-    fn handle_nav_mode(&mut self, key_event: &KeyEvent) {
-        match key_event.modifiers {
-            KeyModifiers::CONTROL => {
-                if let KeyCode::Char('h') = key_event.code {
-                    self.properties
-                        .nav_input
-                        .handle(input::InputType::DeletePrevWord);
-                }
+    /// Narrows the items down to the ones fuzzy matching the filter term. The full listing is
+    /// kept aside, so it can be restored when the term gets cleared
+    fn apply_live_filter(&mut self) {
+        // A flat listing that is still filling in would be filtered half done
+        if self.properties.manager.is_flattening() {
+            return;
+        }
+        let term = self.properties.filter_input.get_value().clone();
+        if term.is_empty() {
+            if let Some(items) = self.properties.unfiltered.take() {
+                *self.properties.items.lock().unwrap() = items;
             }
-            _ => self.handle_navkey_code(key_event),
+        } else {
+            let all = self
+                .properties
+                .unfiltered
+                .get_or_insert_with(|| self.properties.items.lock().unwrap().clone());
+            let matches: Vec<PathBuf> = all
+                .iter()
+                .filter(|path| {
+                    let name = path.file_name().unwrap_or_default().to_string_lossy();
+                    fuzzy_match(&term, &name).is_some()
+                })
+                .cloned()
+                .collect();
+            *self.properties.items.lock().unwrap() = matches;
         }
+        self.ui.set_main_items(&self.properties);
+
+        // Stay on the same item while it keeps matching
+        let cursor = self.properties.cursor.0.clone();
+        let idx = cursor
+            .and_then(|cursor| {
+                self.properties
+                    .items
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .position(|item| *item == cursor)
+            })
+            .unwrap_or(0);
+        self.properties.main_list_state.select(Some(idx));
+        self.generate_cursor(idx);
     }
 
-    fn handle_edit_mode(&mut self, key_event: &KeyEvent) {
+    /// Drops the live filter and restores the full listing
+    fn clear_live_filter(&mut self) {
+        self.properties.filter_input.clear();
+        self.apply_live_filter();
+    }
+
+    /// Handles find mode keyevents. The cursor jumps to the first match while typing
+    fn handle_find_mode(&mut self, key_event: &KeyEvent) {
         match key_event.code {
+            KeyCode::Enter => {
+                let value = self.properties.find_input.get_value().clone();
+                self.properties.find_input.commit();
+                self.properties.mode = AppMode::Normal;
+                // An empty pattern repeats the last search, like in vim
+                if value.is_empty() {
+                    self.find_next(1, true);
+                } else {
+                    self.properties.find_pattern = Some(value);
+                }
+                self.properties.find_input.clear();
+                self.ui.set_main_items(&self.properties);
+            }
             KeyCode::Esc => {
-                self.properties.edit_input.clear();
+                self.properties.find_input.clear();
                 self.properties.mode = AppMode::Normal;
-                return;
+                self.select_index(self.find_origin);
+                self.ui.set_main_items(&self.properties);
             }
-            _ => {}
+            KeyCode::Up | KeyCode::Down => {
+                if key_event.code == KeyCode::Up {
+                    self.properties.find_input.history_prev();
+                } else {
+                    self.properties.find_input.history_next();
+                }
+                self.find_incremental();
+            }
+            _ => {
+                if self.properties.find_input.handle_key(key_event) {
+                    self.find_incremental();
+                }
+            }
+        }
+    }
+
+    /// Moves the cursor to the first item matching the typed pattern, starting where the
+    /// search started
+    fn find_incremental(&mut self) {
+        let pattern = self.properties.find_input.get_value().clone();
+        let idx = self
+            .find_from(self.find_origin, true, |name| {
+                find_match(&pattern, name).is_some()
+            })
+            .unwrap_or(self.find_origin);
+        self.select_index(idx);
+        self.ui.set_main_items(&self.properties);
+    }
+
+    /// Jumps to the *count*th next (or previous) item matching the last search pattern (n/N)
+    fn find_next(&mut self, count: usize, forward: bool) {
+        let Some(pattern) = self.properties.find_pattern.clone() else {
+            return;
+        };
+        let mut idx = self.properties.main_list_state.selected().unwrap_or(0);
+        for _ in 0..count {
+            let start = if forward {
+                idx + 1
+            } else {
+                idx.wrapping_sub(1)
+            };
+            match self.find_from(start, forward, |name| find_match(&pattern, name).is_some()) {
+                Some(next) => idx = next,
+                None => return,
+            }
+        }
+        self.select_index(idx);
+    }
+
+    /// Jumps to the *count*th next item whose name starts with *c* (f{char})
+    fn jump_to_char(&mut self, c: char, count: usize) {
+        let lower = |c: char| c.to_lowercase().next().unwrap_or(c);
+        let mut idx = self.properties.main_list_state.selected().unwrap_or(0);
+        for _ in 0..count {
+            let next = self.find_from(idx + 1, true, |name| {
+                name.chars().next().map(lower) == Some(lower(c))
+            });
+            match next {
+                Some(next) => idx = next,
+                None => return,
+            }
+        }
+        self.select_index(idx);
+    }
+
+    /// Returns the index of the first item whose name satisfies *matches*, going through the
+    /// items from *start* in the given direction and wrapping around at the ends
+    fn find_from<F: Fn(&str) -> bool>(
+        &self,
+        start: usize,
+        forward: bool,
+        matches: F,
+    ) -> Option<usize> {
+        let items = self.properties.items.lock().unwrap();
+        let len = items.len();
+        if len == 0 {
+            return None;
+        }
+        // wrapping_sub on 0 lands on usize::MAX, which wraps to the last item here
+        let start = if start >= len && !forward {
+            len - 1
+        } else {
+            start % len
+        };
+        (0..len)
+            .map(|i| {
+                if forward {
+                    (start + i) % len
+                } else {
+                    (start + len - i) % len
+                }
+            })
+            .find(|&idx| {
+                let name = items[idx].file_name().unwrap_or_default().to_string_lossy();
+                matches(&name)
+            })
+    }
+
+    fn select_index(&mut self, idx: usize) {
+        self.properties.main_list_state.select(Some(idx));
+        self.generate_cursor(idx);
+    }
+
+    fn handle_edit_mode(&mut self, key_event: &KeyEvent) {
+        if key_event.code == KeyCode::Esc && self.properties.edit_input.cancel_completion() {
+            return;
+        }
+        if key_event.code == KeyCode::Esc {
+            self.properties.edit_input.clear();
+            self.properties.targets.clear();
+            self.properties.renames.clear();
+            self.properties.edit_target = None;
+            self.properties.mode = AppMode::Normal;
+            return;
         }
 
-        match &self.properties.mode {
-            AppMode::Edit(x) => match x {
-                EditAction::Create | EditAction::Move | EditAction::Copy | EditAction::Rename => {
-                    match key_event.modifiers {
-                        KeyModifiers::CONTROL => {
-                            if let KeyCode::Char('h') = key_event.code {
-                                self.properties
-                                    .edit_input
-                                    .handle(input::InputType::DeletePrevWord);
-                            }
-                        }
-                        _ => self.handle_editkey_code(key_event, x.clone()),
+        if let AppMode::Edit(x) = &self.properties.mode {
+            match x {
+                EditAction::Create
+                | EditAction::Move
+                | EditAction::Copy
+                | EditAction::Rename
+                | EditAction::Hide
+                | EditAction::ShowOnly
+                | EditAction::PatternRename
+                | EditAction::Compress => {
+                    let action = *x;
+                    self.handle_editkey_code(key_event, action);
+                    match self.properties.mode {
+                        AppMode::Edit(EditAction::Rename) => self.check_rename_target(),
+                        AppMode::Edit(EditAction::PatternRename) => self.preview_pattern_rename(),
+                        _ => {}
                     }
                 }
                 EditAction::Delete => {
-                    let idx = self.properties.main_list_state.selected();
-                    if let Some(idx) = idx {
-                        self.generate_cursor(idx);
-                        if key_event.code == KeyCode::Enter {
-                            if let (Some(path), _) = &self.properties.cursor {
-                                _ = self.properties.manager.delete_fsitem(path);
-                            }
-                            self.properties.mode = AppMode::Normal;
-                        }
+                    if key_event.code == KeyCode::Enter && self.restore_edit_target() {
+                        let count = self.properties.targets.len().max(1);
+                        self.execute_edit(EditAction::Delete, "");
+                        self.last_edit = Some(LastEdit {
+                            action: EditAction::Delete,
+                            value: String::new(),
+                            count,
+                        });
+                        self.properties.mode = AppMode::Normal;
                     }
                 }
-            },
-            _ => {}
+            }
+        }
+        // Typing doesn't touch the listing, reloading then would restart a flat listing
+        if !matches!(self.properties.mode, AppMode::Edit(_)) {
+            self.reload_dir();
+        }
+    }
+
+    /// Puts the cursor back on the item the prompt was opened on, the listing may have changed
+    /// since. Falls back to the selected row, returns false if there is none
+    fn restore_edit_target(&mut self) -> bool {
+        if let Some(path) = self.properties.edit_target.take() {
+            self.set_cursor(Some(path));
+            return true;
+        }
+        match self.properties.main_list_state.selected() {
+            Some(idx) => {
+                self.generate_cursor(idx);
+                true
+            }
+            None => false,
         }
-        self.reload_dir();
     }
 
     fn reload_dir(&mut self) {
+        if let Some(archive) = &self.properties.archive {
+            let items = archive.read_dir(&archive.get_current_path());
+            let selected = self.properties.main_list_state.selected().unwrap_or(0);
+            self.set_archive_items(items, selected);
+            return;
+        }
         self.change_dir(self.properties.manager.get_current_path().clone());
         self.properties.manager.after_reload();
+
+        // Keep the cursor in sync with the selected row, items may have moved
+        let len = self.properties.items.lock().unwrap().len();
+        if let Some(selected) = self.properties.main_list_state.selected()
+            && len > 0
+        {
+            let idx = selected.min(len - 1);
+            self.properties.main_list_state.select(Some(idx));
+            self.generate_cursor(idx);
+        }
+    }
+
+    /// Runs an edit action with *value* as its input, on the item under the cursor
+    fn execute_edit(&mut self, action: EditAction, value: &str) {
+        let mut result = Ok(());
+        match action {
+            EditAction::Create => {
+                let dir = self.target_dir();
+                result = self.properties.manager.create_fsitem_at(&dir, value);
+            }
+            EditAction::Delete => {
+                let targets = std::mem::take(&mut self.properties.targets);
+                if !targets.is_empty() {
+                    for path in &targets {
+                        _ = self.properties.manager.delete_fsitem(path);
+                    }
+                } else if let (Some(path), _) = &self.properties.cursor {
+                    _ = self.properties.manager.delete_fsitem(path);
+                }
+            }
+            EditAction::Rename => {
+                if let (Some(path), _) = &self.properties.cursor
+                    && path
+                        .file_name()
+                        .is_some_and(|n| n.to_string_lossy() != value)
+                {
+                    let source = path.clone();
+                    result = self.properties.manager.rename_fsitem(source, value);
+                }
+            }
+            EditAction::Move | EditAction::Copy => {
+                if let (Some(path), _) = &self.properties.cursor {
+                    let source = path.clone();
+                    let mut dest = PathBuf::from(value);
+
+                    if !dest.exists() {
+                        let mut val = self.properties.get_current_path().clone();
+                        val.push(dest);
+                        dest = val;
+                    }
+
+                    let mov_option = if action == EditAction::Move {
+                        MoveOption::Move
+                    } else {
+                        MoveOption::Copy
+                    };
+
+                    result = self
+                        .properties
+                        .manager
+                        .move_fsitem(source, dest, mov_option);
+                }
+            }
+            EditAction::Hide => {
+                let filter = self.properties.manager.get_filter_mut();
+                if filter.add_hide_pattern(value.trim()).is_ok() {
+                    _ = filter.save();
+                }
+            }
+            EditAction::ShowOnly => {
+                result = self.properties.manager.get_filter_mut().set_only(value);
+            }
+            EditAction::PatternRename => {
+                let targets = std::mem::take(&mut self.properties.targets);
+                self.properties.renames.clear();
+                result = rename::Pattern::parse(value)
+                    .and_then(|pattern| pattern.apply(&targets))
+                    .and_then(|renames| self.properties.manager.bulk_rename(&renames));
+            }
+            EditAction::Compress => {
+                let targets = std::mem::take(&mut self.properties.targets);
+                let dest = self.properties.get_current_path().join(value.trim());
+                result = self.properties.manager.compress_fsitems(targets, dest);
+            }
+        }
+        if let Err(e) = result {
+            self.properties.message = Some(e);
+        }
+    }
+
+    /// Repeats the last file action on the item under the cursor (.)
+    fn repeat_last_edit(&mut self) {
+        let Some(last) = self.last_edit.clone() else {
+            return;
+        };
+        let Some(selected) = self.properties.main_list_state.selected() else {
+            return;
+        };
+        self.generate_cursor(selected);
+
+        if matches!(
+            last.action,
+            EditAction::Delete | EditAction::PatternRename | EditAction::Compress
+        ) {
+            self.properties.targets = self
+                .properties
+                .items
+                .lock()
+                .unwrap()
+                .iter()
+                .skip(selected)
+                .take(last.count)
+                .cloned()
+                .collect();
+        }
+        self.execute_edit(last.action, &last.value);
+        self.reload_dir();
+    }
+
+    /// Replays the keys of a macro register *count* times (@{reg})
+    fn replay_macro(&mut self, register: char, count: usize) {
+        // A macro calling itself (or another one) would never end
+        if self.macros.is_replaying() {
+            return;
+        }
+        let Some(keys) = self.macros.get(register) else {
+            return;
+        };
+
+        self.macros.set_replaying(true);
+        for _ in 0..count {
+            for key in &keys {
+                _ = self.handle_key_event(key);
+            }
+        }
+        self.macros.set_replaying(false);
     }
 
     fn handle_editkey_code(&mut self, key_event: &KeyEvent, action: EditAction) {
         match key_event.code {
+            KeyCode::Enter if self.properties.edit_input.accept_completion() => {}
             KeyCode::Enter => {
-                let idx = self.properties.main_list_state.selected();
-                if let None = idx {
+                if !self.restore_edit_target() {
                     return;
                 }
-                self.generate_cursor(idx.unwrap());
-                match action {
-                    EditAction::Create => {
-                        let new_item = self.properties.edit_input.get_value();
-                        _ = self.properties.manager.create_fsitem(&new_item);
-                    }
-                    EditAction::Rename => {
-                        if let (Some(path), _) = &self.properties.cursor {
-                            let source = path.clone();
-                            let dest = self.properties.edit_input.get_value();
-                            _ = self.properties.manager.rename_fsitem(source, dest);
-                        }
-                    }
-                    EditAction::Move | EditAction::Copy => {
-                        if let (Some(path), _) = &self.properties.cursor {
-                            let source = path.clone();
-                            let mut dest = PathBuf::from(self.properties.edit_input.get_value());
-
-                            if !dest.exists() {
-                                let mut val = self.properties.get_current_path().clone();
-                                val.push(dest);
-                                dest = val;
-                            }
-
-                            let mov_option = if action == EditAction::Move {
-                                MoveOption::Move
-                            } else {
-                                MoveOption::Copy
-                            };
-
-                            _ = self
-                                .properties
-                                .manager
-                                .move_fsitem(source, dest, mov_option);
-                        }
-                    }
-                    _ => {}
+                let value = self.properties.edit_input.get_value().clone();
+                let count = self.properties.targets.len().max(1);
+                self.execute_edit(action, &value);
+                if !matches!(action, EditAction::Hide | EditAction::ShowOnly) {
+                    self.last_edit = Some(LastEdit {
+                        action,
+                        value,
+                        count,
+                    });
                 }
                 //Maybe i could implement jump to item here
+                self.properties.edit_input.commit();
                 self.properties.edit_input.clear();
                 self.properties.mode = AppMode::Normal;
             }
             KeyCode::Esc => {
                 self.properties.mode = AppMode::Normal;
             }
-            KeyCode::Backspace => self
-                .properties
-                .edit_input
-                .handle(input::InputType::DeleteChar),
-            KeyCode::Tab => self
-                .properties
-                .edit_input
-                .handle(input::InputType::AutoComplete(
-                    self.properties.get_current_path().clone(),
-                )),
-            KeyCode::Char(c) => {
+            KeyCode::Tab | KeyCode::BackTab => {
+                let dir = self.target_dir();
+                let fuzzy = self.properties.fuzzy_completion;
                 self.properties
                     .edit_input
-                    .handle(input::InputType::AppendChar(c));
+                    .complete(key_event.code == KeyCode::Tab, |text| {
+                        (0, completion::path_candidates(text, &dir, false, fuzzy))
+                    });
+            }
+            KeyCode::Up => self.properties.edit_input.history_prev(),
+            KeyCode::Down => self.properties.edit_input.history_next(),
+            KeyCode::Char(c)
+                if action == EditAction::Rename && key_event.modifiers == KeyModifiers::ALT =>
+            {
+                self.select_name_part(c)
+            }
+            _ => {
+                self.properties.edit_input.handle_key(key_event);
             }
-            _ => {}
         }
     }
 
-    /// Handles search mode keycodes (regular keys without modifiers)
-    fn handle_searchkey_code(&mut self, key_event: &KeyEvent) {
+    /// Handles search mode keyevents, line editing keys go to the input
+    fn handle_search_mode(&mut self, key_event: &KeyEvent) {
         match key_event.code {
-            KeyCode::Enter => {
-                if !self.properties.manager.is_searching() {
-                    let items = Arc::clone(&self.properties.items);
-                    let term = self.properties.search_input.get_value();
-                    if term.is_empty() {
-                        return;
-                    }
-                    // PANICS! on empty input
-                    self.properties
-                        .manager
-                        .perform_search(
-                            term,
-                            items,
-                            self.properties.main_list_state.selected().unwrap_or(0),
-                        )
-                        .unwrap();
-                    self.properties.search_input.clear();
-                    self.properties.mode = AppMode::Normal;
+            KeyCode::Enter if !self.properties.manager.is_searching() => {
+                let items = Arc::clone(&self.properties.items);
+                let term = self.properties.search_input.get_value();
+                if term.is_empty() {
+                    return;
+                }
+                // Blank terms and terms with ".." get refused, the prompt stays open to fix them
+                if let Err(e) = self.properties.manager.perform_search(
+                    term,
+                    items,
+                    self.properties.main_list_state.selected().unwrap_or(0),
+                ) {
+                    self.properties.message = Some(e.to_string());
+                    return;
                 }
+                // The results are real paths, the archive listing is gone
+                self.properties.archive = None;
+                self.properties.search_input.commit();
+                self.properties.search_input.clear();
+                self.properties.mode = AppMode::Normal;
             }
             KeyCode::Esc => {
                 self.properties.mode = AppMode::Normal;
             }
-            KeyCode::Backspace => self
-                .properties
-                .search_input
-                .handle(input::InputType::DeleteChar),
-            KeyCode::Char(c) => {
-                self.properties
-                    .search_input
-                    .handle(input::InputType::AppendChar(c));
+            KeyCode::Up => self.properties.search_input.history_prev(),
+            KeyCode::Down => self.properties.search_input.history_next(),
+            _ => {
+                self.properties.search_input.handle_key(key_event);
             }
-            _ => {}
         }
     }
 
-    /// Handles navigation mode keycodes (regular keys without modifiers)
-    fn handle_navkey_code(&mut self, key_event: &KeyEvent) {
+    /// Handles command line keyevents, line editing keys go to the input
+    fn handle_command_mode(&mut self, key_event: &KeyEvent) {
         match key_event.code {
-            KeyCode::Enter => {
-                if !self.properties.manager.is_searching() {
-                    let term = self.properties.nav_input.get_value();
-                    let mut path = PathBuf::from(term);
-                    if !path.exists() {
-                        let mut val = self.properties.get_current_path().clone();
-                        val.push(path);
-                        path = val;
-                    }
-                    self.change_dir(path);
-                    self.properties.nav_input.clear();
-                    self.properties.mode = AppMode::Normal;
+            KeyCode::Enter if self.properties.command_input.accept_completion() => {}
+            KeyCode::Esc if self.properties.command_input.cancel_completion() => {}
+            KeyCode::Enter if !self.properties.manager.is_searching() => {
+                let line = self.properties.command_input.get_value().trim().to_string();
+                self.properties.command_input.commit();
+                self.properties.command_input.clear();
+                self.properties.mode = AppMode::Normal;
+                if line.is_empty() {
+                    return;
+                }
+                if let Err(e) = self.run_command(&line) {
+                    self.properties.message = Some(e);
                 }
             }
             KeyCode::Esc => {
+                self.properties.command_input.clear();
                 self.properties.mode = AppMode::Normal;
             }
-            KeyCode::Backspace => self
-                .properties
-                .nav_input
-                .handle(input::InputType::DeleteChar),
-            KeyCode::Tab => self
-                .properties
-                .nav_input
-                .handle(input::InputType::AutoComplete(
-                    self.properties.get_current_path().clone(),
-                )),
-            KeyCode::Char(c) => {
+            KeyCode::Up => self.properties.command_input.history_prev(),
+            KeyCode::Down => self.properties.command_input.history_next(),
+            KeyCode::Tab | KeyCode::BackTab => {
+                let ctx = CompletionContext {
+                    current: self.properties.manager.get_current_path(),
+                    themes: self
+                        .properties
+                        .themes
+                        .iter()
+                        .map(|t| t.get_name())
+                        .collect(),
+                    bookmarks: self.properties.bookmarks.get_names(),
+                    fuzzy: self.properties.fuzzy_completion,
+                };
                 self.properties
-                    .nav_input
-                    .handle(input::InputType::AppendChar(c));
+                    .command_input
+                    .complete(key_event.code == KeyCode::Tab, |line| {
+                        command::complete(line, &ctx)
+                    });
+            }
+            _ => {
+                self.properties.command_input.handle_key(key_event);
             }
-            _ => {}
         }
     }
 
+    /// Runs a command line, see command::COMMANDS. Plain paths navigate
+    fn run_command(&mut self, line: &str) -> Result<(), String> {
+        match command::parse(line)? {
+            Command::Cd(path) => {
+                let path = command::expand_path(&path, self.properties.get_current_path());
+                if !path.is_dir() {
+                    return Err(format!("Not a command or directory: {line}"));
+                }
+                self.change_dir(path);
+            }
+            Command::Mkdir(_) | Command::Touch(_) | Command::Rename(_)
+                if self.properties.archive.is_some() =>
+            {
+                return Err(String::from(READ_ONLY));
+            }
+            Command::Mkdir(name) => {
+                let dir = self.target_dir();
+                let name = format!("{}/", name.trim_end_matches('/'));
+                self.properties.manager.create_fsitem_at(&dir, &name)?;
+                self.reload_dir();
+            }
+            Command::Touch(name) => {
+                let dir = self.target_dir();
+                self.properties.manager.create_fsitem_at(&dir, &name)?;
+                self.reload_dir();
+            }
+            Command::Rename(name) => {
+                let Some(source) = self.properties.cursor.0.clone() else {
+                    return Err(String::from("Nothing to rename"));
+                };
+                self.properties
+                    .manager
+                    .rename_fsitem(source.clone(), &name)?;
+                self.reload_dir();
+                self.select_path(&source.with_file_name(&name));
+            }
+            Command::Sort(mode, reverse) => self.update_sort(|sort| {
+                sort.mode = mode;
+                sort.reverse = reverse;
+            }),
+            Command::Set(Setting::Hidden, value) => {
+                let show = value.unwrap_or(!self.properties.manager.get_filter().shows_hidden());
+                self.set_show_hidden(show);
+            }
+            Command::Set(Setting::Fuzzy, value) => {
+                self.properties.fuzzy_completion =
+                    value.unwrap_or(!self.properties.fuzzy_completion);
+            }
+            Command::Set(Setting::Number, value) => {
+                self.properties.line_numbers = value.unwrap_or(!self.properties.line_numbers);
+            }
+            Command::Set(Setting::DirsFirst, value) => {
+                self.update_sort(|sort| sort.dirs_first = value.unwrap_or(!sort.dirs_first))
+            }
+            Command::Theme(name) => {
+                let name = name.to_lowercase();
+                let themes = self.properties.get_themes();
+                let idx = themes
+                    .iter()
+                    .position(|theme| theme.get_name().to_lowercase() == name)
+                    .or_else(|| {
+                        let matches: Vec<usize> = (0..themes.len())
+                            .filter(|&i| themes[i].get_name().to_lowercase().starts_with(&name))
+                            .collect();
+                        (matches.len() == 1).then(|| matches[0])
+                    })
+                    .ok_or(format!("Unknown theme: {name}"))?;
+                self.properties.current_theme = idx;
+                self.properties.theme_list_state.select(Some(idx));
+                self.reload_dir();
+            }
+            Command::Graphics(protocol) => self.properties.graphics = protocol,
+            Command::Bookmark(BookmarkAction::Go(name)) => {
+                let path = self
+                    .properties
+                    .bookmarks
+                    .get(&name)
+                    .cloned()
+                    .ok_or(format!("No bookmark named {name}"))?;
+                if !path.is_dir() {
+                    return Err(format!("Bookmark {name} points to a missing directory"));
+                }
+                self.change_dir(path);
+            }
+            Command::Bookmark(BookmarkAction::Add(name)) => {
+                let path = self.properties.get_current_path().clone();
+                self.properties.bookmarks.add(&name, &path)?;
+                self.properties.bookmarks.save()?;
+                self.properties.message = Some(format!("Bookmarked {} as {name}", path.display()));
+            }
+            Command::Bookmark(BookmarkAction::Remove(name)) => {
+                if !self.properties.bookmarks.remove(&name) {
+                    return Err(format!("No bookmark named {name}"));
+                }
+                self.properties.bookmarks.save()?;
+            }
+            Command::Reindex => {
+                self.properties
+                    .manager
+                    .build_index(&xdg::home_dir(), IndexOption::Recursive)
+                    .map_err(|e| e.to_string())?;
+            }
+            Command::Quit => {
+                self.properties.exit = true;
+                self.properties.manager.shutdown();
+            }
+        }
+        Ok(())
+    }
+
     pub fn step_back(&mut self) {
+        if let Some(archive) = &mut self.properties.archive {
+            match archive.step_back() {
+                Some(cursor_idx) => {
+                    let items = archive.read_dir(&archive.get_current_path());
+                    self.drop_live_filter();
+                    self.set_archive_items(items, cursor_idx);
+                }
+                None => self.close_archive(),
+            }
+            return;
+        }
         // TODO: I'll need better error handling here
         // TODO: I'll have to to create some kind of error buffer
         if let Ok(cursor_idx) = self.properties.manager.step_back() {
-            self.properties.items = Arc::new(Mutex::new(
-                self.properties
-                    .manager
-                    .read_dir(self.properties.get_current_path(), OpenOption::Full)
-                    .unwrap(),
-            ));
+            self.properties.tree.clear();
+            self.drop_live_filter();
+            let (items, hidden_count) = self
+                .properties
+                .manager
+                .list_dir(self.properties.get_current_path(), OpenOption::Full)
+                .unwrap();
+            self.set_items(items, hidden_count);
             let cursor_idx = if cursor_idx >= self.properties.items.lock().unwrap().len() {
                 0
             } else {
@@ -435,23 +1530,267 @@ impl<'a> App<'a> {
         if !new_path.is_dir() {
             return;
         }
-        if let Ok(items) = self.properties.manager.change_dir(
+        self.properties.archive = None;
+        let same_dir = &new_path == self.properties.get_current_path();
+        if let Ok((items, hidden_count)) = self.properties.manager.change_dir(
             new_path.clone(),
             self.properties.main_list_state.selected().unwrap_or(0),
         ) {
-            self.properties.items = Arc::new(Mutex::new(items));
+            if same_dir {
+                self.properties.tree.refresh();
+            } else {
+                self.properties.tree.clear();
+                self.drop_live_filter();
+            }
+            self.set_items(items, hidden_count);
         }
         self.ui.set_main_items(&self.properties);
         self.generate_cursor(0);
     }
 
+    /// Opens an archive as a read-only directory in the main list
+    fn open_archive(&mut self, path: PathBuf) {
+        match ArchiveDir::open(&path) {
+            Ok(archive) => {
+                let items = archive.read_dir(archive.get_path());
+                self.properties.archive = Some(archive);
+                self.properties.tree.clear();
+                self.drop_live_filter();
+                self.set_archive_items(items, 0);
+            }
+            Err(e) => self.properties.message = Some(e),
+        }
+    }
+
+    /// Leaves the archive, back to the directory it is in with the cursor on it
+    fn close_archive(&mut self) {
+        if let Some(archive) = self.properties.archive.take() {
+            self.drop_live_filter();
+            self.reload_dir();
+            self.select_path(archive.get_path());
+        }
+    }
+
+    /// Shows a directory of the open archive, with the cursor on *idx*
+    fn set_archive_items(&mut self, items: Vec<PathBuf>, idx: usize) {
+        let idx = idx.min(items.len().saturating_sub(1));
+        self.properties.items = Arc::new(Mutex::new(items));
+        self.ui.set_main_items(&self.properties);
+        self.select_index(idx);
+    }
+
+    /// Returns true if *path* is a file inside the open archive
+    fn is_archive_file(&self, path: &Path) -> bool {
+        self.properties
+            .archive
+            .as_ref()
+            .is_some_and(|archive| archive.get_file(path).is_some())
+    }
+
+    /// Extracts the archive under the cursor into a new folder next to it, in the background
+    fn extract_archive(&mut self) {
+        let Some(path) = self.properties.cursor.0.clone() else {
+            return;
+        };
+        if let Err(e) = self.properties.manager.extract_fsitem(path) {
+            self.properties.message = Some(e);
+        }
+    }
+
+    /// Shows the outcome of background jobs that finished, the new files in the listing
+    fn collect_jobs(&mut self) {
+        let finished = self.properties.manager.take_finished_jobs();
+        let Some(last) = finished.last() else {
+            return;
+        };
+        self.properties.message = Some(match last {
+            Ok(message) | Err(message) => message.clone(),
+        });
+        if self.properties.archive.is_none() {
+            let cursor = self.properties.cursor.0.clone();
+            self.reload_dir();
+            if let Some(path) = cursor {
+                self.select_path(&path);
+            }
+        }
+    }
+
+    /// Extracts *count* items from the cursor, or the one under it, next to the open archive, in
+    /// the background
+    fn extract_members(&mut self, count: Option<usize>) {
+        let Some(archive) = &self.properties.archive else {
+            return;
+        };
+        let members: Vec<PathBuf> = self
+            .items_from_cursor(Some(count.unwrap_or(1)))
+            .iter()
+            .filter_map(|item| item.strip_prefix(archive.get_path()).ok())
+            .map(Path::to_path_buf)
+            .collect();
+        let source = archive.get_path().to_path_buf();
+        let dest = self.properties.get_current_path().to_path_buf();
+        self.properties
+            .manager
+            .extract_members(source, members, dest);
+    }
+
+    /// Replaces the items of the main list. In tree view the items get flattened into tree rows,
+    /// in flat view they get replaced by every file under the current directory. *hidden_count*
+    /// is the number of items the filter left out
+    fn set_items(&mut self, items: Vec<PathBuf>, hidden_count: usize) {
+        self.properties.hidden_count = hidden_count;
+        let items = match self.properties.view_mode {
+            ViewMode::List => items,
+            ViewMode::Tree => self.properties.tree.build(items, &self.properties.manager),
+            ViewMode::Flat => {
+                // The listing fills in the background, correct_ml_state() reapplies the live
+                // filter once it is done
+                self.properties.unfiltered = None;
+                let items = Arc::new(Mutex::new(Vec::new()));
+                self.properties
+                    .manager
+                    .flatten_dir(Arc::clone(&items), self.properties.flat_depth);
+                self.properties.items = items;
+                return;
+            }
+        };
+        self.properties.items = Arc::new(Mutex::new(items));
+
+        // A reload keeps the live filter, applied to the fresh listing
+        if self.properties.unfiltered.is_some() {
+            self.properties.unfiltered = None;
+            self.apply_live_filter();
+        }
+    }
+
+    /// Forgets the live filter without restoring the old listing, used when leaving the directory
+    fn drop_live_filter(&mut self) {
+        self.properties.unfiltered = None;
+        self.properties.filter_input.clear();
+    }
+
+    /// Moves the cursor to *path* if it is in the main list
+    fn select_path(&mut self, path: &PathBuf) {
+        let idx = self
+            .properties
+            .items
+            .lock()
+            .unwrap()
+            .iter()
+            .position(|item| item == path);
+        if let Some(idx) = idx {
+            self.properties.main_list_state.select(Some(idx));
+            self.generate_cursor(idx);
+        }
+    }
+
+    /// Switches the main list to *mode*, or back to the plain list if *mode* is already active
+    pub fn toggle_view_mode(&mut self, mode: ViewMode) {
+        self.properties.view_mode = if self.properties.view_mode == mode {
+            ViewMode::List
+        } else {
+            mode
+        };
+        self.properties.tree.clear();
+
+        let cursor = self.properties.cursor.0.clone();
+        self.reload_dir();
+        if let Some(path) = cursor {
+            self.select_path(&path);
+        }
+    }
+
+    /// Re-flattens the tree after a directory got expanded or collapsed
+    fn rebuild_tree(&mut self) {
+        let (items, hidden_count) = self
+            .properties
+            .manager
+            .list_dir(self.properties.get_current_path(), OpenOption::Full)
+            .unwrap_or_default();
+        self.set_items(items, hidden_count);
+        self.ui.set_main_items(&self.properties);
+    }
+
+    /// Expands the directory under the cursor (zl)
+    fn expand_tree_item(&mut self) {
+        if self.properties.view_mode != ViewMode::Tree {
+            return;
+        }
+        if let (Some(path), _) = &self.properties.cursor {
+            let path = path.clone();
+            if self
+                .properties
+                .tree
+                .expand(&path, &self.properties.manager)
+                .is_ok()
+            {
+                self.rebuild_tree();
+                self.select_path(&path);
+            }
+        }
+    }
+
+    /// Collapses the directory under the cursor, or the parent of the item under the cursor (zh)
+    fn collapse_tree_item(&mut self) {
+        if self.properties.view_mode != ViewMode::Tree {
+            return;
+        }
+        let Some(idx) = self.properties.main_list_state.selected() else {
+            return;
+        };
+        let Some(path) = self.properties.items.lock().unwrap().get(idx).cloned() else {
+            return;
+        };
+
+        if self.properties.tree.collapse(&path) {
+            self.rebuild_tree();
+            self.select_path(&path);
+        } else if let Some(parent_idx) = self.properties.tree.parent_row(idx) {
+            let parent = self.properties.tree.get_rows()[parent_idx].path.clone();
+            self.properties.tree.collapse(&parent);
+            self.rebuild_tree();
+            self.select_path(&parent);
+        }
+    }
+
+    /// Expands or collapses the directory under the cursor (Tab)
+    fn toggle_tree_item(&mut self) {
+        if let (Some(path), _) = &self.properties.cursor {
+            if self.properties.tree.is_expanded(path) {
+                self.collapse_tree_item();
+            } else {
+                self.expand_tree_item();
+            }
+        }
+    }
+
+    /// Returns the directory new items should be created in. In tree view this is the expanded
+    /// directory under the cursor or the parent of the item under the cursor
+    fn target_dir(&self) -> PathBuf {
+        if self.properties.view_mode == ViewMode::Tree
+            && let (Some(path), _) = &self.properties.cursor
+        {
+            if self.properties.tree.is_expanded(path) {
+                return path.clone();
+            }
+            if let Some(parent) = path.parent() {
+                return parent.to_path_buf();
+            }
+        }
+        self.properties.get_current_path().clone()
+    }
+
     fn generate_cursor(&mut self, idx: usize) {
         let path = self.properties.items.lock().unwrap().get(idx).cloned();
+        self.set_cursor(path);
+    }
+
+    fn set_cursor(&mut self, path: Option<PathBuf>) {
         let mut metadata = None;
-        if let Some(p) = &path {
-            if let Ok(md) = p.metadata() {
-                metadata = Some(md);
-            }
+        if let Some(p) = &path
+            && let Ok(md) = p.metadata()
+        {
+            metadata = Some(md);
         }
 
         self.properties.cursor = (path, metadata);
@@ -460,6 +1799,27 @@ impl<'a> App<'a> {
     fn correct_ml_state(&mut self) {
         if self.properties.items.lock().unwrap().is_empty() {
             self.properties.main_list_state.select(Some(0));
+        } else if self.properties.cursor.0.is_none() {
+            // Items filled in the background (search, flatten) don't trigger a cursor update
+            let idx = self.properties.main_list_state.selected().unwrap_or(0);
+            self.generate_cursor(idx);
+        }
+
+        // A live filter typed while flattening gets applied to the finished listing
+        if self.properties.unfiltered.is_none()
+            && !self.properties.filter_input.get_value().is_empty()
+            && !self.properties.manager.is_flattening()
+        {
+            self.apply_live_filter();
         }
     }
 }
+
+/// Returns the length of a file name without its extension, in chars. Dotfiles like .bashrc
+/// have no extension
+fn stem_len(name: &str) -> usize {
+    match name.rfind('.') {
+        Some(idx) if idx > 0 => name[..idx].chars().count(),
+        _ => name.chars().count(),
+    }
+}
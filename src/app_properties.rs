@@ -1,15 +1,21 @@
 use std::{
+    collections::HashSet,
     fmt::Display,
     fs::Metadata,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
-    time::Duration,
 };
 
-use ratatui::widgets::ListState;
+use ratatui::widgets::{ListState, TableState};
 
 use crate::{
+    archive::ArchiveDir,
+    bookmarks::Bookmarks,
+    database::DatabaseBrowser,
     manager::{Manager, OpenOption},
+    preview::{Previewer, graphics::Protocol},
+    rename::Rename,
+    tree::Tree,
     ui::{input, theme},
 };
 
@@ -19,9 +25,17 @@ pub enum AppMode {
     Normal,
     Edit(EditAction),
     Search,
-    Navigate,
+    Command,
     Compare,
     Theme,
+    Filter,
+    Find,
+    /// Confirming the renames of a bulk rename
+    BulkRename,
+    /// The preview of the cursor item, fullscreen and scrollable
+    View,
+    /// Paging through the rows of a database, fullscreen
+    Database,
 }
 
 #[derive(PartialEq, Eq, Clone, Copy)]
@@ -31,6 +45,20 @@ pub enum EditAction {
     Rename,
     Move,
     Copy,
+    Hide,
+    ShowOnly,
+    /// Renaming the targets with a find/replace pattern
+    PatternRename,
+    /// Packing the targets into a new archive
+    Compress,
+}
+
+/// The ways the main list can display the current directory.
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum ViewMode {
+    List,
+    Tree,
+    Flat,
 }
 
 impl Display for AppMode {
@@ -39,9 +67,14 @@ impl Display for AppMode {
             AppMode::Normal => write!(f, "Normal"),
             AppMode::Edit(_) => write!(f, "Edit"),
             AppMode::Search => write!(f, "Search"),
-            AppMode::Navigate => write!(f, "Navigate"),
+            AppMode::Command => write!(f, "Command"),
             AppMode::Compare => write!(f, "Compare"),
             AppMode::Theme => write!(f, "Theme"),
+            AppMode::Filter => write!(f, "Filter"),
+            AppMode::Find => write!(f, "Find"),
+            AppMode::BulkRename => write!(f, "Rename"),
+            AppMode::View => write!(f, "View"),
+            AppMode::Database => write!(f, "Database"),
         }
     }
 }
@@ -56,16 +89,61 @@ pub struct AppProperties {
     pub main_list_state: ListState,
     pub theme_list_state: ListState,
     pub search_input: input::Input,
-    pub nav_input: input::Input,
+    pub command_input: input::Input,
     pub edit_input: input::Input,
+    pub filter_input: input::Input,
+    pub find_input: input::Input,
+    pub find_pattern: Option<String>,
     pub cursor: (Option<PathBuf>, Option<Metadata>),
+    pub view_mode: ViewMode,
+    pub tree: Tree,
+    pub flat_depth: Option<usize>,
+    pub hidden_count: usize,
+    pub unfiltered: Option<Vec<PathBuf>>,
+    pub pending_keys: String,
+    pub list_height: usize,
+    pub targets: Vec<PathBuf>,
+    /// The item under the cursor when the edit prompt opened
+    pub edit_target: Option<PathBuf>,
+    pub yanked: Vec<PathBuf>,
+    pub recording: Option<char>,
+    pub bookmarks: Bookmarks,
+    pub message: Option<String>,
+    pub fuzzy_completion: bool,
+    pub renames: Vec<Rename>,
+    pub rename_table_state: TableState,
+    pub previewer: Previewer,
+    pub line_numbers: bool,
+    /// Shows Markdown files as their source, instead of rendered
+    pub markdown_source: bool,
+    pub graphics: Protocol,
+    /// The archive browsed in the main list, instead of a directory
+    pub archive: Option<ArchiveDir>,
+    /// The database browsed in Database mode
+    pub database: Option<DatabaseBrowser>,
+    pub viewer_scroll: usize,
+    pub viewer_height: usize,
+    /// The first column of the database table shown
+    pub viewer_column: usize,
+    /// Shows the text of a structured preview in the viewer, instead of its tree
+    pub viewer_source: bool,
+    /// The expanded nodes of the key tree in the viewer, see *Value::rows*
+    pub tree_expanded: HashSet<Vec<usize>>,
+    /// The row of the key tree the viewer cursor is on
+    pub tree_cursor: usize,
+}
+
+impl Default for AppProperties {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl AppProperties {
     pub fn new() -> Self {
         let fm = Manager::new();
-        let items = fm
-            .read_dir(fm.get_current_path(), OpenOption::Full)
+        let (items, hidden_count) = fm
+            .list_dir(fm.get_current_path(), OpenOption::Full)
             .unwrap();
         let mut cursor = (None, None);
         if let Some(path) = items.first() {
@@ -84,17 +162,50 @@ impl AppProperties {
             current_theme: 1,
             main_list_state: ListState::default(),
             theme_list_state: ListState::default(),
-            search_input: input::Input::new(),
-            nav_input: input::Input::new(),
-            edit_input: input::Input::new(),
+            search_input: input::Input::with_history("search"),
+            command_input: input::Input::with_history("command"),
+            edit_input: input::Input::with_history("edit"),
+            filter_input: input::Input::new(),
+            find_input: input::Input::with_history("find"),
+            find_pattern: None,
             cursor,
+            view_mode: ViewMode::List,
+            tree: Tree::new(),
+            flat_depth: None,
+            hidden_count,
+            unfiltered: None,
+            pending_keys: String::new(),
+            list_height: 0,
+            targets: Vec::new(),
+            edit_target: None,
+            yanked: Vec::new(),
+            recording: None,
+            bookmarks: Bookmarks::load(),
+            message: None,
+            fuzzy_completion: false,
+            renames: Vec::new(),
+            rename_table_state: TableState::default(),
+            previewer: Previewer::new(),
+            line_numbers: false,
+            markdown_source: false,
+            graphics: Protocol::detect(),
+            archive: None,
+            database: None,
+            viewer_scroll: 0,
+            viewer_height: 0,
+            viewer_column: 0,
+            viewer_source: false,
+            tree_expanded: HashSet::new(),
+            tree_cursor: 0,
         };
         props.main_list_state.select(Some(0));
         props.theme_list_state.select(Some(1));
         props.themes = theme::Theme::init_themes();
         props.search_input.set_color(props.get_theme().get_fg());
-        props.nav_input.set_color(props.get_theme().get_fg());
+        props.command_input.set_color(props.get_theme().get_fg());
         props.edit_input.set_color(props.get_theme().get_fg());
+        props.filter_input.set_color(props.get_theme().get_fg());
+        props.find_input.set_color(props.get_theme().get_fg());
 
         props
     }
@@ -104,7 +215,12 @@ impl AppProperties {
     }
 
     pub fn get_current_path(&self) -> &PathBuf {
-        &self.manager.get_current_path()
+        self.manager.get_current_path()
+    }
+
+    /// Returns true for directories, the ones inside the open archive too
+    pub fn is_dir(&self, path: &Path) -> bool {
+        path.is_dir() || self.archive.as_ref().is_some_and(|a| a.is_dir(path))
     }
 
     pub fn get_theme(&self) -> &theme::Theme {
@@ -126,4 +242,42 @@ impl AppProperties {
     pub fn get_mode(&self) -> &AppMode {
         &self.mode
     }
+
+    /// Returns the live filter term, if the listing is currently narrowed down by one
+    pub fn get_live_filter(&self) -> Option<&String> {
+        self.unfiltered
+            .as_ref()
+            .map(|_| self.filter_input.get_value())
+    }
+
+    /// Returns the pattern of the in-list search, the one being typed while in find mode
+    pub fn get_find_pattern(&self) -> Option<&String> {
+        if self.mode == AppMode::Find {
+            Some(self.find_input.get_value()).filter(|value| !value.is_empty())
+        } else {
+            self.find_pattern.as_ref()
+        }
+    }
+
+    /// Returns the input the current mode types into
+    pub fn get_active_input(&self) -> Option<&input::Input> {
+        match self.mode {
+            AppMode::Search => Some(&self.search_input),
+            AppMode::Command => Some(&self.command_input),
+            AppMode::Filter => Some(&self.filter_input),
+            AppMode::Find => Some(&self.find_input),
+            AppMode::Edit(EditAction::Delete) => None,
+            AppMode::Edit(_) => Some(&self.edit_input),
+            AppMode::Normal
+            | AppMode::Compare
+            | AppMode::Theme
+            | AppMode::BulkRename
+            | AppMode::View
+            | AppMode::Database => None,
+        }
+    }
+
+    pub fn get_view_mode(&self) -> &ViewMode {
+        &self.view_mode
+    }
 }
@@ -0,0 +1,550 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::ops::ControlFlow;
+use std::path::{Component, Path, PathBuf};
+
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use xz2::read::XzDecoder;
+use xz2::write::XzEncoder;
+use zip::write::SimpleFileOptions;
+
+/// The file name suffixes of the archive formats
+const SUFFIXES: [(&str, Format); 8] = [
+    (".zip", Format::Zip),
+    (".tar", Format::Tar),
+    (".tar.gz", Format::TarGz),
+    (".tgz", Format::TarGz),
+    (".tar.xz", Format::TarXz),
+    (".txz", Format::TarXz),
+    (".tar.zst", Format::TarZst),
+    (".tzst", Format::TarZst),
+];
+
+/// The archive formats that can be previewed and browsed.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Format {
+    Zip,
+    Tar,
+    TarGz,
+    TarXz,
+    TarZst,
+}
+
+impl Format {
+    /// Tells the format from the file name, .tar.gz and .tgz alike
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        SUFFIXES
+            .into_iter()
+            .find(|(suffix, _)| name.ends_with(suffix))
+            .map(|(_, format)| format)
+    }
+
+    /// Returns the file name of an archive without the format suffix, a.tar.gz gives a
+    fn stem(path: &Path) -> Option<String> {
+        let name = path.file_name()?.to_string_lossy().into_owned();
+        let lowercase = name.to_lowercase();
+        SUFFIXES
+            .into_iter()
+            .find(|(suffix, _)| lowercase.ends_with(suffix))
+            .map(|(suffix, _)| name[..name.len() - suffix.len()].to_string())
+            .filter(|stem| !stem.is_empty())
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Format::Zip => "ZIP",
+            Format::Tar => "TAR",
+            Format::TarGz => "TAR.GZ",
+            Format::TarXz => "TAR.XZ",
+            Format::TarZst => "TAR.ZST",
+        }
+    }
+}
+
+/// A file or directory stored in an archive.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// The path inside the archive, without leading / or ..
+    pub path: PathBuf,
+    /// Uncompressed size
+    pub size: u64,
+    pub is_dir: bool,
+    /// The unix permissions, if the archive stores them
+    pub mode: Option<u32>,
+}
+
+/// Returns the entries of an archive. Links and entries escaping the archive with .. or an
+/// absolute path are left out
+pub fn list(path: &Path) -> Result<Vec<Entry>, String> {
+    let mut entries = Vec::new();
+    walk(path, |entry, _| {
+        entries.push(entry);
+        Ok(ControlFlow::Continue(()))
+    })?;
+    Ok(entries)
+}
+
+/// Reads up to *limit* bytes of the file *inner* in the archive at *path*. Also returns the
+/// full size of the file
+pub fn read_entry(path: &Path, inner: &Path, limit: usize) -> Result<(Vec<u8>, u64), String> {
+    let mut found = None;
+    walk(path, |entry, reader| {
+        if entry.is_dir || entry.path != inner {
+            return Ok(ControlFlow::Continue(()));
+        }
+        let mut bytes = Vec::new();
+        reader.take(limit as u64).read_to_end(&mut bytes)?;
+        found = Some((bytes, entry.size));
+        Ok(ControlFlow::Break(()))
+    })?;
+    found.ok_or_else(|| format!("{} is not in the archive", inner.display()))
+}
+
+/// Extracts the *members* of the archive at *path* into *dest*, directories with everything in
+/// them. Each member lands in *dest* under its own name. Nothing gets overwritten, returns the
+/// number of files written
+pub fn extract(path: &Path, members: &[PathBuf], dest: &Path) -> Result<usize, String> {
+    for member in members {
+        let name = member.file_name().unwrap_or_default();
+        if dest.join(name).exists() {
+            return Err(format!("{} already exists", name.to_string_lossy()));
+        }
+    }
+
+    let mut count = 0;
+    walk(path, |entry, reader| {
+        let Some(target) = members.iter().find_map(|member| {
+            let relative = entry.path.strip_prefix(member).ok()?;
+            let target = dest.join(member.file_name()?);
+            // Joining an empty path would add a trailing slash
+            if relative.as_os_str().is_empty() {
+                Some(target)
+            } else {
+                Some(target.join(relative))
+            }
+        }) else {
+            return Ok(ControlFlow::Continue(()));
+        };
+        if entry.is_dir {
+            fs::create_dir_all(&target)?;
+        } else {
+            write_file(&target, reader, entry.mode)?;
+            count += 1;
+        }
+        Ok(ControlFlow::Continue(()))
+    })?;
+    Ok(count)
+}
+
+/// Extracts the whole archive at *path* into the new directory *dest*, keeping the stored
+/// permissions. Returns the number of files written and the number of entries left out, links
+/// and paths escaping *dest*
+pub fn extract_all(path: &Path, dest: &Path) -> Result<(usize, usize), String> {
+    fs::create_dir(dest).map_err(|e| e.to_string())?;
+    let mut count = 0;
+    let mut dirs = Vec::new();
+    let skipped = walk(path, |entry, reader| {
+        let target = dest.join(&entry.path);
+        if entry.is_dir {
+            fs::create_dir_all(&target)?;
+            dirs.push((target, entry.mode));
+        } else {
+            write_file(&target, reader, entry.mode)?;
+            count += 1;
+        }
+        Ok(ControlFlow::Continue(()))
+    })?;
+    // Last and deepest first, a read-only directory couldn't be filled anymore
+    for (dir, mode) in dirs.into_iter().rev() {
+        if let Some(mode) = mode {
+            set_mode(&dir, mode).map_err(|e| e.to_string())?;
+        }
+    }
+    Ok((count, skipped))
+}
+
+/// Returns a directory name next to the archive to extract it into, the archive name without
+/// its suffix. A number gets appended while the name is taken
+pub fn extract_dir(path: &Path) -> PathBuf {
+    let stem = Format::stem(path).unwrap_or_else(|| "archive".to_string());
+    let parent = path.parent().unwrap_or(Path::new(""));
+    let mut dest = parent.join(&stem);
+    let mut number = 2;
+    while dest.symlink_metadata().is_ok() {
+        dest = parent.join(format!("{stem}-{number}"));
+        number += 1;
+    }
+    dest
+}
+
+/// Packs *sources* into the new archive *dest*, its format told by the file name. Directories
+/// go in with everything in them, named relative to their parent. Symlinks are stored as links
+/// in tarballs and left out of zips. A partly written archive is removed on errors
+pub fn create(dest: &Path, sources: &[PathBuf]) -> Result<(), String> {
+    let format = Format::from_path(dest)
+        .ok_or("Unknown archive format, use .zip, .tar, .tar.gz, .tar.xz or .tar.zst")?;
+    let file = BufWriter::new(File::create_new(dest).map_err(|e| e.to_string())?);
+    let result = match format {
+        Format::Zip => create_zip(file, sources),
+        Format::Tar => create_tar(file, sources).and_then(|mut file| file.flush()),
+        Format::TarGz => create_tar(GzEncoder::new(file, Compression::default()), sources)
+            .and_then(|encoder| encoder.finish()?.flush()),
+        Format::TarXz => create_tar(XzEncoder::new(file, 6), sources)
+            .and_then(|encoder| encoder.finish()?.flush()),
+        Format::TarZst => zstd::Encoder::new(file, 0)
+            .and_then(|encoder| create_tar(encoder, sources))
+            .and_then(|encoder| encoder.finish()?.flush()),
+    };
+    if let Err(e) = result {
+        _ = fs::remove_file(dest);
+        return Err(e.to_string());
+    }
+    Ok(())
+}
+
+fn create_tar<W: Write>(writer: W, sources: &[PathBuf]) -> io::Result<W> {
+    let mut builder = tar::Builder::new(writer);
+    builder.follow_symlinks(false);
+    for source in sources {
+        let name = source.file_name().unwrap_or(source.as_os_str());
+        if source.symlink_metadata()?.is_dir() {
+            builder.append_dir_all(name, source)?;
+        } else {
+            builder.append_path_with_name(source, name)?;
+        }
+    }
+    builder.into_inner()
+}
+
+fn create_zip<W: Write + io::Seek>(writer: W, sources: &[PathBuf]) -> io::Result<()> {
+    let mut zip = zip::ZipWriter::new(writer);
+    for source in sources {
+        let base = source.parent().unwrap_or(Path::new(""));
+        add_to_zip(&mut zip, source, base)?;
+    }
+    zip.finish()?.flush()
+}
+
+/// Adds *path* to the zip, named relative to *base*, directories with everything in them
+fn add_to_zip<W: Write + io::Seek>(
+    zip: &mut zip::ZipWriter<W>,
+    path: &Path,
+    base: &Path,
+) -> io::Result<()> {
+    let metadata = path.symlink_metadata()?;
+    let name = path.strip_prefix(base).unwrap_or(path).to_string_lossy();
+    let mut options = SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .large_file(metadata.len() >= u32::MAX as u64);
+    if let Some(mode) = get_mode(&metadata) {
+        options = options.unix_permissions(mode);
+    }
+
+    if metadata.is_dir() {
+        zip.add_directory(name, options)?;
+        let mut children: Vec<PathBuf> = fs::read_dir(path)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .collect();
+        children.sort();
+        for child in children {
+            add_to_zip(zip, &child, base)?;
+        }
+    } else if metadata.is_file() {
+        zip.start_file(name, options)?;
+        io::copy(&mut File::open(path)?, zip)?;
+    }
+    Ok(())
+}
+
+/// Writes a new file with the contents of *reader*, creating the directories above it
+fn write_file(target: &Path, reader: &mut dyn Read, mode: Option<u32>) -> io::Result<()> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    io::copy(reader, &mut File::create_new(target)?)?;
+    if let Some(mode) = mode {
+        set_mode(target, mode)?;
+    }
+    Ok(())
+}
+
+/// Applies the permission bits of *mode*, without setuid, setgid and sticky
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o777))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn get_mode(metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode() & 0o777)
+}
+
+#[cfg(not(unix))]
+fn get_mode(_metadata: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+/// Splits a path pointing into an archive, like /tmp/a.zip/dir/file, into the archive and the
+/// path inside it. None if no parent is an archive file
+pub fn split(path: &Path) -> Option<(PathBuf, PathBuf)> {
+    path.ancestors().skip(1).find_map(|ancestor| {
+        if Format::from_path(ancestor).is_some() && ancestor.is_file() {
+            let inner = path.strip_prefix(ancestor).ok()?;
+            Some((ancestor.to_path_buf(), inner.to_path_buf()))
+        } else {
+            None
+        }
+    })
+}
+
+/// Turns the stored path of an entry into a relative one. None for paths that would end up
+/// outside of the extraction directory
+fn safe_path(path: &Path) -> Option<PathBuf> {
+    let mut safe = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => safe.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    (!safe.as_os_str().is_empty()).then_some(safe)
+}
+
+type Visit<'a> = dyn FnMut(Entry, &mut dyn Read) -> io::Result<ControlFlow<()>> + 'a;
+
+/// Goes through the entries of an archive in order, with a reader for the contents of each.
+/// Returns the number of entries left out, links and unsafe paths
+fn walk(
+    path: &Path,
+    mut visit: impl FnMut(Entry, &mut dyn Read) -> io::Result<ControlFlow<()>>,
+) -> Result<usize, String> {
+    let format = Format::from_path(path).ok_or("Not an archive")?;
+    let result = match format {
+        Format::Zip => walk_zip(path, &mut visit),
+        _ => walk_tar(path, format, &mut visit),
+    };
+    result.map_err(|e| e.to_string())
+}
+
+fn walk_zip(path: &Path, visit: &mut Visit) -> io::Result<usize> {
+    let mut zip =
+        zip::ZipArchive::new(BufReader::new(File::open(path)?)).map_err(io::Error::other)?;
+    let mut skipped = 0;
+    for idx in 0..zip.len() {
+        let mut file = zip.by_index(idx).map_err(io::Error::other)?;
+        let Some(entry_path) = safe_path(Path::new(file.name())).filter(|_| !file.is_symlink())
+        else {
+            skipped += 1;
+            continue;
+        };
+        let entry = Entry {
+            path: entry_path,
+            size: file.size(),
+            is_dir: file.is_dir(),
+            mode: file.unix_mode(),
+        };
+        if visit(entry, &mut file)?.is_break() {
+            break;
+        }
+    }
+    Ok(skipped)
+}
+
+fn walk_tar(path: &Path, format: Format, visit: &mut Visit) -> io::Result<usize> {
+    let file = BufReader::new(File::open(path)?);
+    let reader: Box<dyn Read> = match format {
+        Format::TarGz => Box::new(GzDecoder::new(file)),
+        Format::TarXz => Box::new(XzDecoder::new(file)),
+        Format::TarZst => Box::new(zstd::Decoder::with_buffer(file)?),
+        _ => Box::new(file),
+    };
+    let mut archive = tar::Archive::new(reader);
+    let mut skipped = 0;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let kind = entry.header().entry_type();
+        if !kind.is_file() && !kind.is_dir() {
+            if kind.is_symlink() || kind.is_hard_link() {
+                skipped += 1;
+            }
+            continue;
+        }
+        let Some(entry_path) = safe_path(&entry.path()?) else {
+            skipped += 1;
+            continue;
+        };
+        let info = Entry {
+            path: entry_path,
+            size: entry.size(),
+            is_dir: kind.is_dir(),
+            mode: entry.header().mode().ok(),
+        };
+        if visit(info, &mut entry)?.is_break() {
+            break;
+        }
+    }
+    Ok(skipped)
+}
+
+/// An archive opened in the main list as a read-only directory. Its items are paths into the
+/// archive, like /tmp/a.zip/dir/file, that *split* takes apart again.
+#[derive(Debug)]
+pub struct ArchiveDir {
+    path: PathBuf,
+    entries: Vec<Entry>,
+    /// The directory inside the archive that is listed
+    dir: PathBuf,
+    /// The cursor positions in the directories above, restored when stepping back
+    cursors: Vec<usize>,
+}
+
+impl ArchiveDir {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            entries: list(path)?,
+            dir: PathBuf::new(),
+            cursors: Vec::new(),
+        })
+    }
+
+    /// The archive file
+    pub fn get_path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    /// The listed directory, as a path into the archive
+    pub fn get_current_path(&self) -> PathBuf {
+        // Joining an empty path would add a trailing slash
+        if self.dir.as_os_str().is_empty() {
+            self.path.clone()
+        } else {
+            self.path.join(&self.dir)
+        }
+    }
+
+    /// Returns the directories and files right inside *path*, directories first. Directories
+    /// without an entry of their own are taken from the paths of the files in them
+    pub fn read_dir(&self, path: &Path) -> Vec<PathBuf> {
+        let Ok(dir) = path.strip_prefix(&self.path) else {
+            return Vec::new();
+        };
+        let mut children: Vec<(bool, PathBuf)> = Vec::new();
+        for entry in &self.entries {
+            let Ok(rest) = entry.path.strip_prefix(dir) else {
+                continue;
+            };
+            let mut components = rest.components();
+            let Some(name) = components.next() else {
+                continue;
+            };
+            let is_dir = entry.is_dir || components.next().is_some();
+            let child = path.join(name);
+            if !children.iter().any(|(_, known)| *known == child) {
+                children.push((is_dir, child));
+            }
+        }
+        children.sort_by(|(a_dir, a), (b_dir, b)| b_dir.cmp(a_dir).then_with(|| a.cmp(b)));
+        children.into_iter().map(|(_, child)| child).collect()
+    }
+
+    /// Returns the entry of a file in the archive
+    pub fn get_file(&self, path: &Path) -> Option<&Entry> {
+        let inner = path.strip_prefix(&self.path).ok()?;
+        self.entries
+            .iter()
+            .find(|entry| !entry.is_dir && entry.path == inner)
+    }
+
+    /// Returns true if *path* is a directory in the archive, the archive itself included
+    pub fn is_dir(&self, path: &Path) -> bool {
+        let Ok(inner) = path.strip_prefix(&self.path) else {
+            return false;
+        };
+        inner.as_os_str().is_empty()
+            || self
+                .entries
+                .iter()
+                .any(|entry| entry.path.starts_with(inner) && (entry.is_dir || entry.path != inner))
+    }
+
+    /// Lists the directory *path*, remembering *cursor* for stepping back
+    pub fn enter(&mut self, path: &Path, cursor: usize) -> Vec<PathBuf> {
+        if let Ok(inner) = path.strip_prefix(&self.path) {
+            self.dir = inner.to_path_buf();
+            self.cursors.push(cursor);
+        }
+        self.read_dir(path)
+    }
+
+    /// Goes up a directory. Returns the cursor position there, None at the top of the archive
+    pub fn step_back(&mut self) -> Option<usize> {
+        if !self.dir.pop() {
+            return None;
+        }
+        Some(self.cursors.pop().unwrap_or(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn safe(path: &str) -> Option<PathBuf> {
+        safe_path(Path::new(path))
+    }
+
+    #[test]
+    fn keeps_relative_paths() {
+        assert_eq!(safe("a.txt"), Some(PathBuf::from("a.txt")));
+        assert_eq!(safe("dir/sub/a.txt"), Some(PathBuf::from("dir/sub/a.txt")));
+        assert_eq!(safe("dir/"), Some(PathBuf::from("dir")));
+        assert_eq!(safe("./dir/./a.txt"), Some(PathBuf::from("dir/a.txt")));
+        // Dots inside a name aren't parent dirs
+        assert_eq!(safe("..a/b..c"), Some(PathBuf::from("..a/b..c")));
+    }
+
+    #[test]
+    fn refuses_parent_dirs() {
+        assert_eq!(safe(".."), None);
+        assert_eq!(safe("../a.txt"), None);
+        assert_eq!(safe("dir/../../a.txt"), None);
+        // Even when it would stay inside
+        assert_eq!(safe("dir/../a.txt"), None);
+        assert_eq!(safe("dir/.."), None);
+    }
+
+    #[test]
+    fn refuses_absolute_paths() {
+        assert_eq!(safe("/etc/passwd"), None);
+        assert_eq!(safe("//etc/passwd"), None);
+        assert_eq!(safe("/"), None);
+    }
+
+    #[test]
+    fn refuses_empty_paths() {
+        assert_eq!(safe(""), None);
+        assert_eq!(safe("."), None);
+        assert_eq!(safe("./."), None);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn refuses_prefixes() {
+        assert_eq!(safe(r"C:\Windows\a.txt"), None);
+        assert_eq!(safe(r"C:a.txt"), None);
+        assert_eq!(safe(r"\\server\share\a.txt"), None);
+        assert_eq!(safe(r"\\?\C:\a.txt"), None);
+    }
+}
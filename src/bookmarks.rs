@@ -0,0 +1,74 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::xdg;
+
+/// Named directories you can jump back to. Saved as one "name<TAB>path" line per bookmark.
+#[derive(Debug, Default)]
+pub struct Bookmarks {
+    marks: BTreeMap<String, PathBuf>,
+}
+
+impl Bookmarks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates the bookmarks saved in the config file
+    pub fn load() -> Self {
+        let mut bookmarks = Bookmarks::new();
+        if let Ok(file) = fs::read_to_string(Bookmarks::config_path()) {
+            for line in file.lines() {
+                if let Some((name, path)) = line.split_once('\t') {
+                    bookmarks
+                        .marks
+                        .insert(name.to_string(), PathBuf::from(path));
+                }
+            }
+        }
+        bookmarks
+    }
+
+    fn config_path() -> PathBuf {
+        xdg::config_dir().join("bookmarks")
+    }
+
+    /// Writes the bookmarks to the config file
+    pub fn save(&self) -> Result<(), String> {
+        let path = Bookmarks::config_path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let content: String = self
+            .marks
+            .iter()
+            .map(|(name, path)| format!("{}\t{}\n", name, path.to_string_lossy()))
+            .collect();
+        fs::write(path, content).map_err(|e| e.to_string())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PathBuf> {
+        self.marks.get(name)
+    }
+
+    /// Returns the bookmark names in alphabetical order
+    pub fn get_names(&self) -> Vec<&str> {
+        self.marks.keys().map(String::as_str).collect()
+    }
+
+    pub fn add(&mut self, name: &str, path: &Path) -> Result<(), String> {
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(String::from(
+                "Bookmark names can't be empty or contain spaces",
+            ));
+        }
+        self.marks.insert(name.to_string(), path.to_path_buf());
+        Ok(())
+    }
+
+    /// Removes a bookmark. Returns false if there was no such bookmark
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.marks.remove(name).is_some()
+    }
+}
@@ -0,0 +1,341 @@
+use std::path::{Path, PathBuf};
+
+use crate::preview::graphics::Protocol;
+use crate::sort::SortMode;
+use crate::ui::completion::{Candidate, expand, path_candidates};
+
+/// What a command takes as its argument, decides how it gets completed.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ArgKind {
+    None,
+    Dir,
+    Path,
+    Sort,
+    Setting,
+    Theme,
+    Bookmark,
+    Graphics,
+}
+
+/// An entry of the command registry.
+#[derive(Debug)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub alias: Option<&'static str>,
+    pub arg: ArgKind,
+    pub usage: &'static str,
+}
+
+/// Every command the command line knows. Anything else is taken as a path to navigate to.
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "cd",
+        alias: None,
+        arg: ArgKind::Dir,
+        usage: ":cd [dir]",
+    },
+    CommandSpec {
+        name: "mkdir",
+        alias: None,
+        arg: ArgKind::Path,
+        usage: ":mkdir <name>",
+    },
+    CommandSpec {
+        name: "touch",
+        alias: None,
+        arg: ArgKind::Path,
+        usage: ":touch <name>",
+    },
+    CommandSpec {
+        name: "rename",
+        alias: None,
+        arg: ArgKind::Path,
+        usage: ":rename <name>",
+    },
+    CommandSpec {
+        name: "sort",
+        alias: None,
+        arg: ArgKind::Sort,
+        usage: ":sort <name|size|mtime|ctime|ext|type> [rev]",
+    },
+    CommandSpec {
+        name: "set",
+        alias: None,
+        arg: ArgKind::Setting,
+        usage: ":set [no]hidden|[no]dirsfirst|[no]fuzzy|[no]number, ! toggles",
+    },
+    CommandSpec {
+        name: "theme",
+        alias: None,
+        arg: ArgKind::Theme,
+        usage: ":theme <name>",
+    },
+    CommandSpec {
+        name: "graphics",
+        alias: None,
+        arg: ArgKind::Graphics,
+        usage: ":graphics <halfblocks|kitty|sixel>",
+    },
+    CommandSpec {
+        name: "bookmark",
+        alias: Some("bm"),
+        arg: ArgKind::Bookmark,
+        usage: ":bookmark [add|rm] <name>",
+    },
+    CommandSpec {
+        name: "reindex",
+        alias: None,
+        arg: ArgKind::None,
+        usage: ":reindex",
+    },
+    CommandSpec {
+        name: "quit",
+        alias: Some("q"),
+        arg: ArgKind::None,
+        usage: ":q",
+    },
+];
+
+const SETTINGS: &[&str] = &[
+    "hidden",
+    "nohidden",
+    "hidden!",
+    "dirsfirst",
+    "nodirsfirst",
+    "dirsfirst!",
+    "fuzzy",
+    "nofuzzy",
+    "fuzzy!",
+    "number",
+    "nonumber",
+    "number!",
+];
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Setting {
+    Hidden,
+    DirsFirst,
+    /// Fuzzy matching in path completion
+    Fuzzy,
+    /// Line numbers in the text preview
+    Number,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum BookmarkAction {
+    Go(String),
+    Add(String),
+    Remove(String),
+}
+
+/// A parsed command line.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Command {
+    Cd(String),
+    Mkdir(String),
+    Touch(String),
+    Rename(String),
+    Sort(SortMode, bool),
+    /// None toggles the setting
+    Set(Setting, Option<bool>),
+    Theme(String),
+    Graphics(Protocol),
+    Bookmark(BookmarkAction),
+    Reindex,
+    Quit,
+}
+
+/// State the argument completion needs from the app
+pub struct CompletionContext<'a> {
+    pub current: &'a Path,
+    pub themes: Vec<&'a str>,
+    pub bookmarks: Vec<&'a str>,
+    pub fuzzy: bool,
+}
+
+pub fn find_command(name: &str) -> Option<&'static CommandSpec> {
+    COMMANDS
+        .iter()
+        .find(|spec| spec.name == name || spec.alias == Some(name))
+}
+
+/// Parses a command line. A line that doesn't start with a command navigates to it as a path
+pub fn parse(line: &str) -> Result<Command, String> {
+    let line = line.trim();
+    let (name, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let rest = rest.trim();
+    let args: Vec<&str> = rest.split_whitespace().collect();
+
+    let Some(spec) = find_command(name) else {
+        return Ok(Command::Cd(line.to_string()));
+    };
+    let usage = || format!("Usage: {}", spec.usage);
+    let required = || {
+        if rest.is_empty() {
+            Err(usage())
+        } else {
+            Ok(rest.to_string())
+        }
+    };
+
+    match spec.name {
+        "cd" if rest.is_empty() => Ok(Command::Cd(String::from("~"))),
+        "cd" => Ok(Command::Cd(rest.to_string())),
+        "mkdir" => required().map(Command::Mkdir),
+        "touch" => required().map(Command::Touch),
+        "rename" => required().map(Command::Rename),
+        "sort" => {
+            let mode = match args.first() {
+                Some(&"name") => SortMode::Name,
+                Some(&"size") => SortMode::Size,
+                Some(&"mtime") => SortMode::Modified,
+                Some(&"ctime") => SortMode::Changed,
+                Some(&"ext") => SortMode::Extension,
+                Some(&"type") => SortMode::Type,
+                _ => return Err(usage()),
+            };
+            match args.get(1) {
+                None => Ok(Command::Sort(mode, false)),
+                Some(&"rev") => Ok(Command::Sort(mode, true)),
+                Some(_) => Err(usage()),
+            }
+        }
+        "set" => {
+            let option = args.first().ok_or_else(usage)?;
+            let (option, value) = if let Some(option) = option.strip_suffix('!') {
+                (option, None)
+            } else if let Some(option) = option.strip_prefix("no") {
+                (option, Some(false))
+            } else {
+                (*option, Some(true))
+            };
+            match option {
+                "hidden" => Ok(Command::Set(Setting::Hidden, value)),
+                "dirsfirst" => Ok(Command::Set(Setting::DirsFirst, value)),
+                "fuzzy" => Ok(Command::Set(Setting::Fuzzy, value)),
+                "number" => Ok(Command::Set(Setting::Number, value)),
+                _ => Err(format!("Unknown option: {}", args[0])),
+            }
+        }
+        "theme" => required().map(Command::Theme),
+        "graphics" => match args.as_slice() {
+            [name] => Protocol::from_name(name)
+                .map(Command::Graphics)
+                .ok_or_else(usage),
+            _ => Err(usage()),
+        },
+        "bookmark" => match args.as_slice() {
+            ["add", name] => Ok(Command::Bookmark(BookmarkAction::Add(name.to_string()))),
+            ["rm", name] => Ok(Command::Bookmark(BookmarkAction::Remove(name.to_string()))),
+            [name] => Ok(Command::Bookmark(BookmarkAction::Go(name.to_string()))),
+            _ => Err(usage()),
+        },
+        "reindex" => Ok(Command::Reindex),
+        "quit" => Ok(Command::Quit),
+        _ => Err(usage()),
+    }
+}
+
+/// Returns where the last word of a command line starts and what it can be completed to:
+/// command names, a plain path or the argument of the command
+pub fn complete(line: &str, ctx: &CompletionContext) -> (usize, Vec<Candidate>) {
+    // Past the last whitespace char, which can be more than one byte long
+    let start = line
+        .char_indices()
+        .rev()
+        .find(|(_, c)| c.is_whitespace())
+        .map(|(idx, c)| idx + c.len_utf8())
+        .unwrap_or(0);
+    let (head, word) = line.split_at(start);
+    let words = |options: Vec<&str>| -> Vec<Candidate> {
+        options
+            .into_iter()
+            .filter(|option| option.starts_with(word))
+            .map(Candidate::word)
+            .collect()
+    };
+
+    // Nothing but whitespace before the word, it is still the command name
+    let candidates = if head.trim().is_empty() {
+        let names = words(COMMANDS.iter().map(|spec| spec.name).collect());
+        // Plain paths still complete
+        if names.is_empty() || word.contains('/') {
+            path_candidates(word, ctx.current, true, ctx.fuzzy)
+        } else {
+            names
+        }
+    } else {
+        let name = head.split_whitespace().next().unwrap_or_default();
+        let arg_idx = head.split_whitespace().count() - 1;
+        let Some(spec) = find_command(name) else {
+            return (start, Vec::new());
+        };
+        match spec.arg {
+            ArgKind::None => Vec::new(),
+            ArgKind::Dir => path_candidates(word, ctx.current, true, ctx.fuzzy),
+            ArgKind::Path => path_candidates(word, ctx.current, false, ctx.fuzzy),
+            ArgKind::Sort if arg_idx == 0 => {
+                words(vec!["name", "size", "mtime", "ctime", "ext", "type"])
+            }
+            ArgKind::Sort => words(vec!["rev"]),
+            ArgKind::Setting => words(SETTINGS.to_vec()),
+            ArgKind::Theme => words(ctx.themes.clone()),
+            ArgKind::Graphics => words(Protocol::NAMES.to_vec()),
+            ArgKind::Bookmark if arg_idx == 0 => {
+                words([vec!["add", "rm"], ctx.bookmarks.clone()].concat())
+            }
+            ArgKind::Bookmark => words(ctx.bookmarks.clone()),
+        }
+    };
+    (start, candidates)
+}
+
+/// Resolves a typed path: ~ and $VARs get expanded and relative paths start at *current*
+pub fn expand_path(path: &str, current: &Path) -> PathBuf {
+    current.join(expand(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn complete_in(line: &str) -> (usize, Vec<String>) {
+        let ctx = CompletionContext {
+            current: Path::new("/nonexistent"),
+            themes: vec!["dark", "light"],
+            bookmarks: Vec::new(),
+            fuzzy: false,
+        };
+        let (start, candidates) = complete(line, &ctx);
+        let labels = candidates.iter().map(|c| c.get_label().to_string());
+        (start, labels.collect())
+    }
+
+    #[test]
+    fn completes_command_names() {
+        let (start, names) = complete_in("them");
+        assert_eq!(start, 0);
+        assert!(names.contains(&String::from("theme")));
+    }
+
+    #[test]
+    fn completes_arguments() {
+        assert_eq!(complete_in("theme l"), (6, vec![String::from("light")]));
+    }
+
+    #[test]
+    fn multi_byte_whitespace() {
+        // A no-break space is two bytes long
+        let (start, names) = complete_in("theme\u{a0}l");
+        assert_eq!(start, "theme\u{a0}".len());
+        assert_eq!(names, [String::from("light")]);
+    }
+
+    #[test]
+    fn only_whitespace_before_the_word() {
+        let (start, names) = complete_in("  them");
+        assert_eq!(start, 2);
+        assert!(names.contains(&String::from("theme")));
+        assert_eq!(complete_in("   ").0, 3);
+    }
+}
@@ -0,0 +1,347 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, OpenFlags};
+
+/// The file extensions SQLite databases usually have
+const EXTENSIONS: [&str; 4] = ["db", "sqlite", "sqlite3", "db3"];
+/// The header every SQLite database starts with
+const MAGIC: &[u8; 16] = b"SQLite format 3\0";
+/// How many characters of a text field are shown
+const FIELD_LENGTH: usize = 200;
+
+/// A table or view of a database.
+#[derive(Debug, Clone)]
+pub struct Table {
+    pub name: String,
+    pub is_view: bool,
+    /// None for a view or a table that wasn't counted, or if counting failed
+    pub rows: Option<u64>,
+    /// The statement that created it
+    pub sql: String,
+}
+
+/// Rows read from a table, every field as text.
+#[derive(Debug, Clone, Default)]
+pub struct Page {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+    /// The table, first row and row count the page was read for
+    key: (usize, usize, usize),
+}
+
+/// Returns true for a file with a database extension that starts with the SQLite header
+pub fn is_database(path: &Path) -> bool {
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+    if !extension.is_some_and(|ext| EXTENSIONS.contains(&ext.as_str())) {
+        return false;
+    }
+    let mut header = [0; MAGIC.len()];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .is_ok_and(|_| &header == MAGIC)
+}
+
+/// Returns the tables of a database, then its views. Only the tables get their rows counted,
+/// the query of a view could take any time
+pub fn tables(path: &Path) -> Result<Vec<Table>, String> {
+    let connection = open(path)?;
+    let mut tables = read_tables(&connection).map_err(|e| e.to_string())?;
+    for table in tables.iter_mut().filter(|table| !table.is_view) {
+        table.rows = count_rows(&connection, &table.name);
+    }
+    Ok(tables)
+}
+
+/// Opens a database without ever writing to it
+fn open(path: &Path) -> Result<Connection, String> {
+    let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+    Connection::open_with_flags(path, flags).map_err(|e| e.to_string())
+}
+
+/// Reads the names and statements of the tables and views, without counting their rows
+fn read_tables(connection: &Connection) -> rusqlite::Result<Vec<Table>> {
+    let mut statement = connection.prepare(
+        "SELECT name, type, sql FROM sqlite_schema \
+         WHERE type IN ('table', 'view') AND name NOT LIKE 'sqlite_%' ORDER BY type, name",
+    )?;
+    let schema = statement
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(schema
+        .into_iter()
+        .map(|(name, kind, sql)| Table {
+            name,
+            is_view: kind == "view",
+            rows: None,
+            sql: sql.unwrap_or_default(),
+        })
+        .collect())
+}
+
+/// Counts the rows of the table *name*, None if that fails
+fn count_rows(connection: &Connection, name: &str) -> Option<u64> {
+    let count = format!("SELECT count(*) FROM {}", quote(name));
+    connection
+        .query_row(&count, [], |row| row.get::<_, i64>(0))
+        .ok()
+        .map(|rows| rows as u64)
+}
+
+/// Quotes a table name for a statement
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Shows a field in a table cell: on one line, blobs by their size
+fn field(value: ValueRef) -> String {
+    match value {
+        ValueRef::Null => String::from("NULL"),
+        ValueRef::Integer(int) => int.to_string(),
+        ValueRef::Real(float) => format!("{float:?}"),
+        ValueRef::Text(bytes) => String::from_utf8_lossy(bytes)
+            .chars()
+            .map(|c| if c.is_control() { ' ' } else { c })
+            .take(FIELD_LENGTH)
+            .collect(),
+        ValueRef::Blob(bytes) => format!("<{} bytes>", bytes.len()),
+    }
+}
+
+/// A database opened read-only, its tables read a page of rows at a time.
+pub struct DatabaseBrowser {
+    path: PathBuf,
+    connection: Connection,
+    tables: Vec<Table>,
+    /// The table being browsed
+    table: usize,
+    /// The row counts of the tables shown so far, each table is counted when it is first shown
+    counts: HashMap<usize, Option<u64>>,
+    /// The last page read, kept while the same rows are shown
+    page: Option<Page>,
+}
+
+impl DatabaseBrowser {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let connection = open(path)?;
+        let tables = read_tables(&connection).map_err(|e| e.to_string())?;
+        if tables.is_empty() {
+            return Err(String::from("The database has no tables"));
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            connection,
+            tables,
+            table: 0,
+            counts: HashMap::new(),
+            page: None,
+        })
+    }
+
+    pub fn get_path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn get_tables(&self) -> &[Table] {
+        &self.tables
+    }
+
+    pub fn get_table(&self) -> &Table {
+        &self.tables[self.table]
+    }
+
+    pub fn get_table_index(&self) -> usize {
+        self.table
+    }
+
+    /// Returns the row count of the current table, None for a view
+    pub fn get_row_count(&mut self) -> Option<u64> {
+        let table = &self.tables[self.table];
+        if table.is_view {
+            return None;
+        }
+        *self
+            .counts
+            .entry(self.table)
+            .or_insert_with(|| count_rows(&self.connection, &table.name))
+    }
+
+    /// Switches to the table *offset* places after the current one, wrapping around
+    pub fn cycle_table(&mut self, offset: isize) {
+        let count = self.tables.len() as isize;
+        self.table = (self.table as isize + offset).rem_euclid(count) as usize;
+    }
+
+    /// Reads *count* rows of the current table from *first* on
+    pub fn page(&mut self, first: usize, count: usize) -> Result<&Page, String> {
+        let key = (self.table, first, count);
+        if self.page.as_ref().is_none_or(|page| page.key != key) {
+            let page = self.read_page(first, count).map_err(|e| e.to_string())?;
+            self.page = Some(Page { key, ..page });
+        }
+        Ok(self.page.as_ref().unwrap())
+    }
+
+    fn read_page(&self, first: usize, count: usize) -> rusqlite::Result<Page> {
+        let query = format!(
+            "SELECT * FROM {} LIMIT ?1 OFFSET ?2",
+            quote(&self.get_table().name)
+        );
+        let mut statement = self.connection.prepare(&query)?;
+        let columns: Vec<String> = statement
+            .column_names()
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let rows = statement
+            .query_map([count as i64, first as i64], |row| {
+                (0..columns.len())
+                    .map(|idx| row.get_ref(idx).map(field))
+                    .collect()
+            })?
+            .collect::<rusqlite::Result<Vec<Vec<String>>>>()?;
+
+        Ok(Page {
+            columns,
+            rows,
+            key: (0, 0, 0),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a database with a table of three rows, a table with a quote in its name and a
+    /// view, in a fresh folder
+    fn create(test: &str) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("rune-test-{}-{test}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("test.db");
+        let connection = Connection::open(&path).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE people (name TEXT, age INTEGER, photo BLOB);
+                 INSERT INTO people VALUES ('ada', 36, x'0102'), ('alan', 41, NULL), ('grace', 85.5, NULL);
+                 CREATE TABLE \"odd\"\"name\" (id INTEGER);
+                 INSERT INTO \"odd\"\"name\" VALUES (1);
+                 CREATE VIEW adults AS SELECT name FROM people WHERE age > 40;",
+            )
+            .unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn quote_escapes_quotes() {
+        assert_eq!(quote("people"), "\"people\"");
+        assert_eq!(quote("odd\"name"), "\"odd\"\"name\"");
+    }
+
+    #[test]
+    fn is_database_checks_extension_and_header() {
+        let (dir, path) = create("is_database");
+        assert!(is_database(&path));
+
+        let renamed = dir.join("test.txt");
+        std::fs::copy(&path, &renamed).unwrap();
+        assert!(!is_database(&renamed));
+
+        let fake = dir.join("fake.db");
+        std::fs::write(&fake, "not a database at all").unwrap();
+        assert!(!is_database(&fake));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn open_is_read_only() {
+        let (dir, path) = create("read_only");
+        let connection = open(&path).unwrap();
+        assert!(
+            connection
+                .execute("INSERT INTO people VALUES ('linus', 20, NULL)", [])
+                .is_err()
+        );
+        assert_eq!(count_rows(&connection, "people"), Some(3));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn tables_count_tables_but_not_views() {
+        let (dir, path) = create("tables");
+        let tables = tables(&path).unwrap();
+        let summary: Vec<(&str, bool, Option<u64>)> = tables
+            .iter()
+            .map(|table| (table.name.as_str(), table.is_view, table.rows))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("odd\"name", false, Some(1)),
+                ("people", false, Some(3)),
+                ("adults", true, None),
+            ]
+        );
+        assert!(tables[2].sql.starts_with("CREATE VIEW adults"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn browser_reads_pages() {
+        let (dir, path) = create("pages");
+        let mut browser = DatabaseBrowser::open(&path).unwrap();
+        browser.cycle_table(1);
+        assert_eq!(browser.get_table().name, "people");
+        assert_eq!(browser.get_row_count(), Some(3));
+
+        let page = browser.page(0, 2).unwrap();
+        assert_eq!(page.columns, vec!["name", "age", "photo"]);
+        assert_eq!(
+            page.rows,
+            vec![vec!["ada", "36", "<2 bytes>"], vec!["alan", "41", "NULL"]]
+        );
+        let page = browser.page(2, 2).unwrap();
+        assert_eq!(page.rows, vec![vec!["grace", "85.5", "NULL"]]);
+        assert!(browser.page(3, 2).unwrap().rows.is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn browser_cycles_tables() {
+        let (dir, path) = create("cycle");
+        let mut browser = DatabaseBrowser::open(&path).unwrap();
+        assert_eq!(browser.get_table().name, "odd\"name");
+        assert_eq!(browser.page(0, 10).unwrap().rows, vec![vec!["1"]]);
+
+        browser.cycle_table(-1);
+        assert_eq!(browser.get_table().name, "adults");
+        assert_eq!(browser.get_row_count(), None);
+        assert_eq!(
+            browser.page(0, 10).unwrap().rows,
+            vec![vec!["alan"], vec!["grace"]]
+        );
+
+        browser.cycle_table(2);
+        assert_eq!(browser.get_table_index(), 1);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,376 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use glob::Pattern;
+
+use crate::xdg;
+
+/// Decides which items are listed. Dotfiles can be toggled, items matching one of the
+/// hide patterns are never listed and the show-only pattern narrows the files down to the
+/// ones matching it (folders stay visible, so you can still navigate).
+#[derive(Debug, Clone)]
+pub struct Filter {
+    show_hidden: bool,
+    hide_patterns: Vec<Pattern>,
+    only: Option<Pattern>,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Filter {
+    pub fn new() -> Self {
+        Self {
+            show_hidden: true,
+            hide_patterns: Vec::new(),
+            only: None,
+        }
+    }
+
+    /// Creates a filter with the hide patterns saved in the config file
+    pub fn load() -> Self {
+        let mut filter = Filter::new();
+        if let Ok(file) = fs::read_to_string(Filter::config_path()) {
+            file.lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .for_each(|line| {
+                    _ = filter.add_hide_pattern(line);
+                });
+        }
+        filter
+    }
+
+    /// Hide patterns are stored one per line in $XDG_CONFIG_HOME/rune/hidden
+    fn config_path() -> PathBuf {
+        xdg::config_dir().join("hidden")
+    }
+
+    /// Writes the hide patterns to the config file
+    pub fn save(&self) -> Result<(), String> {
+        let path = Filter::config_path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let content: Vec<&str> = self.hide_patterns.iter().map(|p| p.as_str()).collect();
+        fs::write(path, content.join("\n") + "\n").map_err(|e| e.to_string())
+    }
+
+    pub fn shows_hidden(&self) -> bool {
+        self.show_hidden
+    }
+
+    pub fn set_show_hidden(&mut self, show: bool) {
+        self.show_hidden = show;
+    }
+
+    pub fn get_hide_patterns(&self) -> &Vec<Pattern> {
+        &self.hide_patterns
+    }
+
+    pub fn add_hide_pattern(&mut self, pattern: &str) -> Result<(), String> {
+        let pattern = Pattern::new(pattern).map_err(|e| e.to_string())?;
+        if !self.hide_patterns.contains(&pattern) {
+            self.hide_patterns.push(pattern);
+        }
+        Ok(())
+    }
+
+    /// Removes a hide pattern. Returns false if there was no such pattern
+    pub fn remove_hide_pattern(&mut self, pattern: &str) -> bool {
+        let len = self.hide_patterns.len();
+        self.hide_patterns.retain(|p| p.as_str() != pattern);
+        len != self.hide_patterns.len()
+    }
+
+    pub fn get_only(&self) -> Option<&Pattern> {
+        self.only.as_ref()
+    }
+
+    /// Only lists files matching *pattern*. An empty pattern removes the filter
+    pub fn set_only(&mut self, pattern: &str) -> Result<(), String> {
+        if pattern.trim().is_empty() {
+            self.only = None;
+            return Ok(());
+        }
+        self.only = Some(Pattern::new(pattern.trim()).map_err(|e| e.to_string())?);
+        Ok(())
+    }
+
+    /// Returns true if *path* should be listed
+    pub fn is_visible(&self, path: &Path) -> bool {
+        let Some(name) = path.file_name() else {
+            return true;
+        };
+        let name = name.to_string_lossy();
+
+        if !self.show_hidden && name.starts_with('.') {
+            return false;
+        }
+        if self.hide_patterns.iter().any(|p| p.matches(&name)) {
+            return false;
+        }
+        match &self.only {
+            Some(only) => path.is_dir() || only.matches(&name),
+            None => true,
+        }
+    }
+
+    /// Reads the items of *dir* the filter lists, at most *limit* of them, along with the number
+    /// of items it left out
+    pub fn read_dir(&self, dir: &Path, limit: usize) -> io::Result<(Vec<PathBuf>, usize)> {
+        let mut items = Vec::new();
+        let mut hidden = 0;
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if !self.is_visible(&path) {
+                hidden += 1;
+                continue;
+            }
+            items.push(path);
+            if items.len() >= limit {
+                break;
+            }
+        }
+        Ok((items, hidden))
+    }
+
+    /// Returns true if the filter can drop items, used to decide if the hidden count is shown
+    pub fn is_active(&self) -> bool {
+        !self.show_hidden || !self.hide_patterns.is_empty() || self.only.is_some()
+    }
+}
+
+/// Case-insensitive fuzzy match of *pattern* against *text*. Returns the char indices of *text*
+/// that matched, or None if *pattern* isn't a subsequence of *text*.
+/// A plain substring match is preferred, so the highlighted part stays in one piece when it can.
+pub fn fuzzy_match(pattern: &str, text: &str) -> Option<Vec<usize>> {
+    // One lowercase char per char, so the indices still line up with the original text
+    let lower = |c: char| c.to_lowercase().next().unwrap_or(c);
+    let pattern: Vec<char> = pattern.chars().map(lower).collect();
+    let text: Vec<char> = text.chars().map(lower).collect();
+    if pattern.is_empty() {
+        return Some(Vec::new());
+    }
+
+    if let Some(start) = text
+        .windows(pattern.len())
+        .position(|window| window == pattern.as_slice())
+    {
+        return Some((start..start + pattern.len()).collect());
+    }
+
+    let mut indices = Vec::with_capacity(pattern.len());
+    let mut chars = pattern.iter().peekable();
+    for (i, c) in text.iter().enumerate() {
+        if chars.peek() == Some(&c) {
+            indices.push(i);
+            chars.next();
+        }
+    }
+    if chars.peek().is_none() {
+        Some(indices)
+    } else {
+        None
+    }
+}
+
+/// Finds the first occurrence of *pattern* in *text*. Returns the char indices of the match.
+/// Smartcase like vim: the match ignores case unless *pattern* has an uppercase char.
+pub fn find_match(pattern: &str, text: &str) -> Option<Vec<usize>> {
+    let ignore_case = !pattern.chars().any(char::is_uppercase);
+    let fold = |c: char| {
+        if ignore_case {
+            c.to_lowercase().next().unwrap_or(c)
+        } else {
+            c
+        }
+    };
+    let pattern: Vec<char> = pattern.chars().map(fold).collect();
+    let text: Vec<char> = text.chars().map(fold).collect();
+    if pattern.is_empty() {
+        return None;
+    }
+
+    text.windows(pattern.len())
+        .position(|window| window == pattern.as_slice())
+        .map(|start| (start..start + pattern.len()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory with the given files, names ending in / become folders
+    fn setup(test: &str, names: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rune-test-{}-{test}", std::process::id()));
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for name in names {
+            match name.strip_suffix('/') {
+                Some(folder) => fs::create_dir(dir.join(folder)).unwrap(),
+                None => fs::write(dir.join(name), "").unwrap(),
+            }
+        }
+        dir
+    }
+
+    /// The names *filter* lists in *dir*, sorted, and the hidden count
+    fn listing(filter: &Filter, dir: &Path) -> (Vec<String>, usize) {
+        let (items, hidden) = filter.read_dir(dir, usize::MAX).unwrap();
+        let mut names: Vec<String> = items
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        (names, hidden)
+    }
+
+    #[test]
+    fn shows_everything_by_default() {
+        let filter = Filter::new();
+        assert!(!filter.is_active());
+        assert!(filter.is_visible(Path::new("/x/.bashrc")));
+        assert!(filter.is_visible(Path::new("/x/a.txt")));
+        // Paths without a name, like the root, are always listed
+        assert!(filter.is_visible(Path::new("/")));
+    }
+
+    #[test]
+    fn hides_dotfiles() {
+        let mut filter = Filter::new();
+        filter.set_show_hidden(false);
+        assert!(filter.is_active());
+        assert!(!filter.is_visible(Path::new("/x/.bashrc")));
+        assert!(filter.is_visible(Path::new("/x/a.txt")));
+        assert!(filter.is_visible(Path::new("/x/a.b")));
+    }
+
+    #[test]
+    fn hide_patterns() {
+        let mut filter = Filter::new();
+        filter.add_hide_pattern("*.o").unwrap();
+        filter.add_hide_pattern("target").unwrap();
+        filter.add_hide_pattern("*.o").unwrap();
+        assert_eq!(filter.get_hide_patterns().len(), 2);
+        assert!(filter.is_active());
+        assert!(!filter.is_visible(Path::new("/x/main.o")));
+        assert!(!filter.is_visible(Path::new("/x/target")));
+        // Patterns match the name, not the path
+        assert!(filter.is_visible(Path::new("/target/main.c")));
+        assert!(filter.add_hide_pattern("[").is_err());
+
+        assert!(filter.remove_hide_pattern("*.o"));
+        assert!(!filter.remove_hide_pattern("*.o"));
+        assert!(filter.is_visible(Path::new("/x/main.o")));
+    }
+
+    #[test]
+    fn show_only_keeps_folders() {
+        let dir = setup("only", &["a.rs", "b.txt", "src/"]);
+        let mut filter = Filter::new();
+        filter.set_only(" *.rs ").unwrap();
+        assert_eq!(filter.get_only().unwrap().as_str(), "*.rs");
+        assert!(filter.is_active());
+        assert_eq!(
+            listing(&filter, &dir),
+            (vec![String::from("a.rs"), String::from("src")], 1)
+        );
+
+        assert!(filter.set_only("[").is_err());
+        filter.set_only("  ").unwrap();
+        assert!(filter.get_only().is_none());
+        assert!(!filter.is_active());
+        assert_eq!(listing(&filter, &dir).1, 0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn modes_combine() {
+        let dir = setup(
+            "combine",
+            &[".env.rs", ".git/", "a.rs", "b.txt", "gen.rs", "src/"],
+        );
+        let mut filter = Filter::new();
+        filter.set_show_hidden(false);
+        filter.add_hide_pattern("gen*").unwrap();
+        filter.set_only("*.rs").unwrap();
+        // A dotfile stays hidden even if it is shown only, a hide pattern beats show-only
+        assert_eq!(
+            listing(&filter, &dir),
+            (vec![String::from("a.rs"), String::from("src")], 4)
+        );
+
+        filter.set_show_hidden(true);
+        assert_eq!(
+            listing(&filter, &dir),
+            (
+                vec![
+                    String::from(".env.rs"),
+                    String::from(".git"),
+                    String::from("a.rs"),
+                    String::from("src")
+                ],
+                2
+            )
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn read_dir_stops_at_the_limit() {
+        let dir = setup("limit", &["a", "b", "c", "d", "e"]);
+        let (items, hidden) = Filter::new().read_dir(&dir, 3).unwrap();
+        assert_eq!(items.len(), 3);
+        assert_eq!(hidden, 0);
+        assert!(Filter::new().read_dir(&dir.join("missing"), 3).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn fuzzy_folds_case() {
+        assert_eq!(fuzzy_match("abc", "ABC"), Some(vec![0, 1, 2]));
+        assert_eq!(fuzzy_match("ABC", "abc"), Some(vec![0, 1, 2]));
+        assert_eq!(fuzzy_match("", "abc"), Some(Vec::new()));
+        assert_eq!(fuzzy_match("abcd", "abc"), None);
+        assert_eq!(fuzzy_match("ba", "abc"), None);
+    }
+
+    #[test]
+    fn fuzzy_prefers_substrings() {
+        // A subsequence would be 1, 3
+        assert_eq!(fuzzy_match("ab", "xaxab"), Some(vec![3, 4]));
+        assert_eq!(fuzzy_match("fb", "foo_bar"), Some(vec![0, 4]));
+    }
+
+    #[test]
+    fn fuzzy_indexes_chars() {
+        assert_eq!(fuzzy_match("日本", "x日y本.txt"), Some(vec![1, 3]));
+        assert_eq!(fuzzy_match("wö", "héllo WÖrld"), Some(vec![6, 7]));
+        assert_eq!(fuzzy_match("ld", "héllo wörld"), Some(vec![9, 10]));
+        // İ lowercases to two chars, only the first is kept so the indices stay in line
+        assert_eq!(fuzzy_match("x", "İx"), Some(vec![1]));
+    }
+
+    #[test]
+    fn find_is_smartcase() {
+        assert_eq!(find_match("abc", "xABC"), Some(vec![1, 2, 3]));
+        // An uppercase char makes the match case sensitive
+        assert_eq!(find_match("Abc", "xabc"), None);
+        assert_eq!(find_match("Abc", "abc_Abc"), Some(vec![4, 5, 6]));
+        assert_eq!(find_match("É", "café"), None);
+        assert_eq!(find_match("é", "CAFÉ"), Some(vec![3]));
+    }
+
+    #[test]
+    fn find_matches_in_one_piece() {
+        assert_eq!(find_match("", "abc"), None);
+        assert_eq!(find_match("ac", "abc"), None);
+        assert_eq!(find_match("bb", "abbb"), Some(vec![1, 2]));
+        assert_eq!(find_match("本", "日本日本"), Some(vec![1]));
+    }
+}
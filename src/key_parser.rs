@@ -0,0 +1,292 @@
+use std::time::{Duration, Instant};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// Pending key sequences get dropped after this long without a new key, like vim's timeoutlen
+const TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// Cursor movements in the main list.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Motion {
+    Down(usize),
+    Up(usize),
+    /// 1 based line number, like 5G
+    Line(usize),
+    Bottom,
+    ScreenTop,
+    ScreenMiddle,
+    ScreenBottom,
+    HalfPageDown,
+    HalfPageUp,
+    PageDown,
+    PageUp,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Operator {
+    Delete,
+    Yank,
+}
+
+/// What a (possibly multi key) sequence resolved to.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Action {
+    /// The sequence isn't complete yet
+    Pending,
+    /// The sequence didn't mean anything and got dropped
+    Cancelled,
+    Move(Motion),
+    /// An operator applied to *count* items starting at the cursor (dd, 3yy)
+    Operate(Operator, usize),
+    /// A two key command like zt, sn, gr or @a, with the count typed before it
+    Prefixed(char, KeyCode, Option<usize>),
+    /// A single key command, with the count typed before it
+    Key(KeyEvent, Option<usize>),
+}
+
+/// Parses normal mode keys into counts, operators and motions.
+#[derive(Debug)]
+pub struct KeyParser {
+    count: Option<usize>,
+    pending: Option<char>,
+    keys: String,
+    last_key: Instant,
+}
+
+impl Default for KeyParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KeyParser {
+    pub fn new() -> Self {
+        Self {
+            count: None,
+            pending: None,
+            keys: String::new(),
+            last_key: Instant::now(),
+        }
+    }
+
+    /// Returns the keys of the sequence typed so far, shown in the status bar
+    pub fn get_pending(&self) -> &str {
+        &self.keys
+    }
+
+    pub fn clear(&mut self) {
+        self.count = None;
+        self.pending = None;
+        self.keys.clear();
+    }
+
+    /// Drops the pending sequence if no key came in for a while. Returns true if it did
+    pub fn check_timeout(&mut self) -> bool {
+        if !self.keys.is_empty() && self.last_key.elapsed() > TIMEOUT {
+            self.clear();
+            return true;
+        }
+        false
+    }
+
+    /// Feeds a key into the parser
+    pub fn feed(&mut self, key: &KeyEvent) -> Action {
+        self.last_key = Instant::now();
+
+        if key.code == KeyCode::Esc && !self.keys.is_empty() {
+            self.clear();
+            return Action::Cancelled;
+        }
+
+        if let Some(prefix) = self.pending {
+            let count = self.count;
+            self.clear();
+            return self.resolve_pending(prefix, key, count);
+        }
+
+        if key.modifiers == KeyModifiers::CONTROL {
+            let motion = match key.code {
+                KeyCode::Char('d') => Some(Motion::HalfPageDown),
+                KeyCode::Char('u') => Some(Motion::HalfPageUp),
+                KeyCode::Char('f') => Some(Motion::PageDown),
+                KeyCode::Char('b') => Some(Motion::PageUp),
+                _ => None,
+            };
+            let count = self.count;
+            self.clear();
+            return match motion {
+                Some(motion) => Action::Move(motion),
+                None => Action::Key(*key, count),
+            };
+        }
+
+        if let KeyCode::Char(c) = key.code {
+            // A leading 0 isn't a count
+            if let Some(digit) = c.to_digit(10)
+                && (digit != 0 || self.count.is_some())
+            {
+                let count = self.count.unwrap_or(0);
+                self.count = Some(count.saturating_mul(10).saturating_add(digit as usize));
+                self.keys.push(c);
+                return Action::Pending;
+            }
+
+            if matches!(c, 'g' | 'd' | 'y' | 'z' | 's' | 'q' | '@' | 'f') {
+                self.pending = Some(c);
+                self.keys.push(c);
+                return Action::Pending;
+            }
+        }
+
+        let count = self.count;
+        self.clear();
+        let n = count.unwrap_or(1);
+        match key.code {
+            KeyCode::Down | KeyCode::Char('j') => Action::Move(Motion::Down(n)),
+            KeyCode::Up | KeyCode::Char('k') => Action::Move(Motion::Up(n)),
+            KeyCode::Char('G') => match count {
+                Some(line) => Action::Move(Motion::Line(line)),
+                None => Action::Move(Motion::Bottom),
+            },
+            KeyCode::Char('H') => Action::Move(Motion::ScreenTop),
+            KeyCode::Char('M') => Action::Move(Motion::ScreenMiddle),
+            KeyCode::Char('L') => Action::Move(Motion::ScreenBottom),
+            _ => Action::Key(*key, count),
+        }
+    }
+
+    /// Resolves the second key of a sequence
+    fn resolve_pending(&mut self, prefix: char, key: &KeyEvent, count: Option<usize>) -> Action {
+        match (prefix, key.code) {
+            ('g', KeyCode::Char('g')) => Action::Move(Motion::Line(count.unwrap_or(1))),
+            ('d', KeyCode::Char('d')) => Action::Operate(Operator::Delete, count.unwrap_or(1)),
+            ('y', KeyCode::Char('y')) => Action::Operate(Operator::Yank, count.unwrap_or(1)),
+            ('g' | 'z' | 's' | 'q' | '@' | 'f', code) => Action::Prefixed(prefix, code, count),
+            _ => Action::Cancelled,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(c: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+
+    /// Feeds every char of *keys*, returning what the last one resolved to
+    fn feed(parser: &mut KeyParser, keys: &str) -> Action {
+        keys.chars().map(|c| parser.feed(&key(c))).last().unwrap()
+    }
+
+    #[test]
+    fn counts_repeat_motions() {
+        let mut parser = KeyParser::new();
+        assert_eq!(feed(&mut parser, "j"), Action::Move(Motion::Down(1)));
+        assert_eq!(feed(&mut parser, "3j"), Action::Move(Motion::Down(3)));
+        assert_eq!(feed(&mut parser, "12k"), Action::Move(Motion::Up(12)));
+        assert_eq!(feed(&mut parser, "10j"), Action::Move(Motion::Down(10)));
+    }
+
+    #[test]
+    fn count_keys_are_pending() {
+        let mut parser = KeyParser::new();
+        assert_eq!(feed(&mut parser, "42"), Action::Pending);
+        assert_eq!(parser.get_pending(), "42");
+        assert_eq!(feed(&mut parser, "j"), Action::Move(Motion::Down(42)));
+        assert_eq!(parser.get_pending(), "");
+    }
+
+    #[test]
+    fn leading_zero_is_not_a_count() {
+        let mut parser = KeyParser::new();
+        assert_eq!(feed(&mut parser, "0"), Action::Key(key('0'), None));
+    }
+
+    #[test]
+    fn huge_counts_saturate() {
+        let mut parser = KeyParser::new();
+        let action = feed(&mut parser, "99999999999999999999999j");
+        assert_eq!(action, Action::Move(Motion::Down(usize::MAX)));
+    }
+
+    #[test]
+    fn line_motions() {
+        let mut parser = KeyParser::new();
+        assert_eq!(feed(&mut parser, "G"), Action::Move(Motion::Bottom));
+        assert_eq!(feed(&mut parser, "5G"), Action::Move(Motion::Line(5)));
+        assert_eq!(feed(&mut parser, "gg"), Action::Move(Motion::Line(1)));
+        assert_eq!(feed(&mut parser, "7gg"), Action::Move(Motion::Line(7)));
+        assert_eq!(feed(&mut parser, "H"), Action::Move(Motion::ScreenTop));
+    }
+
+    #[test]
+    fn control_keys_page() {
+        let mut parser = KeyParser::new();
+        let ctrl_d = KeyEvent::new(KeyCode::Char('d'), KeyModifiers::CONTROL);
+        assert_eq!(parser.feed(&ctrl_d), Action::Move(Motion::HalfPageDown));
+        let ctrl_x = KeyEvent::new(KeyCode::Char('x'), KeyModifiers::CONTROL);
+        feed(&mut parser, "3");
+        assert_eq!(parser.feed(&ctrl_x), Action::Key(ctrl_x, Some(3)));
+    }
+
+    #[test]
+    fn operators_take_counts() {
+        let mut parser = KeyParser::new();
+        assert_eq!(
+            feed(&mut parser, "dd"),
+            Action::Operate(Operator::Delete, 1)
+        );
+        assert_eq!(feed(&mut parser, "3yy"), Action::Operate(Operator::Yank, 3));
+        // The operator has to be doubled
+        assert_eq!(feed(&mut parser, "dy"), Action::Cancelled);
+        assert_eq!(feed(&mut parser, "dj"), Action::Cancelled);
+    }
+
+    #[test]
+    fn prefixed_commands() {
+        let mut parser = KeyParser::new();
+        assert_eq!(
+            feed(&mut parser, "zt"),
+            Action::Prefixed('z', KeyCode::Char('t'), None)
+        );
+        assert_eq!(
+            feed(&mut parser, "2@a"),
+            Action::Prefixed('@', KeyCode::Char('a'), Some(2))
+        );
+        assert_eq!(
+            feed(&mut parser, "gr"),
+            Action::Prefixed('g', KeyCode::Char('r'), None)
+        );
+    }
+
+    #[test]
+    fn single_keys_keep_their_count() {
+        let mut parser = KeyParser::new();
+        assert_eq!(feed(&mut parser, "x"), Action::Key(key('x'), None));
+        assert_eq!(feed(&mut parser, "4R"), Action::Key(key('R'), Some(4)));
+    }
+
+    #[test]
+    fn escape_cancels_a_sequence() {
+        let mut parser = KeyParser::new();
+        feed(&mut parser, "3d");
+        let esc = KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE);
+        assert_eq!(parser.feed(&esc), Action::Cancelled);
+        assert_eq!(feed(&mut parser, "j"), Action::Move(Motion::Down(1)));
+        // Without a sequence, Esc is a plain key
+        assert_eq!(parser.feed(&esc), Action::Key(esc, None));
+    }
+
+    #[test]
+    fn sequences_time_out() {
+        let mut parser = KeyParser::new();
+        feed(&mut parser, "5g");
+        assert!(!parser.check_timeout());
+        parser.last_key -= TIMEOUT * 2;
+        assert!(parser.check_timeout());
+        assert_eq!(parser.get_pending(), "");
+        assert_eq!(feed(&mut parser, "j"), Action::Move(Motion::Down(1)));
+    }
+}
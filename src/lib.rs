@@ -1,5 +1,16 @@
 pub mod app;
 pub mod app_properties;
+pub mod archive;
+pub mod bookmarks;
+pub mod command;
+pub mod database;
+pub mod filter;
+pub mod key_parser;
+pub mod macros;
 pub mod manager;
-pub mod offset_buffer;
+pub mod preview;
+pub mod rename;
+pub mod sort;
+pub mod tree;
 pub mod ui;
+pub mod xdg;
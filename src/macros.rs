@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+
+use crossterm::event::KeyEvent;
+
+/// Records key sequences into named registers (q{reg} ... q) so they can be replayed (@{reg}).
+#[derive(Debug, Default)]
+pub struct MacroRecorder {
+    registers: HashMap<char, Vec<KeyEvent>>,
+    recording: Option<(char, Vec<KeyEvent>)>,
+    last_played: Option<char>,
+    replaying: bool,
+}
+
+impl MacroRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the register that is being recorded into
+    pub fn get_recording(&self) -> Option<char> {
+        self.recording.as_ref().map(|(reg, _)| *reg)
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.replaying
+    }
+
+    pub fn set_replaying(&mut self, replaying: bool) {
+        self.replaying = replaying;
+    }
+
+    pub fn start(&mut self, register: char) {
+        self.recording = Some((register, Vec::new()));
+    }
+
+    /// Stops recording and stores the keys in the register
+    pub fn stop(&mut self) {
+        if let Some((register, keys)) = self.recording.take() {
+            self.registers.insert(register, keys);
+        }
+    }
+
+    /// Adds a key to the macro being recorded. Keys coming from a replay are not recorded twice
+    pub fn record(&mut self, key: &KeyEvent) {
+        if self.replaying {
+            return;
+        }
+        if let Some((_, keys)) = &mut self.recording {
+            keys.push(*key);
+        }
+    }
+
+    /// Returns the keys stored in *register*. '@' stands for the last played register, like @@
+    pub fn get(&mut self, register: char) -> Option<Vec<KeyEvent>> {
+        let register = if register == '@' {
+            self.last_played?
+        } else {
+            register
+        };
+        self.last_played = Some(register);
+        self.registers.get(&register).cloned()
+    }
+}
@@ -1,5 +1,3 @@
-use tokio;
-
 use rune::app::*;
 use std::io;
 
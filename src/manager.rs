@@ -1,17 +1,22 @@
-use core::{fmt, time};
+use core::fmt;
 use std::collections::{HashMap, HashSet};
-use std::env::vars;
 use std::error::Error;
-use std::fs::OpenOptions;
-use std::io::{BufRead, Read, Write};
-use std::path::PathBuf;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant, SystemTime};
-use std::{fs, io, u32, usize};
+use std::{fs, io};
 
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 
-use crate::ui::UI;
+use crate::archive;
+use crate::filter::Filter;
+use crate::preview::encoding::{self, DecodedText};
+use crate::rename;
+use crate::sort::{self, SortOptions};
+
+/// How much of a file the text preview reads
+const TEXT_LIMIT: usize = 16 * 1024;
 
 #[derive(Debug)]
 pub enum ManagerError {
@@ -23,7 +28,10 @@ impl Error for ManagerError {}
 
 impl fmt::Display for ManagerError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        todo!()
+        match self {
+            ManagerError::InvalidPath => write!(f, "Invalid path"),
+            ManagerError::NoPermission => write!(f, "Permission denied"),
+        }
     }
 }
 
@@ -45,7 +53,16 @@ pub enum MoveOption {
 struct Flags {
     pub is_searching: Arc<Mutex<bool>>,
     pub is_indexing: Arc<Mutex<bool>>,
+    pub is_flattening: Arc<Mutex<bool>>,
     pub is_loading: bool,
+    pub jobs: Arc<Mutex<Jobs>>,
+}
+
+/// The file operations running in the background, and the outcomes of the finished ones
+#[derive(Default)]
+struct Jobs {
+    running: Vec<String>,
+    finished: Vec<Result<String, String>>,
 }
 
 impl Flags {
@@ -53,7 +70,9 @@ impl Flags {
         Self {
             is_searching: Arc::new(Mutex::new(false)),
             is_indexing: Arc::new(Mutex::new(false)),
+            is_flattening: Arc::new(Mutex::new(false)),
             is_loading: false,
+            jobs: Arc::new(Mutex::new(Jobs::default())),
         }
     }
 }
@@ -73,13 +92,18 @@ impl Index {
 }
 
 pub struct Manager {
-    root: PathBuf,
-    homedir: PathBuf,
     current: PathBuf,
     flags: Flags,
     pathstack: Vec<(PathBuf, usize)>,
     index: Arc<Mutex<Index>>,
-    cache: HashMap<String, HashSet<PathBuf>>,
+    sort_options: HashMap<PathBuf, SortOptions>,
+    filter: Filter,
+}
+
+impl Default for Manager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Manager {
@@ -88,13 +112,12 @@ impl Manager {
         let home = PathBuf::from(std::env::var("HOME").unwrap_or("/".to_string()));
 
         let mut manager = Self {
-            root: PathBuf::from("/"),
-            homedir: home.clone(),
             current: home.clone(),
             flags: Flags::new(),
             pathstack: Vec::new(),
             index: Arc::new(Mutex::new(Index::new())),
-            cache: HashMap::new(),
+            sort_options: HashMap::new(),
+            filter: Filter::load(),
         };
 
         let index = manager.index.lock().unwrap();
@@ -134,7 +157,7 @@ impl Manager {
 
     pub fn shutdown(&self) {
         let index = Arc::clone(&self.index);
-        Manager::save_index(index);
+        _ = Manager::save_index(index);
     }
 
     pub fn get_current_path(&self) -> &PathBuf {
@@ -146,77 +169,75 @@ impl Manager {
     }
 
     /// Returns the directory currently opened in the manager
-    pub fn read_dir(&self, path: &PathBuf, option: OpenOption) -> std::io::Result<Vec<PathBuf>> {
-        let mut items = Vec::new();
+    pub fn read_dir(&self, path: &Path, option: OpenOption) -> std::io::Result<Vec<PathBuf>> {
+        self.list_dir(path, option).map(|(items, _)| items)
+    }
 
+    /// Same as read_dir(), but also returns the number of items the filter doesn't list
+    pub fn list_dir(
+        &self,
+        path: &Path,
+        option: OpenOption,
+    ) -> std::io::Result<(Vec<PathBuf>, usize)> {
         let size = match option {
             OpenOption::Full => usize::MAX,
             OpenOption::Preview => 100,
         };
-        for entry in std::fs::read_dir(&path)?.take(size) {
-            let entry = entry?;
-            let path = entry.path();
+        let (mut items, hidden) = self.filter.read_dir(path, size)?;
+        sort::sort_items(&mut items, &self.get_sort(path));
 
-            items.push(path);
-        }
-        items.sort_by(|a, b| {
-            // Helper function to categorize paths
-            fn categorize(path: &PathBuf) -> (u8, String) {
-                let is_hidden = path
-                    .file_name()
-                    .and_then(|name| name.to_str())
-                    .map(|name| name.starts_with('.'))
-                    .unwrap_or(false);
-
-                let is_folder = path.is_dir();
-
-                let priority = if is_hidden {
-                    2
-                } else if is_folder {
-                    0
-                } else {
-                    1
-                };
-
-                let name = path
-                    .file_name()
-                    .and_then(|name| name.to_str())
-                    .unwrap_or("")
-                    .to_string();
-
-                (priority, name)
-            }
+        Ok((items, hidden))
+    }
 
-            let (priority_a, name_a) = categorize(a);
-            let (priority_b, name_b) = categorize(b);
+    pub fn get_filter(&self) -> &Filter {
+        &self.filter
+    }
 
-            priority_a
-                .cmp(&priority_b)
-                .then_with(|| name_a.cmp(&name_b))
-        });
+    pub fn get_filter_mut(&mut self) -> &mut Filter {
+        &mut self.filter
+    }
+
+    /// Returns how *path* is sorted. Directories that weren't sorted explicitly use the default
+    pub fn get_sort(&self, path: &Path) -> SortOptions {
+        self.sort_options.get(path).copied().unwrap_or_default()
+    }
 
-        Ok(items)
+    /// Remembers how *path* should be sorted, used by read_dir() from then on
+    pub fn set_sort(&mut self, path: &Path, options: SortOptions) {
+        self.sort_options.insert(path.to_path_buf(), options);
     }
 
-    pub fn read_file(&self, path: &PathBuf) -> io::Result<String> {
+    /// Reads the first 100 lines of a text file, for the preview. The encoding gets detected,
+    /// files that don't look like text are an InvalidData error
+    pub fn read_file(path: &Path) -> io::Result<DecodedText> {
         if !path.is_file() {
             return Err(io::ErrorKind::IsADirectory.into());
         }
 
-        let mut file = fs::File::open(path)?;
-        let mut buffer = vec![0; 16 * 1024];
-        let bytes = file.read(&mut buffer)?;
-        buffer.truncate(bytes);
+        let file = fs::File::open(path)?;
+        let mut buffer = Vec::new();
+        file.take(TEXT_LIMIT as u64).read_to_end(&mut buffer)?;
+        Manager::decode_text(&buffer)
+    }
 
-        match std::str::from_utf8(&buffer) {
-            Ok(text) => {
-                let preview: String = text.lines().take(100).collect::<Vec<&str>>().join("\n");
-                Ok(preview)
+    /// Decodes the start of a file like *read_file*, for files that aren't on disk
+    pub fn decode_text(buffer: &[u8]) -> io::Result<DecodedText> {
+        let buffer = &buffer[..buffer.len().min(TEXT_LIMIT)];
+        match encoding::decode(buffer) {
+            Some(mut decoded) => {
+                // Old Mac files break lines with a lone CR
+                if decoded.line_ending == Some("CR") {
+                    decoded.text = decoded.text.replace('\r', "\n");
+                }
+                decoded.text = decoded
+                    .text
+                    .lines()
+                    .take(100)
+                    .collect::<Vec<&str>>()
+                    .join("\n");
+                Ok(decoded)
             }
-            Err(_) => Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Binary or non-UTF-8 file",
-            )),
+            None => Err(io::Error::new(io::ErrorKind::InvalidData, "Binary file")),
         }
     }
 
@@ -237,6 +258,7 @@ impl Manager {
 
         //pathstack could maybe just store references
         self.pathstack.push((self.current.clone(), cursor_idx));
+        self.cancel_flatten();
         items.lock().unwrap().clear();
 
         *self.flags.is_searching.lock().unwrap() = true;
@@ -256,8 +278,8 @@ impl Manager {
     fn index_search(&mut self, term: &str, items: &Arc<Mutex<Vec<PathBuf>>>) {
         let split: Vec<&str> = term.split(".").collect();
         let mut filename = String::from(split[0]);
-        for i in 1..split.len() - 1 {
-            filename.push_str(&format!(".{}", split[i]));
+        for part in &split[1..split.len() - 1] {
+            filename.push_str(&format!(".{}", part));
         }
         let extension = if split.len() > 1 {
             Some(split.last().unwrap())
@@ -284,7 +306,7 @@ impl Manager {
     ///performs a recursive, multithreadded search traversing from the current direcoty
     fn fallback_search(&self, term: &str, items: &Arc<Mutex<Vec<PathBuf>>>) {
         let is_searching_arc = Arc::clone(&self.flags.is_searching);
-        let items = Arc::clone(&items);
+        let items = Arc::clone(items);
         let term = term.to_string();
         let path = self.current.clone();
         let search_flag = Arc::clone(&self.flags.is_searching);
@@ -302,11 +324,43 @@ impl Manager {
         is_searching: Arc<Mutex<bool>>,
         delta_time: Instant,
     ) -> Result<(), Box<dyn Error>> {
+        let term = term.to_lowercase();
+        let visit = |path: &PathBuf| {
+            let mut items_lock = items.lock().unwrap();
+            // Temporary fix, for huge searches
+            if items_lock.len() > 2000 {
+                return false;
+            }
+            if let Some(name) = path.file_name()
+                && name.to_string_lossy().to_lowercase().contains(&term)
+                && !items_lock.contains(path)
+            {
+                items_lock.push(path.clone());
+            }
+            true
+        };
+        Manager::traverse(&path, 0, usize::MAX, &is_searching, delta_time, &visit)
+    }
+
+    /// Recursive, multithreadded traversal used by the search.
+    /// *visit* gets called for every item, returning false stops the traversal below that item.
+    /// Directories deeper than *max_depth* are not read.
+    fn traverse<F>(
+        path: &Path,
+        depth: usize,
+        max_depth: usize,
+        running: &Arc<Mutex<bool>>,
+        delta_time: Instant,
+        visit: &F,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        F: Fn(&PathBuf) -> bool + Sync,
+    {
         if delta_time.elapsed() > Duration::from_secs(20) {
             return Ok(());
         }
 
-        if !*is_searching.lock().unwrap() {
+        if !*running.lock().unwrap() {
             return Ok(());
         }
 
@@ -322,44 +376,110 @@ impl Manager {
             return Ok(());
         }
 
-        // Temporary fix, for huge searches
-        if items.lock().unwrap().len() > 2000 {
-            return Ok(());
-        }
-
         let content: Vec<_> = fs::read_dir(path)?.filter_map(Result::ok).collect();
         content.par_iter().for_each(|item| {
             let path = item.path();
 
-            if let Some(name) = path.file_name() {
-                let term = term.to_lowercase();
-                let mut items_lock = items.lock().unwrap();
-                if name.to_string_lossy().to_lowercase().contains(&term)
-                    && !items_lock.contains(&path)
-                {
-                    items_lock.push(path.clone());
-                }
-                drop(items_lock);
+            if !visit(&path) {
+                return;
             }
 
-            if path.is_dir() {
-                let items = Arc::clone(&items);
-                let is_searching = Arc::clone(&is_searching);
-                Manager::fallback_recursion(term, path, items, is_searching, delta_time)
+            if path.is_dir() && depth + 1 < max_depth {
+                Manager::traverse(&path, depth + 1, max_depth, running, delta_time, visit)
                     .unwrap_or(());
             }
         });
         Ok(())
     }
 
+    /// Starts listing every file under the current directory into *items*, going at most
+    /// *max_depth* directories deep. Runs in the background and can be stopped with
+    /// cancel_flatten(). The items get sorted once the listing is done.
+    pub fn flatten_dir(&mut self, items: Arc<Mutex<Vec<PathBuf>>>, max_depth: Option<usize>) {
+        self.cancel_flatten();
+        // Every listing gets its own flag, so a cancelled one can't be revived by the next one
+        let is_flattening = Arc::new(Mutex::new(true));
+        self.flags.is_flattening = Arc::clone(&is_flattening);
+        let path = self.current.clone();
+        let sort = self.get_sort(&path);
+        let filter = self.filter.clone();
+        items.lock().unwrap().clear();
+
+        tokio::spawn(async move {
+            let visit = |path: &PathBuf| {
+                if !filter.is_visible(path) {
+                    return false;
+                }
+                if path.is_file() {
+                    items.lock().unwrap().push(path.clone());
+                }
+                true
+            };
+            let max_depth = max_depth.unwrap_or(usize::MAX);
+            Manager::flatten_recursion(&path, 0, max_depth, &is_flattening, &visit);
+
+            if *is_flattening.lock().unwrap() {
+                sort::sort_items(&mut items.lock().unwrap(), &sort);
+            }
+            *is_flattening.lock().unwrap() = false;
+        });
+    }
+
+    /// Recursive, multithreadded walk for the flatten view. Unlike traverse() there is no time
+    /// limit, the walk only stops when *running* gets cleared. Symlinked directories are not
+    /// followed, so a link back up the tree can't loop
+    fn flatten_recursion<F>(
+        path: &Path,
+        depth: usize,
+        max_depth: usize,
+        running: &Arc<Mutex<bool>>,
+        visit: &F,
+    ) where
+        F: Fn(&PathBuf) -> bool + Sync,
+    {
+        if !*running.lock().unwrap() {
+            return;
+        }
+        let Ok(entries) = fs::read_dir(path) else {
+            return;
+        };
+
+        let content: Vec<_> = entries.filter_map(Result::ok).collect();
+        content.par_iter().for_each(|entry| {
+            let path = entry.path();
+            if !visit(&path) {
+                return;
+            }
+
+            // The file type of the entry itself, a symlink isn't a directory here
+            let is_dir = entry
+                .file_type()
+                .is_ok_and(|kind| kind.is_dir() && !kind.is_symlink());
+            if is_dir && depth + 1 < max_depth {
+                Manager::flatten_recursion(&path, depth + 1, max_depth, running, visit);
+            }
+        });
+    }
+
+    /// Stops the flatten listing if one is running
+    pub fn cancel_flatten(&self) {
+        *self.flags.is_flattening.lock().unwrap() = false;
+    }
+
     /// Creates a folder or a file in the current directory.
     /// Filenames ending with "/" are considered folders.
     ///
     /// *file_name* is a '&str' because it is easier just to specify a name
     /// and then let the function build the correct path based on the current directory.
     pub fn create_fsitem(&self, file_name: &str) -> Result<(), String> {
+        self.create_fsitem_at(&self.current, file_name)
+    }
+
+    /// Same as create_fsitem(), but creates the item inside *dir* instead of the current
+    /// directory. Used by the tree view, where the cursor can be inside an expanded folder.
+    pub fn create_fsitem_at(&self, dir: &Path, file_name: &str) -> Result<(), String> {
         let mut path = PathBuf::new();
-        path.push(&self.current);
+        path.push(dir);
         path.push(file_name);
 
         if file_name.ends_with('/') {
@@ -411,6 +531,11 @@ impl Manager {
         }
     }
 
+    /// Renames several items at once, see rename::execute()
+    pub fn bulk_rename(&self, renames: &[(PathBuf, PathBuf)]) -> Result<(), String> {
+        rename::execute(renames)
+    }
+
     pub fn move_fsitem(
         &self,
         source: PathBuf,
@@ -433,6 +558,96 @@ impl Manager {
         }
     }
 
+    /// Packs *sources* into the new archive *dest* in the background, the format is told by
+    /// the extension of *dest*
+    pub fn compress_fsitems(&self, sources: Vec<PathBuf>, dest: PathBuf) -> Result<(), String> {
+        if archive::Format::from_path(&dest).is_none() {
+            return Err(String::from(
+                "Unknown archive format, use .zip, .tar, .tar.gz, .tar.xz or .tar.zst",
+            ));
+        }
+        if dest.symlink_metadata().is_ok() {
+            return Err(String::from("Destination already exists"));
+        }
+
+        let name = dest
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        self.spawn_job(format!("Compressing {name}..."), move || {
+            archive::create(&dest, &sources)?;
+            Ok(format!("Created {name}"))
+        });
+        Ok(())
+    }
+
+    /// Extracts the archive *source* in the background, into a new folder next to it
+    pub fn extract_fsitem(&self, source: PathBuf) -> Result<(), String> {
+        if archive::Format::from_path(&source).is_none() || !source.is_file() {
+            return Err(String::from("Not an archive"));
+        }
+
+        let name = source
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        self.spawn_job(format!("Extracting {name}..."), move || {
+            let dest = archive::extract_dir(&source);
+            let (count, skipped) = archive::extract_all(&source, &dest)?;
+            let dir = dest.file_name().unwrap_or_default().to_string_lossy();
+            Ok(match skipped {
+                0 => format!("Extracted {count} file(s) to {dir}"),
+                _ => {
+                    format!("Extracted {count} file(s) to {dir}, skipped {skipped} unsafe entries")
+                }
+            })
+        });
+        Ok(())
+    }
+
+    /// Extracts *members* of the archive *source* into *dest* in the background
+    pub fn extract_members(&self, source: PathBuf, members: Vec<PathBuf>, dest: PathBuf) {
+        let name = source
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        self.spawn_job(format!("Extracting from {name}..."), move || {
+            let count = archive::extract(&source, &members, &dest)?;
+            Ok(format!("Extracted {count} file(s) to {}", dest.display()))
+        });
+    }
+
+    /// Runs *job* on its own thread, *name* is listed while it runs
+    fn spawn_job(
+        &self,
+        name: String,
+        job: impl FnOnce() -> Result<String, String> + Send + 'static,
+    ) {
+        let jobs = Arc::clone(&self.flags.jobs);
+        jobs.lock().unwrap().running.push(name.clone());
+        std::thread::spawn(move || {
+            let result = job();
+            let mut jobs = jobs.lock().unwrap();
+            if let Some(idx) = jobs.running.iter().position(|running| *running == name) {
+                jobs.running.remove(idx);
+            }
+            jobs.finished.push(result);
+        });
+    }
+
+    /// Returns the names of the background jobs still running
+    pub fn running_jobs(&self) -> Vec<String> {
+        self.flags.jobs.lock().unwrap().running.clone()
+    }
+
+    /// Returns the outcomes of the background jobs that finished since the last call
+    pub fn take_finished_jobs(&self) -> Vec<Result<String, String>> {
+        std::mem::take(&mut self.flags.jobs.lock().unwrap().finished)
+    }
+
     /// Moves a file or folder to a different filesystem or mount point.
     /// It's basically a helper function for move_fsitem()
     fn move_crossfs(
@@ -478,7 +693,7 @@ impl Manager {
                         }
                         Ok(())
                     } else {
-                        return Err(format!("Can't open dir: {}", dest.to_string_lossy()));
+                        Err(format!("Can't open dir: {}", dest.to_string_lossy()))
                     }
                 }
                 Err(e) => Err(e.to_string()),
@@ -496,12 +711,17 @@ impl Manager {
         *self.flags.is_indexing.lock().unwrap()
     }
 
+    pub fn is_flattening(&self) -> bool {
+        *self.flags.is_flattening.lock().unwrap()
+    }
+
     pub fn is_loading(&self) -> bool {
         self.flags.is_loading
     }
 
     pub fn step_back(&mut self) -> Result<usize, ManagerError> {
         *self.flags.is_searching.lock().unwrap() = false;
+        self.cancel_flatten();
         if let Some((prev, cursor_idx)) = self.pathstack.pop() {
             self.current = prev;
             return Ok(cursor_idx);
@@ -517,35 +737,36 @@ impl Manager {
         &mut self,
         new_path: PathBuf,
         cursor_idx: usize,
-    ) -> Result<Vec<PathBuf>, ManagerError> {
+    ) -> Result<(Vec<PathBuf>, usize), ManagerError> {
         if !new_path.exists() || !new_path.is_dir() {
             return Err(ManagerError::InvalidPath);
         }
 
         *self.flags.is_searching.lock().unwrap() = false;
+        self.cancel_flatten();
 
         self.pathstack.push((self.current.clone(), cursor_idx));
         self.current = new_path;
 
         //I'll have to handle this error here better later on
-        let items = self.read_dir(&self.current, OpenOption::Full).unwrap();
+        let listing = self.list_dir(&self.current, OpenOption::Full).unwrap();
 
-        Ok(items)
+        Ok(listing)
     }
 
     /// Public function for building the index. Spawns a thread so the building can run in the
     /// background. Calls Manager::index_recursion
-    pub fn build_index(&self, dir: &PathBuf, option: IndexOption) -> Result<(), ManagerError> {
+    pub fn build_index(&self, dir: &Path, option: IndexOption) -> Result<(), ManagerError> {
         self.index.lock().unwrap().last_sync = Some(SystemTime::now());
         let index = Arc::clone(&self.index);
         let index2 = Arc::clone(&self.index);
         let is_indexing = Arc::clone(&self.flags.is_indexing);
-        let dir = dir.clone();
+        let dir = dir.to_path_buf();
 
         std::thread::spawn(move || {
             *is_indexing.lock().unwrap() = true;
-            Manager::index_recursion(index, &dir, option);
-            Manager::save_index(index2);
+            _ = Manager::index_recursion(index, &dir, option);
+            _ = Manager::save_index(index2);
             *is_indexing.lock().unwrap() = false;
         });
         Ok(())
@@ -592,16 +813,18 @@ impl Manager {
                         hs
                     });
 
-                if let IndexOption::Recursive = option {
-                    if path.is_dir() {
-                        Manager::index_recursion(index.clone(), &path, IndexOption::Recursive);
-                    }
+                if let IndexOption::Recursive = option
+                    && path.is_dir()
+                {
+                    _ = Manager::index_recursion(index.clone(), &path, IndexOption::Recursive);
                 }
             }
         });
         Ok(())
     }
 
+    // Public as it always was, Index itself stays internal
+    #[allow(private_interfaces)]
     pub fn save_index(index: Arc<Mutex<Index>>) -> Result<(), Box<dyn Error>> {
         fs::create_dir_all("index/").unwrap();
         let file1 = fs::File::create("index/index.json")?;
@@ -644,3 +867,61 @@ impl Manager {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn flatten_skips_symlinked_dirs() {
+        let dir = std::env::temp_dir().join(format!("rune-test-{}-flatten", std::process::id()));
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("a/b")).unwrap();
+        fs::write(dir.join("a/b/file"), "").unwrap();
+        fs::write(dir.join("top"), "").unwrap();
+        // A loop back up the tree, and a link to a file which still gets listed
+        std::os::unix::fs::symlink(&dir, dir.join("a/b/up")).unwrap();
+        std::os::unix::fs::symlink(dir.join("top"), dir.join("a/link")).unwrap();
+
+        let files = Mutex::new(Vec::new());
+        let visit = |path: &PathBuf| {
+            if path.is_file() {
+                files
+                    .lock()
+                    .unwrap()
+                    .push(path.strip_prefix(&dir).unwrap().to_path_buf());
+            }
+            true
+        };
+        let running = Arc::new(Mutex::new(true));
+        Manager::flatten_recursion(&dir, 0, usize::MAX, &running, &visit);
+
+        let mut files = files.into_inner().unwrap();
+        files.sort();
+        assert_eq!(
+            files,
+            [
+                PathBuf::from("a/b/file"),
+                PathBuf::from("a/link"),
+                PathBuf::from("top")
+            ]
+        );
+
+        // A depth of 1 only lists the directory itself
+        let files = Mutex::new(Vec::new());
+        let visit = |path: &PathBuf| {
+            if path.is_file() {
+                files.lock().unwrap().push(path.clone());
+            }
+            true
+        };
+        Manager::flatten_recursion(&dir, 0, 1, &running, &visit);
+        assert_eq!(files.into_inner().unwrap(), [dir.join("top")]);
+
+        // Nothing gets listed once cancelled
+        *running.lock().unwrap() = false;
+        Manager::flatten_recursion(&dir, 0, usize::MAX, &running, &|_: &PathBuf| panic!());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,360 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::archive::{self, Entry, Format};
+use crate::database::{self, Table};
+use crate::manager::Manager;
+
+pub mod data;
+pub mod encoding;
+pub mod graphics;
+pub mod hexdump;
+pub mod highlight;
+pub mod markdown;
+pub mod metadata;
+pub mod picture;
+
+use data::{DataFormat, ParseError, TablePreview, Value};
+use encoding::DecodedText;
+use highlight::{HighlightedLine, Highlighter};
+use markdown::MdLine;
+use metadata::Field;
+use picture::ImagePreview;
+
+/// How many previews are kept around
+const CACHE_SIZE: usize = 64;
+
+/// A file preview, built off the render path by the *Previewer*.
+#[derive(Debug)]
+pub enum Preview {
+    Text(TextPreview),
+    Hex(HexPreview),
+    Image(ImagePreview),
+    Archive(ArchivePreview),
+    Data(DataPreview),
+    Table(TablePreview),
+    Markdown(MarkdownPreview),
+    Database(DatabasePreview),
+    /// Nothing to show, with the reason
+    Unsupported(String),
+}
+
+#[derive(Debug)]
+pub struct TextPreview {
+    /// The name of the syntax the text got highlighted with
+    pub syntax: Option<String>,
+    pub encoding: &'static str,
+    pub line_ending: Option<&'static str>,
+    pub lines: Vec<HighlightedLine>,
+}
+
+#[derive(Debug)]
+pub struct HexPreview {
+    /// The format named by the file header
+    pub kind: Option<&'static str>,
+    /// The start of the file
+    pub bytes: Vec<u8>,
+    pub size: u64,
+    /// What the contents tell about an audio file or an executable
+    pub metadata: Vec<Field>,
+}
+
+#[derive(Debug)]
+pub struct ArchivePreview {
+    pub format: Format,
+    pub entries: Vec<Entry>,
+    /// The size of every file unpacked
+    pub total: u64,
+}
+
+#[derive(Debug)]
+pub struct DataPreview {
+    pub format: DataFormat,
+    /// The parsed document, or where parsing failed
+    pub document: Result<Value, ParseError>,
+    /// The pretty-printed document, or the text as it is if it didn't parse
+    pub lines: Vec<HighlightedLine>,
+}
+
+#[derive(Debug)]
+pub struct MarkdownPreview {
+    pub rendered: Vec<MdLine>,
+    /// The highlighted source, shown instead of the rendered document when asked for
+    pub source: TextPreview,
+}
+
+#[derive(Debug)]
+pub struct DatabasePreview {
+    pub tables: Vec<Table>,
+    /// The highlighted statement of every table
+    pub schemas: Vec<Vec<HighlightedLine>>,
+}
+
+/// A preview stays valid as long as the file isn't modified
+type Key = (PathBuf, SystemTime);
+
+#[derive(Default)]
+struct Cache {
+    previews: HashMap<Key, Arc<Preview>>,
+    /// Insertion order, the oldest preview gets dropped first
+    order: VecDeque<Key>,
+    /// The preview the worker was asked for last
+    requested: Option<Key>,
+}
+
+/// Builds previews on a worker thread and caches them per (path, mtime).
+pub struct Previewer {
+    cache: Arc<Mutex<Cache>>,
+    sender: Sender<Key>,
+}
+
+impl Default for Previewer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Previewer {
+    pub fn new() -> Self {
+        let cache = Arc::new(Mutex::new(Cache::default()));
+        let (sender, receiver) = mpsc::channel();
+        let worker_cache = Arc::clone(&cache);
+        std::thread::spawn(move || Previewer::work(receiver, worker_cache));
+
+        Self { cache, sender }
+    }
+
+    /// Returns the preview of *path* if it is ready, otherwise asks the worker for it
+    pub fn get(&self, path: &Path) -> Option<Arc<Preview>> {
+        // Files inside an archive change with the archive
+        let modified = match fs::metadata(path) {
+            Ok(metadata) => metadata.modified().ok()?,
+            Err(_) => {
+                let (archive, _) = archive::split(path)?;
+                fs::metadata(archive).and_then(|md| md.modified()).ok()?
+            }
+        };
+        let key = (path.to_path_buf(), modified);
+
+        let mut cache = self.cache.lock().unwrap();
+        if let Some(preview) = cache.previews.get(&key) {
+            return Some(Arc::clone(preview));
+        }
+        if cache.requested.as_ref() != Some(&key) {
+            cache.requested = Some(key.clone());
+            _ = self.sender.send(key);
+        }
+        None
+    }
+
+    /// Builds the requested previews. Requests that piled up while the last one was being built
+    /// are skipped, only the newest one matters when scrolling quickly
+    fn work(receiver: Receiver<Key>, cache: Arc<Mutex<Cache>>) {
+        let highlighter = Highlighter::new();
+        while let Ok(mut key) = receiver.recv() {
+            while let Ok(newer) = receiver.try_recv() {
+                key = newer;
+            }
+            if cache.lock().unwrap().previews.contains_key(&key) {
+                continue;
+            }
+
+            let preview = Arc::new(build(&highlighter, &key.0));
+            let mut cache = cache.lock().unwrap();
+            cache.previews.insert(key.clone(), preview);
+            cache.order.push_back(key);
+            while cache.order.len() > CACHE_SIZE {
+                if let Some(oldest) = cache.order.pop_front() {
+                    cache.previews.remove(&oldest);
+                }
+            }
+        }
+    }
+}
+
+fn build(highlighter: &Highlighter, path: &Path) -> Preview {
+    if !path.exists()
+        && let Some((archive, inner)) = archive::split(path)
+    {
+        let limit = if picture::is_image(path) {
+            picture::ENTRY_LIMIT
+        } else if DataFormat::from_path(path).is_some() || data::delimiter(path).is_some() {
+            data::DATA_LIMIT
+        } else if markdown::is_markdown(path) {
+            markdown::MARKDOWN_LIMIT
+        } else {
+            hexdump::HEX_LIMIT
+        };
+        return match archive::read_entry(&archive, &inner, limit) {
+            Ok((bytes, size)) => build_from_bytes(highlighter, path, bytes, size),
+            Err(e) => Preview::Unsupported(e),
+        };
+    }
+    if let Some(format) = Format::from_path(path) {
+        return match archive::list(path) {
+            Ok(entries) => Preview::Archive(ArchivePreview {
+                format,
+                total: entries.iter().map(|entry| entry.size).sum(),
+                entries,
+            }),
+            Err(e) => Preview::Unsupported(e),
+        };
+    }
+    if database::is_database(path) {
+        return match database::tables(path) {
+            Ok(tables) => Preview::Database(DatabasePreview {
+                schemas: (tables.iter())
+                    .map(|table| highlighter.highlight_code("sql", &table.sql))
+                    .collect(),
+                tables,
+            }),
+            Err(e) => Preview::Unsupported(e),
+        };
+    }
+    if picture::is_image(path) {
+        return match picture::load(path) {
+            Ok(image) => Preview::Image(image),
+            Err(e) => Preview::Unsupported(e),
+        };
+    }
+    let size = fs::metadata(path).map_or(0, |metadata| metadata.len());
+    if let Some(format) = DataFormat::from_path(path)
+        && size <= data::DATA_LIMIT as u64
+        && let Ok(bytes) = fs::read(path)
+        && let Some(preview) = data_preview(highlighter, path, format, &bytes)
+    {
+        return preview;
+    }
+    if markdown::is_markdown(path)
+        && size <= markdown::MARKDOWN_LIMIT as u64
+        && let Ok(bytes) = fs::read(path)
+        && let Some(preview) = markdown_preview(highlighter, path, &bytes)
+    {
+        return preview;
+    }
+    if let Some(delimiter) = data::delimiter(path) {
+        return match fs::File::open(path).map_err(|e| e.to_string()) {
+            Ok(file) => data::table(io::BufReader::new(file), delimiter)
+                .map_or_else(Preview::Unsupported, Preview::Table),
+            Err(e) => Preview::Unsupported(e),
+        };
+    }
+    match Manager::read_file(path) {
+        Ok(decoded) => text_preview(highlighter, path, decoded),
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => match read_bytes(path) {
+            Ok(hex) => Preview::Hex(hex),
+            Err(e) => Preview::Unsupported(e.to_string()),
+        },
+        Err(e) => Preview::Unsupported(e.to_string()),
+    }
+}
+
+fn text_preview(highlighter: &Highlighter, path: &Path, decoded: DecodedText) -> Preview {
+    let (syntax, lines) = highlighter.highlight(path, &decoded.text);
+    Preview::Text(TextPreview {
+        syntax,
+        encoding: decoded.encoding,
+        line_ending: decoded.line_ending,
+        lines,
+    })
+}
+
+/// Parses a JSON, YAML or TOML file and pretty-prints it. None if it isn't text
+fn data_preview(
+    highlighter: &Highlighter,
+    path: &Path,
+    format: DataFormat,
+    bytes: &[u8],
+) -> Option<Preview> {
+    let decoded = encoding::decode(bytes)?;
+    let document = data::parse(format, &decoded.text);
+    let text = match &document {
+        Ok(value) => data::pretty(format, value),
+        Err(_) => decoded.text,
+    };
+    let (_, lines) = highlighter.highlight(path, &text);
+    Some(Preview::Data(DataPreview {
+        format,
+        document,
+        lines,
+    }))
+}
+
+/// Renders a Markdown file and highlights its source. None if it isn't text
+fn markdown_preview(highlighter: &Highlighter, path: &Path, bytes: &[u8]) -> Option<Preview> {
+    let mut decoded = encoding::decode(bytes)?;
+    let rendered = markdown::render(&decoded.text, highlighter);
+    decoded.text = decoded
+        .text
+        .lines()
+        .take(markdown::SOURCE_LINES)
+        .collect::<Vec<&str>>()
+        .join("\n");
+    let Preview::Text(source) = text_preview(highlighter, path, decoded) else {
+        return None;
+    };
+    Some(Preview::Markdown(MarkdownPreview { rendered, source }))
+}
+
+/// Builds the preview of a file that was read into memory, *size* is its full size
+fn build_from_bytes(highlighter: &Highlighter, path: &Path, bytes: Vec<u8>, size: u64) -> Preview {
+    if picture::is_image(path) {
+        return match picture::decode(&bytes) {
+            Ok(image) => Preview::Image(image),
+            Err(e) => Preview::Unsupported(e),
+        };
+    }
+    // A file cut off at the limit wouldn't parse
+    let complete = bytes.len() as u64 == size;
+    if let Some(format) = DataFormat::from_path(path)
+        && complete
+        && let Some(preview) = data_preview(highlighter, path, format, &bytes)
+    {
+        return preview;
+    }
+    if markdown::is_markdown(path)
+        && let Some(preview) = markdown_preview(highlighter, path, &bytes)
+    {
+        return preview;
+    }
+    if let Some(delimiter) = data::delimiter(path)
+        && let Ok(table) = data::table(bytes.as_slice(), delimiter)
+    {
+        return Preview::Table(table);
+    }
+    match Manager::decode_text(&bytes) {
+        Ok(decoded) => text_preview(highlighter, path, decoded),
+        Err(_) => Preview::Hex(HexPreview {
+            kind: hexdump::sniff(&bytes),
+            // Durations are worked out from the full size, a cut off file would mislead
+            metadata: if complete {
+                metadata::read(&mut io::Cursor::new(&bytes), &bytes)
+            } else {
+                Vec::new()
+            },
+            bytes,
+            size,
+        }),
+    }
+}
+
+/// Reads the start of a binary file for the hex dump, and what its contents tell about it
+fn read_bytes(path: &Path) -> io::Result<HexPreview> {
+    let mut file = fs::File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut bytes = Vec::new();
+    (&mut file)
+        .take(hexdump::HEX_LIMIT as u64)
+        .read_to_end(&mut bytes)?;
+    Ok(HexPreview {
+        kind: hexdump::sniff(&bytes),
+        metadata: metadata::read(&mut file, &bytes),
+        bytes,
+        size,
+    })
+}
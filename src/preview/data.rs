@@ -0,0 +1,476 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+
+/// The largest data file that gets parsed, in bytes. Bigger files are previewed as text
+pub const DATA_LIMIT: usize = 4 * 1024 * 1024;
+
+/// How many pretty-printed lines are kept for the preview
+const PRETTY_LINES: usize = 1000;
+
+/// How many rows of a table are kept for the preview, the rest only gets counted
+const TABLE_ROWS: usize = 1000;
+
+/// The longest string shown in the key tree, in chars
+const SUMMARY_LENGTH: usize = 60;
+
+/// The key toml uses to pass datetimes through serde
+const TOML_DATETIME: &str = "$__toml_private_datetime";
+
+/// The structured text formats that get parsed for the preview.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DataFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl DataFormat {
+    /// Tells the format from the extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        match extension.as_str() {
+            "json" | "geojson" | "jsonld" => Some(DataFormat::Json),
+            "yaml" | "yml" => Some(DataFormat::Yaml),
+            "toml" => Some(DataFormat::Toml),
+            _ => None,
+        }
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            DataFormat::Json => "JSON",
+            DataFormat::Yaml => "YAML",
+            DataFormat::Toml => "TOML",
+        }
+    }
+}
+
+/// Where and why a document couldn't be parsed. Lines and columns start at 1
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+/// A parsed document. Keys stay in the order of the file
+#[derive(Debug, Clone)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Uint(u64),
+    Float(f64),
+    String(String),
+    /// A TOML date and time, kept apart from strings so it prints without quotes
+    Datetime(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+    /// The documents of a YAML stream with more than one
+    Documents(Vec<Value>),
+}
+
+impl Value {
+    /// Describes the value in a few words: scalars as they are written, containers by size
+    pub fn summary(&self) -> String {
+        match self {
+            Value::Null => String::from("null"),
+            Value::Bool(value) => value.to_string(),
+            Value::Int(value) => value.to_string(),
+            Value::Uint(value) => value.to_string(),
+            Value::Float(value) => value.to_string(),
+            Value::String(value) => {
+                let mut short: String = value.chars().take(SUMMARY_LENGTH).collect();
+                if short.len() < value.len() {
+                    short.push('…');
+                }
+                format!("{short:?}")
+            }
+            Value::Datetime(value) => value.clone(),
+            Value::Array(items) => format!("[{}]", count(items.len(), "item")),
+            Value::Object(entries) => format!("{{{}}}", count(entries.len(), "key")),
+            Value::Documents(documents) => format!("{} documents", documents.len()),
+        }
+    }
+
+    /// Returns the keys and values right inside the value, array items keyed by their index
+    pub fn children(&self) -> Vec<(String, &Value)> {
+        match self {
+            Value::Array(items) => items
+                .iter()
+                .enumerate()
+                .map(|(idx, item)| (format!("[{idx}]"), item))
+                .collect(),
+            Value::Documents(documents) => documents
+                .iter()
+                .enumerate()
+                .map(|(idx, document)| (format!("--- {}", idx + 1), document))
+                .collect(),
+            Value::Object(entries) => entries
+                .iter()
+                .map(|(key, value)| (key.clone(), value))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn is_container(&self) -> bool {
+        matches!(
+            self,
+            Value::Array(_) | Value::Object(_) | Value::Documents(_)
+        )
+    }
+
+    /// Lists the key tree the way it is shown: the children of the document, and the children
+    /// of every *expanded* node under it. Nodes are named by the child indices leading to them
+    pub fn rows(&self, expanded: &HashSet<Vec<usize>>) -> Vec<TreeRow<'_>> {
+        let mut rows = Vec::new();
+        if self.is_container() {
+            push_rows(&mut rows, self, &mut Vec::new(), expanded);
+        } else {
+            rows.push(TreeRow {
+                depth: 0,
+                path: Vec::new(),
+                key: String::new(),
+                value: self,
+                expanded: false,
+            });
+        }
+        rows
+    }
+}
+
+fn push_rows<'a>(
+    rows: &mut Vec<TreeRow<'a>>,
+    value: &'a Value,
+    path: &mut Vec<usize>,
+    expanded: &HashSet<Vec<usize>>,
+) {
+    for (idx, (key, child)) in value.children().into_iter().enumerate() {
+        path.push(idx);
+        let is_expanded = child.is_container() && expanded.contains(path);
+        rows.push(TreeRow {
+            depth: path.len() - 1,
+            path: path.clone(),
+            key,
+            value: child,
+            expanded: is_expanded,
+        });
+        if is_expanded {
+            push_rows(rows, child, path, expanded);
+        }
+        path.pop();
+    }
+}
+
+/// Counts *noun*, in plural unless there is one
+pub fn count(count: usize, noun: &str) -> String {
+    match count {
+        1 => format!("1 {noun}"),
+        _ => format!("{count} {noun}s"),
+    }
+}
+
+/// A line of the key tree.
+#[derive(Debug)]
+pub struct TreeRow<'a> {
+    pub depth: usize,
+    /// The child indices leading to the node, what *rows* takes as expanded
+    pub path: Vec<usize>,
+    pub key: String,
+    pub value: &'a Value,
+    pub expanded: bool,
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any value")
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_none<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_bool<E>(self, value: bool) -> Result<Value, E> {
+        Ok(Value::Bool(value))
+    }
+
+    fn visit_i64<E>(self, value: i64) -> Result<Value, E> {
+        Ok(Value::Int(value))
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<Value, E> {
+        Ok(Value::Uint(value))
+    }
+
+    fn visit_f64<E>(self, value: f64) -> Result<Value, E> {
+        Ok(Value::Float(value))
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Value, E> {
+        Ok(Value::String(value.to_string()))
+    }
+
+    fn visit_string<E>(self, value: String) -> Result<Value, E> {
+        Ok(Value::String(value))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut items = Vec::new();
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(Value::Array(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut entries = Vec::new();
+        // YAML keys can be anything, they get shown the way a value would be
+        while let Some(key) = map.next_key::<Value>()? {
+            let key = match key {
+                Value::String(key) => key,
+                key => key.summary(),
+            };
+            if key == TOML_DATETIME {
+                let datetime: String = map.next_value()?;
+                return Ok(Value::Datetime(datetime));
+            }
+            entries.push((key, map.next_value()?));
+        }
+        Ok(Value::Object(entries))
+    }
+
+    fn visit_enum<A: de::EnumAccess<'de>>(self, data: A) -> Result<Value, A::Error> {
+        // A YAML !tag, shown as a key with the tagged value
+        use de::VariantAccess;
+        let (tag, variant): (String, _) = data.variant()?;
+        let value = variant.newtype_variant()?;
+        Ok(Value::Object(vec![(format!("!{tag}"), value)]))
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Null => serializer.serialize_unit(),
+            Value::Bool(value) => serializer.serialize_bool(*value),
+            Value::Int(value) => serializer.serialize_i64(*value),
+            Value::Uint(value) => serializer.serialize_u64(*value),
+            Value::Float(value) => serializer.serialize_f64(*value),
+            Value::String(value) => serializer.serialize_str(value),
+            Value::Datetime(value) => match value.parse::<toml::value::Datetime>() {
+                Ok(datetime) => datetime.serialize(serializer),
+                Err(_) => serializer.serialize_str(value),
+            },
+            Value::Array(items) | Value::Documents(items) => {
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(item)?;
+                }
+                seq.end()
+            }
+            Value::Object(entries) => {
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+/// Parses *text* as *format*. A YAML stream with several documents gives *Value::Documents*
+pub fn parse(format: DataFormat, text: &str) -> Result<Value, ParseError> {
+    match format {
+        DataFormat::Json => serde_json::from_str(text).map_err(|e| ParseError {
+            line: e.line(),
+            column: e.column(),
+            message: strip_location(&e.to_string()),
+        }),
+        DataFormat::Yaml => {
+            let yaml_error = |e: serde_yaml::Error| {
+                let location = e.location();
+                ParseError {
+                    line: location.as_ref().map_or(1, |l| l.line()),
+                    column: location.as_ref().map_or(1, |l| l.column()),
+                    message: strip_location(&e.to_string()),
+                }
+            };
+            let mut documents = serde_yaml::Deserializer::from_str(text)
+                .map(Value::deserialize)
+                .collect::<Result<Vec<Value>, _>>()
+                .map_err(yaml_error)?;
+            Ok(match documents.len() {
+                0 => Value::Null,
+                1 => documents.remove(0),
+                _ => Value::Documents(documents),
+            })
+        }
+        DataFormat::Toml => toml::from_str(text).map_err(|e| {
+            let offset = e.span().map_or(0, |span| span.start).min(text.len());
+            let (line, column) = line_column(text, offset);
+            ParseError {
+                line,
+                column,
+                message: e.message().to_string(),
+            }
+        }),
+    }
+}
+
+/// Prints the document in its own format, evenly indented. Only the first lines are kept
+pub fn pretty(format: DataFormat, value: &Value) -> String {
+    let printed = match (format, value) {
+        (DataFormat::Yaml, Value::Documents(documents)) => documents
+            .iter()
+            .map(|document| serde_yaml::to_string(document).map_err(|e| e.to_string()))
+            .collect::<Result<Vec<String>, String>>()
+            .map(|documents| documents.join("---\n")),
+        (DataFormat::Json, _) => serde_json::to_string_pretty(value).map_err(|e| e.to_string()),
+        (DataFormat::Yaml, _) => serde_yaml::to_string(value).map_err(|e| e.to_string()),
+        (DataFormat::Toml, _) => toml::to_string_pretty(value).map_err(|e| e.to_string()),
+    };
+    let printed = printed.unwrap_or_else(|e| e);
+    printed
+        .lines()
+        .take(PRETTY_LINES)
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+/// serde_json and serde_yaml end their messages with the location, which is shown apart
+fn strip_location(message: &str) -> String {
+    match message.rfind(" at line ") {
+        Some(idx) => message[..idx].to_string(),
+        None => message.to_string(),
+    }
+}
+
+/// Returns the line and column of the byte *offset* in *text*, both starting at 1
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = text.get(..offset).unwrap_or(text);
+    let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// Returns the field delimiter of a CSV or TSV file, told by the extension
+pub fn delimiter(path: &Path) -> Option<u8> {
+    let extension = path.extension()?.to_string_lossy().to_lowercase();
+    match extension.as_str() {
+        "csv" => Some(b','),
+        "tsv" | "tab" => Some(b'\t'),
+        _ => None,
+    }
+}
+
+/// The start of a CSV or TSV file, split into fields.
+#[derive(Debug)]
+pub struct TablePreview {
+    pub delimiter: u8,
+    /// The first row, taken as the column names
+    pub header: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+    /// Every row after the header, the ones that didn't fit in *rows* too
+    pub total: usize,
+    /// The most fields in a row
+    pub columns: usize,
+    /// The first row with a field count other than the header's
+    pub error: Option<ParseError>,
+}
+
+impl TablePreview {
+    pub fn get_name(&self) -> &'static str {
+        if self.delimiter == b'\t' {
+            "TSV"
+        } else {
+            "CSV"
+        }
+    }
+}
+
+/// Reads a table from *reader*, fields that aren't UTF-8 are decoded lossily. Rows with more or
+/// fewer fields than the header are kept, the first one gets reported as *error*
+pub fn table(reader: impl Read, delimiter: u8) -> Result<TablePreview, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(reader);
+    let mut preview = TablePreview {
+        delimiter,
+        header: Vec::new(),
+        rows: Vec::new(),
+        total: 0,
+        columns: 0,
+        error: None,
+    };
+
+    for (idx, record) in reader.byte_records().enumerate() {
+        let record = record.map_err(|e| e.to_string())?;
+        preview.columns = preview.columns.max(record.len());
+        if idx == 0 {
+            preview.header = fields(&record);
+            continue;
+        }
+        if record.len() != preview.header.len() && preview.error.is_none() {
+            preview.error = Some(ParseError {
+                line: record.position().map_or(idx + 1, |p| p.line() as usize),
+                column: record.len().min(preview.header.len()) + 1,
+                message: format!(
+                    "expected {} fields, found {}",
+                    preview.header.len(),
+                    record.len()
+                ),
+            });
+        }
+        if preview.rows.len() < TABLE_ROWS {
+            preview.rows.push(fields(&record));
+        }
+        preview.total += 1;
+    }
+    Ok(preview)
+}
+
+fn fields(record: &csv::ByteRecord) -> Vec<String> {
+    record
+        .iter()
+        .map(|field| String::from_utf8_lossy(field).into_owned())
+        .collect()
+}
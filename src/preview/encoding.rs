@@ -0,0 +1,255 @@
+use encoding_rs::{UTF_16BE, UTF_16LE, WINDOWS_1252};
+
+/// Text decoded from the start of a file.
+#[derive(Debug)]
+pub struct DecodedText {
+    pub text: String,
+    /// The name of the detected encoding, like UTF-8 or UTF-16LE
+    pub encoding: &'static str,
+    /// LF, CRLF, CR or Mixed. None if there is no line break at all
+    pub line_ending: Option<&'static str>,
+}
+
+/// Decodes the start of a file, *bytes* may end in the middle of a character. Returns None if
+/// the bytes don't look like text
+pub fn decode(bytes: &[u8]) -> Option<DecodedText> {
+    let (text, encoding) = if let Some(rest) = bytes.strip_prefix(b"\xef\xbb\xbf") {
+        (decode_utf8(rest)?, "UTF-8 BOM")
+    } else if let Some(rest) = bytes.strip_prefix(b"\xff\xfe") {
+        (decode_utf16(rest, true), "UTF-16LE")
+    } else if let Some(rest) = bytes.strip_prefix(b"\xfe\xff") {
+        (decode_utf16(rest, false), "UTF-16BE")
+    } else if let Some(little_endian) = utf16_without_bom(bytes) {
+        // Checked before UTF-8, the zero bytes would be valid UTF-8 too
+        let name = if little_endian {
+            "UTF-16LE"
+        } else {
+            "UTF-16BE"
+        };
+        (decode_utf16(bytes, little_endian), name)
+    } else if let Some(text) = decode_utf8(bytes) {
+        (text, "UTF-8")
+    } else if bytes.iter().any(|&byte| (0x80..0xa0).contains(&byte)) {
+        // Control characters in Latin-1, but punctuation like “ ” € in Windows-1252
+        let (text, _) = WINDOWS_1252.decode_without_bom_handling(bytes);
+        (text.into_owned(), "Windows-1252")
+    } else {
+        (bytes.iter().map(|&byte| byte as char).collect(), "Latin-1")
+    };
+
+    if !looks_like_text(&text) {
+        return None;
+    }
+    Some(DecodedText {
+        line_ending: line_ending(&text),
+        text,
+        encoding,
+    })
+}
+
+/// Decodes valid UTF-8, a character cut off at the end is dropped
+fn decode_utf8(bytes: &[u8]) -> Option<String> {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => Some(text.to_string()),
+        // error_len() is None when the bytes just stop in the middle of a character
+        Err(e) if e.error_len().is_none() => {
+            Some(String::from_utf8_lossy(&bytes[..e.valid_up_to()]).into_owned())
+        }
+        Err(_) => None,
+    }
+}
+
+/// Decodes UTF-16, without a half code unit or a lone high surrogate at the end
+fn decode_utf16(bytes: &[u8], little_endian: bool) -> String {
+    let mut len = bytes.len() & !1;
+    if len >= 2 {
+        let last = &bytes[len - 2..len];
+        let high = if little_endian { last[1] } else { last[0] };
+        if (0xd8..0xdc).contains(&high) {
+            len -= 2;
+        }
+    }
+    let encoding = if little_endian { UTF_16LE } else { UTF_16BE };
+    let (text, _) = encoding.decode_without_bom_handling(&bytes[..len]);
+    text.into_owned()
+}
+
+/// Guesses UTF-16 without a BOM from the zero bytes mostly ASCII text has in every other byte.
+/// Returns whether it is little endian
+fn utf16_without_bom(bytes: &[u8]) -> Option<bool> {
+    let pairs = bytes.len() / 2;
+    if pairs < 2 {
+        return None;
+    }
+    let zeros = |offset: usize| {
+        bytes
+            .iter()
+            .skip(offset)
+            .step_by(2)
+            .filter(|&&b| b == 0)
+            .count()
+    };
+    let (even, odd) = (zeros(0), zeros(1));
+    if odd * 10 >= pairs * 9 && even * 10 < pairs {
+        Some(true)
+    } else if even * 10 >= pairs * 9 && odd * 10 < pairs {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Text has no NUL characters and only a few other control characters
+fn looks_like_text(text: &str) -> bool {
+    let mut chars = 0;
+    let mut control = 0;
+    for c in text.chars() {
+        if c == '\0' {
+            return false;
+        }
+        // Tabs, line breaks, form feeds and escape sequences are common in text files
+        if c.is_control() && !matches!(c, '\t' | '\n' | '\r' | '\x0c' | '\x1b') {
+            control += 1;
+        }
+        chars += 1;
+    }
+    control * 20 <= chars
+}
+
+fn line_ending(text: &str) -> Option<&'static str> {
+    let crlf = text.matches("\r\n").count();
+    let lf = text.matches('\n').count() - crlf;
+    let cr = text.matches('\r').count() - crlf;
+    match (crlf > 0, lf > 0, cr > 0) {
+        (false, false, false) => None,
+        (true, false, false) => Some("CRLF"),
+        (false, true, false) => Some("LF"),
+        (false, false, true) => Some("CR"),
+        _ => Some("Mixed"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16(text: &str, little_endian: bool) -> Vec<u8> {
+        text.encode_utf16()
+            .flat_map(|unit| {
+                if little_endian {
+                    unit.to_le_bytes()
+                } else {
+                    unit.to_be_bytes()
+                }
+            })
+            .collect()
+    }
+
+    /// The decoded text and the encoding name
+    fn decoded(bytes: &[u8]) -> (String, &'static str) {
+        let decoded = decode(bytes).unwrap();
+        (decoded.text, decoded.encoding)
+    }
+
+    #[test]
+    fn utf8() {
+        assert_eq!(
+            decoded("héllo 日本\n".as_bytes()),
+            ("héllo 日本\n".to_string(), "UTF-8")
+        );
+        assert_eq!(decoded(b""), (String::new(), "UTF-8"));
+    }
+
+    #[test]
+    fn utf8_bom() {
+        let bytes = [b"\xef\xbb\xbf".as_slice(), "héllo".as_bytes()].concat();
+        assert_eq!(decoded(&bytes), ("héllo".to_string(), "UTF-8 BOM"));
+    }
+
+    #[test]
+    fn utf16_boms() {
+        let bytes = [b"\xff\xfe".to_vec(), utf16("héllo 日本", true)].concat();
+        assert_eq!(decoded(&bytes), ("héllo 日本".to_string(), "UTF-16LE"));
+        let bytes = [b"\xfe\xff".to_vec(), utf16("héllo 日本", false)].concat();
+        assert_eq!(decoded(&bytes), ("héllo 日本".to_string(), "UTF-16BE"));
+    }
+
+    #[test]
+    fn utf16_without_a_bom() {
+        let text = "plain ascii text\n";
+        assert_eq!(utf16_without_bom(&utf16(text, true)), Some(true));
+        assert_eq!(utf16_without_bom(&utf16(text, false)), Some(false));
+        assert_eq!(decoded(&utf16(text, true)), (text.to_string(), "UTF-16LE"));
+        assert_eq!(decoded(&utf16(text, false)), (text.to_string(), "UTF-16BE"));
+        // Too short to tell, or not enough zeros
+        assert_eq!(utf16_without_bom(b"a\0"), None);
+        assert_eq!(utf16_without_bom(b"plain ascii"), None);
+        assert_eq!(utf16_without_bom(&utf16("日本語のテキスト", true)), None);
+    }
+
+    #[test]
+    fn utf16_cut_off() {
+        // A half code unit at the end
+        let mut bytes = utf16("ab", true);
+        bytes.push(b'c');
+        assert_eq!(decode_utf16(&bytes, true), "ab");
+        // A high surrogate without the low one that would follow
+        let emoji = utf16("ab😀", true);
+        assert_eq!(decode_utf16(&emoji[..emoji.len() - 2], true), "ab");
+        let emoji = utf16("ab😀", false);
+        assert_eq!(decode_utf16(&emoji[..emoji.len() - 2], false), "ab");
+        assert_eq!(decode_utf16(&emoji, false), "ab😀");
+        assert_eq!(decode_utf16(b"", true), "");
+    }
+
+    #[test]
+    fn utf8_cut_off() {
+        let bytes = "ab日本".as_bytes();
+        assert_eq!(
+            decode_utf8(&bytes[..bytes.len() - 1]),
+            Some("ab日".to_string())
+        );
+        assert_eq!(
+            decode_utf8(&bytes[..bytes.len() - 2]),
+            Some("ab日".to_string())
+        );
+        // An invalid byte in the middle isn't UTF-8
+        assert_eq!(decode_utf8(b"caf\xe9 ok"), None);
+    }
+
+    #[test]
+    fn single_byte_encodings() {
+        assert_eq!(
+            decoded(b"caf\xe9 cr\xe8me"),
+            ("café crème".to_string(), "Latin-1")
+        );
+        // 0x80 to 0x9f are punctuation in Windows-1252
+        assert_eq!(
+            decoded(b"\x93quoted\x94 costs 5\x80, caf\xe9"),
+            ("“quoted” costs 5€, café".to_string(), "Windows-1252")
+        );
+    }
+
+    #[test]
+    fn binary_is_not_text() {
+        assert!(decode(b"\x7fELF\x02\x01\x01\0\0\0").is_none());
+        assert!(!looks_like_text("a\0b"));
+        assert!(!looks_like_text("\x01\x02\x03 abc"));
+        assert!(looks_like_text("\ttabs\r\n\x1b[1mbold\x1b[0m\x0c"));
+        // One control char in twenty is still text
+        assert!(looks_like_text(&format!("\x01{}", "a".repeat(19))));
+        assert!(!looks_like_text(&format!("\x01{}", "a".repeat(18))));
+        assert!(looks_like_text(""));
+    }
+
+    #[test]
+    fn line_endings() {
+        assert_eq!(line_ending("no break"), None);
+        assert_eq!(line_ending("a\nb\n"), Some("LF"));
+        assert_eq!(line_ending("a\r\nb\r\n"), Some("CRLF"));
+        assert_eq!(line_ending("a\rb\r"), Some("CR"));
+        assert_eq!(line_ending("a\r\nb\n"), Some("Mixed"));
+        assert_eq!(line_ending("a\rb\n"), Some("Mixed"));
+        assert_eq!(decode(b"a\r\nb").unwrap().line_ending, Some("CRLF"));
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use image::RgbaImage;
+use image::imageops::FilterType;
+
+use super::Preview;
+use super::picture::{Rgb, fit};
+
+/// Deletes every image kitty shows
+pub const KITTY_CLEAR: &str = "\x1b_Ga=d,q=2\x1b\\";
+
+/// How images get drawn into the terminal.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Protocol {
+    /// Colored ▀ characters, works everywhere
+    HalfBlocks,
+    /// The kitty graphics protocol (kitty, WezTerm, Ghostty)
+    Kitty,
+    Sixel,
+}
+
+impl Protocol {
+    pub const NAMES: &[&str] = &["halfblocks", "kitty", "sixel"];
+
+    /// Picks kitty in terminals known to support it. Sixel support can't be told from the
+    /// environment, it has to be picked with :graphics sixel
+    pub fn detect() -> Self {
+        let term = std::env::var("TERM").unwrap_or_default();
+        let program = std::env::var("TERM_PROGRAM").unwrap_or_default();
+        if std::env::var("KITTY_WINDOW_ID").is_ok()
+            || term.contains("kitty")
+            || matches!(program.as_str(), "WezTerm" | "ghostty")
+        {
+            Protocol::Kitty
+        } else {
+            Protocol::HalfBlocks
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "halfblocks" => Some(Protocol::HalfBlocks),
+            "kitty" => Some(Protocol::Kitty),
+            "sixel" => Some(Protocol::Sixel),
+            _ => None,
+        }
+    }
+}
+
+/// An image to encode: the preview, the size in cells, the protocol and the background
+pub type Request = (Arc<Preview>, u16, u16, Protocol, Rgb);
+
+/// The last encoded image with its escape sequence
+type Encoded = Arc<Mutex<Option<(Request, Arc<String>)>>>;
+
+fn same_request(a: &Request, b: &Request) -> bool {
+    Arc::ptr_eq(&a.0, &b.0) && (a.1, a.2, a.3, a.4) == (b.1, b.2, b.3, b.4)
+}
+
+/// Encodes images for kitty and sixel on a worker thread, scaling and quantising are too slow
+/// for the render path. The last encoded image is kept, it is drawn on every change of focus.
+pub struct Encoder {
+    done: Encoded,
+    /// The request sent last, so it isn't sent again every frame
+    requested: Option<Request>,
+    sender: Sender<Request>,
+}
+
+impl Default for Encoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        let done = Arc::new(Mutex::new(None));
+        let (sender, receiver) = mpsc::channel();
+        let worker_done = Arc::clone(&done);
+        std::thread::spawn(move || Encoder::work(receiver, worker_done));
+
+        Self {
+            done,
+            requested: None,
+            sender,
+        }
+    }
+
+    /// Returns the escape sequence for *request* if it is ready, otherwise asks the worker for it
+    pub fn get(&mut self, request: Request) -> Option<Arc<String>> {
+        if let Some((done, sequence)) = self.done.lock().unwrap().as_ref()
+            && same_request(done, &request)
+        {
+            return Some(Arc::clone(sequence));
+        }
+        if !self
+            .requested
+            .as_ref()
+            .is_some_and(|requested| same_request(requested, &request))
+        {
+            self.requested = Some(request.clone());
+            _ = self.sender.send(request);
+        }
+        None
+    }
+
+    /// Encodes the requested images, only the newest of the requests that piled up
+    fn work(receiver: Receiver<Request>, done: Encoded) {
+        while let Ok(mut request) = receiver.recv() {
+            while let Ok(newer) = receiver.try_recv() {
+                request = newer;
+            }
+            let (preview, cols, rows, protocol, background) = &request;
+            let Preview::Image(image) = preview.as_ref() else {
+                continue;
+            };
+            let cell = cell_size();
+            let sequence = match protocol {
+                Protocol::Kitty => kitty(&image.thumbnail, *cols, *rows, cell),
+                Protocol::Sixel => sixel(&image.thumbnail, *cols, *rows, cell, *background),
+                Protocol::HalfBlocks => continue,
+            };
+            *done.lock().unwrap() = Some((request, Arc::new(sequence)));
+        }
+    }
+}
+
+/// Returns the size of a terminal cell in pixels, 8x16 if the terminal doesn't tell
+pub fn cell_size() -> (u32, u32) {
+    match crossterm::terminal::window_size() {
+        Ok(size) if size.width > 0 && size.columns > 0 && size.rows > 0 => (
+            (size.width / size.columns).max(1) as u32,
+            (size.height / size.rows).max(1) as u32,
+        ),
+        _ => (8, 16),
+    }
+}
+
+/// Returns the pixel size the image gets drawn at inside *cols* x *rows* cells of *cell* pixels
+fn pixel_size(image: &RgbaImage, cols: u16, rows: u16, cell: (u32, u32)) -> (u32, u32) {
+    let (cell_width, cell_height) = cell;
+    fit(
+        image.width(),
+        image.height(),
+        cols as u32 * cell_width,
+        rows as u32 * cell_height,
+    )
+}
+
+/// The escape sequence that draws *image* at the cursor with the kitty graphics protocol,
+/// inside *cols* x *rows* cells of *cell* pixels. The pixels are sent raw, in chunks of 4096
+/// base64 bytes
+pub fn kitty(image: &RgbaImage, cols: u16, rows: u16, cell: (u32, u32)) -> String {
+    let (width, height) = pixel_size(image, cols, rows, cell);
+    let (cell_width, cell_height) = cell;
+    let data = STANDARD.encode(image.as_raw());
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(4096).collect();
+
+    let mut sequence = String::new();
+    for (idx, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(idx + 1 < chunks.len());
+        let chunk = std::str::from_utf8(chunk).unwrap_or_default();
+        if idx == 0 {
+            sequence.push_str(&format!(
+                "\x1b_Ga=T,f=32,s={},v={},c={},r={},C=1,q=2,m={more};{chunk}\x1b\\",
+                image.width(),
+                image.height(),
+                width.div_ceil(cell_width),
+                height.div_ceil(cell_height),
+            ));
+        } else {
+            sequence.push_str(&format!("\x1b_Gm={more};{chunk}\x1b\\"));
+        }
+    }
+    sequence
+}
+
+/// The escape sequence that draws *image* at the cursor as sixels, inside *cols* x *rows*
+/// cells of *cell* pixels. The colors get reduced to a 256 color palette, transparency is
+/// blended onto *background*
+pub fn sixel(image: &RgbaImage, cols: u16, rows: u16, cell: (u32, u32), background: Rgb) -> String {
+    let (width, height) = pixel_size(image, cols, rows, cell);
+    let mut scaled = image::imageops::resize(image, width, height, FilterType::Triangle);
+    for pixel in scaled.pixels_mut() {
+        let [r, g, b, a] = pixel.0;
+        let blend = |channel: u8, bg: u8| {
+            ((channel as u32 * a as u32 + bg as u32 * (255 - a as u32)) / 255) as u8
+        };
+        pixel.0 = [
+            blend(r, background.0),
+            blend(g, background.1),
+            blend(b, background.2),
+            255,
+        ];
+    }
+
+    let quantizer = color_quant::NeuQuant::new(10, 256, scaled.as_raw());
+    let indices: Vec<usize> = scaled
+        .pixels()
+        .map(|pixel| quantizer.index_of(&pixel.0))
+        .collect();
+
+    let mut sequence = format!("\x1bPq\"1;1;{width};{height}");
+    for (idx, color) in quantizer.color_map_rgb().chunks(3).enumerate() {
+        let percent = |channel: u8| channel as u32 * 100 / 255;
+        sequence.push_str(&format!(
+            "#{idx};2;{};{};{}",
+            percent(color[0]),
+            percent(color[1]),
+            percent(color[2])
+        ));
+    }
+
+    // Sixels are bands of 6 pixel rows, every color of a band is drawn in its own pass
+    for band in 0..height.div_ceil(6) {
+        let mut columns: HashMap<usize, Vec<u8>> = HashMap::new();
+        for dy in 0..6 {
+            let y = band * 6 + dy;
+            if y >= height {
+                break;
+            }
+            for x in 0..width {
+                let color = indices[(y * width + x) as usize];
+                columns
+                    .entry(color)
+                    .or_insert_with(|| vec![0; width as usize])[x as usize] |= 1 << dy;
+            }
+        }
+        for (color, bits) in columns {
+            sequence.push_str(&format!("#{color}"));
+            push_run_length(&mut sequence, &bits);
+            sequence.push('$');
+        }
+        sequence.push('-');
+    }
+    sequence.push_str("\x1b\\");
+    sequence
+}
+
+/// Adds a row of sixels, repeats of the same sixel are written as !{count}{sixel}
+fn push_run_length(sequence: &mut String, bits: &[u8]) {
+    let mut idx = 0;
+    while idx < bits.len() {
+        let run = bits[idx..].iter().take_while(|&&b| b == bits[idx]).count();
+        let sixel = (63 + bits[idx]) as char;
+        if run > 3 {
+            sequence.push_str(&format!("!{run}{sixel}"));
+        } else {
+            sequence.extend(std::iter::repeat_n(sixel, run));
+        }
+        idx += run;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn push_run_length_compresses_long_runs() {
+        let mut sequence = String::new();
+        push_run_length(&mut sequence, &[0, 0, 0, 0, 0]);
+        assert_eq!(sequence, "!5?");
+    }
+
+    #[test]
+    fn push_run_length_keeps_short_runs() {
+        let mut sequence = String::new();
+        push_run_length(&mut sequence, &[1, 1, 1, 63, 2]);
+        assert_eq!(sequence, "@@@~A");
+    }
+
+    #[test]
+    fn push_run_length_mixes_runs() {
+        let mut sequence = String::new();
+        push_run_length(&mut sequence, &[63, 63, 63, 63, 0, 1, 1, 1, 1, 1, 1]);
+        assert_eq!(sequence, "!4~?!6@");
+    }
+
+    #[test]
+    fn kitty_sizes_the_image_in_cells() {
+        let image = RgbaImage::from_pixel(20, 10, Rgba([0, 0, 0, 255]));
+        let sequence = kitty(&image, 10, 10, (8, 16));
+        assert!(sequence.starts_with("\x1b_Ga=T,f=32,s=20,v=10,c=3,r=1,C=1,q=2,m=0;"));
+        assert!(sequence.ends_with("\x1b\\"));
+    }
+
+    #[test]
+    fn kitty_sends_chunks() {
+        let image = RgbaImage::from_pixel(64, 64, Rgba([0, 0, 0, 255]));
+        let sequence = kitty(&image, 100, 100, (8, 16));
+        // 64 * 64 * 4 bytes are 21848 base64 bytes, 6 chunks
+        assert_eq!(sequence.matches("\x1b_G").count(), 6);
+        assert_eq!(sequence.matches("m=1;").count(), 5);
+        assert!(sequence.rfind("\x1b_Gm=0;") > sequence.rfind("m=1;"));
+    }
+
+    #[test]
+    fn sixel_scales_into_the_cells() {
+        let image = RgbaImage::from_pixel(100, 100, Rgba([255, 0, 0, 255]));
+        let sequence = sixel(&image, 2, 2, (10, 20), (0, 0, 0));
+        assert!(sequence.starts_with("\x1bPq\"1;1;20;20"));
+        assert!(sequence.ends_with("-\x1b\\"));
+        // 20 rows are 4 bands of 6
+        assert_eq!(sequence.matches('-').count(), 4);
+    }
+}
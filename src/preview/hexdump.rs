@@ -0,0 +1,103 @@
+/// How much of a binary file gets dumped
+pub const HEX_LIMIT: usize = 64 * 1024;
+
+/// Known file headers: offset, magic bytes and the name of the format
+const MAGIC: &[(usize, &[u8], &str)] = &[
+    (0, b"\x7fELF", "ELF"),
+    (0, b"\x89PNG\r\n\x1a\n", "PNG image"),
+    (0, b"\xff\xd8\xff", "JPEG image"),
+    (0, b"GIF87a", "GIF image"),
+    (0, b"GIF89a", "GIF image"),
+    (0, b"BM", "BMP image"),
+    (0, b"II*\0", "TIFF image"),
+    (0, b"MM\0*", "TIFF image"),
+    (0, b"%PDF-", "PDF document"),
+    (0, b"PK\x03\x04", "ZIP archive"),
+    (0, b"PK\x05\x06", "ZIP archive (empty)"),
+    (0, b"\x1f\x8b", "gzip"),
+    (0, b"BZh", "bzip2"),
+    (0, b"\xfd7zXZ\0", "xz"),
+    (0, b"\x28\xb5\x2f\xfd", "zstd"),
+    (0, b"7z\xbc\xaf\x27\x1c", "7z archive"),
+    (0, b"Rar!\x1a\x07", "RAR archive"),
+    (257, b"ustar", "tar archive"),
+    (0, b"SQLite format 3\0", "SQLite database"),
+    (0, b"\0asm", "WebAssembly"),
+    (0, b"MZ", "PE / DOS executable"),
+    (0, b"\xfe\xed\xfa\xce", "Mach-O"),
+    (0, b"\xfe\xed\xfa\xcf", "Mach-O"),
+    (0, b"\xcf\xfa\xed\xfe", "Mach-O"),
+    (0, b"\xca\xfe\xba\xbe", "Java class / Mach-O fat"),
+    (0, b"ID3", "MP3 audio"),
+    (0, b"OggS", "Ogg"),
+    (0, b"fLaC", "FLAC audio"),
+    (0, b"\x1aE\xdf\xa3", "Matroska / WebM"),
+    (4, b"ftyp", "MP4 / QuickTime"),
+    (0, b"wOFF", "WOFF font"),
+    (0, b"wOF2", "WOFF2 font"),
+    (0, b"\0\x01\0\0\0", "TrueType font"),
+    (0, b"OTTO", "OpenType font"),
+    (0, b"!<arch>\n", "ar archive"),
+];
+
+/// Names the format of a file from its first bytes
+pub fn sniff(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"RIFF") && bytes.len() >= 12 {
+        return match &bytes[8..12] {
+            b"WAVE" => Some("WAV audio"),
+            b"WEBP" => Some("WebP image"),
+            b"AVI " => Some("AVI video"),
+            _ => Some("RIFF"),
+        };
+    }
+    // An MPEG Layer III frame header, for MP3 files without an ID3 tag
+    if bytes.len() >= 2 && bytes[0] == 0xff && bytes[1] & 0xe6 == 0xe2 {
+        return Some("MP3 audio");
+    }
+    MAGIC
+        .iter()
+        .find(|(offset, magic, _)| bytes.get(*offset..offset + magic.len()) == Some(magic))
+        .map(|(_, _, name)| *name)
+}
+
+/// How a byte is shown in the dump, each class gets its own color.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ByteClass {
+    Null,
+    Printable,
+    Whitespace,
+    Control,
+    /// Not ASCII
+    High,
+}
+
+pub fn classify(byte: u8) -> ByteClass {
+    match byte {
+        0 => ByteClass::Null,
+        b' ' | b'\t' | b'\n' | b'\r' => ByteClass::Whitespace,
+        _ if byte.is_ascii_graphic() => ByteClass::Printable,
+        _ if byte.is_ascii() => ByteClass::Control,
+        _ => ByteClass::High,
+    }
+}
+
+/// The character shown for a byte in the ASCII gutter
+pub fn gutter_char(byte: u8) -> char {
+    match classify(byte) {
+        ByteClass::Printable => byte as char,
+        ByteClass::Whitespace if byte == b' ' => ' ',
+        ByteClass::Null => '⋄',
+        ByteClass::Whitespace => '_',
+        ByteClass::Control => '•',
+        ByteClass::High => '×',
+    }
+}
+
+/// How many bytes fit in a row of *width* columns: the 8 digit offset, 3 columns per byte in
+/// hex and 1 in the gutter. Rows hold 4, 8 or 16 bytes
+pub fn bytes_per_row(width: u16) -> usize {
+    [16, 8, 4]
+        .into_iter()
+        .find(|&n| 10 + n * 4 + n / 8 <= width as usize)
+        .unwrap_or(4)
+}
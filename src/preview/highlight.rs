@@ -0,0 +1,153 @@
+use std::path::Path;
+
+use syntect::parsing::{ParseState, Scope, ScopeStack, SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+
+/// What a piece of highlighted text is, the UI picks a theme color for each kind.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Token {
+    Plain,
+    Keyword,
+    String,
+    Comment,
+    /// Numbers and language constants like true or None
+    Constant,
+    Function,
+    Type,
+}
+
+/// Scope prefixes and the token they turn into. The innermost scope that matches one wins
+const KINDS: &[(&str, Token)] = &[
+    ("comment", Token::Comment),
+    ("string", Token::String),
+    ("constant", Token::Constant),
+    ("keyword", Token::Keyword),
+    ("storage", Token::Keyword),
+    ("entity.name.function", Token::Function),
+    ("support.function", Token::Function),
+    ("variable.function", Token::Function),
+    ("entity.name", Token::Type),
+    ("support.type", Token::Type),
+    ("support.class", Token::Type),
+];
+
+/// A highlighted line, split into pieces of the same kind
+pub type HighlightedLine = Vec<(Token, String)>;
+
+/// Splits text into tokens with the syntaxes bundled with syntect.
+pub struct Highlighter {
+    syntaxes: SyntaxSet,
+    kinds: Vec<(Scope, Token)>,
+}
+
+impl Default for Highlighter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Highlighter {
+    pub fn new() -> Self {
+        Self {
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            kinds: KINDS
+                .iter()
+                .filter_map(|(scope, token)| Scope::new(scope).ok().map(|scope| (scope, *token)))
+                .collect(),
+        }
+    }
+
+    /// Picks the syntax by the file name or extension, then by the first line (shebangs, modelines)
+    pub fn find_syntax(&self, path: &Path, text: &str) -> Option<&SyntaxReference> {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let ext = path.extension().unwrap_or_default().to_string_lossy();
+        self.syntaxes
+            .find_syntax_by_extension(&name)
+            .or_else(|| self.syntaxes.find_syntax_by_extension(&ext))
+            .or_else(|| {
+                let first_line = text.lines().next().unwrap_or_default();
+                self.syntaxes.find_syntax_by_first_line(first_line)
+            })
+    }
+
+    /// Returns the name of the syntax used and the highlighted lines. Text without a known
+    /// syntax comes back as plain lines
+    pub fn highlight(&self, path: &Path, text: &str) -> (Option<String>, Vec<HighlightedLine>) {
+        let Some(syntax) = self.find_syntax(path, text) else {
+            return (None, plain_lines(text));
+        };
+        (Some(syntax.name.clone()), self.highlight_with(syntax, text))
+    }
+
+    /// Highlights a code block by its language name, like rust or py. Unknown languages come
+    /// back as plain lines
+    pub fn highlight_code(&self, language: &str, text: &str) -> Vec<HighlightedLine> {
+        match self.syntaxes.find_syntax_by_token(language) {
+            Some(syntax) if !language.is_empty() => self.highlight_with(syntax, text),
+            _ => plain_lines(text),
+        }
+    }
+
+    fn highlight_with(&self, syntax: &SyntaxReference, text: &str) -> Vec<HighlightedLine> {
+        let mut state = ParseState::new(syntax);
+        let mut stack = ScopeStack::new();
+        let mut lines = Vec::new();
+        let mut offset = 0;
+        for line in LinesWithEndings::from(text) {
+            let Ok(ops) = state.parse_line(line, &self.syntaxes) else {
+                // The grammar gave up, the rest stays plain
+                lines.extend(plain_lines(&text[offset..]));
+                break;
+            };
+            offset += line.len();
+
+            let mut tokens = Vec::new();
+            let mut start = 0;
+            for (idx, op) in &ops {
+                push_token(&mut tokens, self.classify(&stack), &line[start..*idx]);
+                start = *idx;
+                _ = stack.apply(op);
+            }
+            push_token(&mut tokens, self.classify(&stack), &line[start..]);
+            lines.push(tokens);
+        }
+        lines
+    }
+
+    fn classify(&self, stack: &ScopeStack) -> Token {
+        stack
+            .as_slice()
+            .iter()
+            .rev()
+            .find_map(|scope| {
+                self.kinds
+                    .iter()
+                    .find(|(prefix, _)| prefix.is_prefix_of(*scope))
+                    .map(|(_, token)| *token)
+            })
+            .unwrap_or(Token::Plain)
+    }
+}
+
+/// Adds a piece to the line, merged into the last one if it is of the same kind. Line endings
+/// are dropped and tabs turned into spaces, the terminal would draw them wrong
+fn push_token(tokens: &mut HighlightedLine, token: Token, piece: &str) {
+    let piece = piece.trim_end_matches(['\n', '\r']).replace('\t', "    ");
+    if piece.is_empty() {
+        return;
+    }
+    match tokens.last_mut() {
+        Some((last, text)) if *last == token => text.push_str(&piece),
+        _ => tokens.push((token, piece)),
+    }
+}
+
+fn plain_lines(text: &str) -> Vec<HighlightedLine> {
+    text.lines()
+        .map(|line| {
+            let mut tokens = Vec::new();
+            push_token(&mut tokens, Token::Plain, line);
+            tokens
+        })
+        .collect()
+}
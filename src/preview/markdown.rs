@@ -0,0 +1,431 @@
+use std::path::Path;
+
+use pulldown_cmark::{Alignment, CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+
+use super::highlight::{Highlighter, Token};
+
+/// The largest Markdown file that gets rendered, in bytes. Bigger files are previewed as text
+pub const MARKDOWN_LIMIT: usize = 1024 * 1024;
+
+/// How many lines of the source are kept for the raw view
+pub const SOURCE_LINES: usize = 1000;
+
+/// How wide a thematic break (---) is drawn, in chars
+const RULE_WIDTH: usize = 40;
+
+/// What a piece of rendered Markdown is, the UI picks a theme color for each kind.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MdKind {
+    Text,
+    /// A heading, with its level from 1 to 6
+    Heading(u8),
+    /// `Inline code`
+    Code,
+    /// Text in a code block, highlighted by the language of the block
+    Block(Token),
+    Link,
+    /// List bullets, quote bars, table borders, rules and raw HTML
+    Marker,
+}
+
+/// How a piece of rendered Markdown looks.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct MdStyle {
+    pub kind: MdKind,
+    pub bold: bool,
+    pub italic: bool,
+    pub strikethrough: bool,
+}
+
+impl MdStyle {
+    fn plain(kind: MdKind) -> Self {
+        Self {
+            kind,
+            bold: false,
+            italic: false,
+            strikethrough: false,
+        }
+    }
+}
+
+/// A rendered line, split into pieces of the same style
+pub type MdLine = Vec<(MdStyle, String)>;
+
+/// Returns true for Markdown files, judged by their extension
+pub fn is_markdown(path: &Path) -> bool {
+    let extension = path.extension().unwrap_or_default().to_string_lossy();
+    matches!(
+        extension.to_lowercase().as_str(),
+        "md" | "markdown" | "mdown" | "mkd"
+    )
+}
+
+/// Lays out a Markdown document as styled lines: headings, emphasis, lists, block quotes, code
+/// blocks highlighted by their language and tables with aligned columns. Paragraphs come as one
+/// long line each, the preview wraps them
+pub fn render(text: &str, highlighter: &Highlighter) -> Vec<MdLine> {
+    let options =
+        Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let mut renderer = Renderer::new(highlighter);
+    for event in Parser::new_ext(text, options) {
+        renderer.event(event);
+    }
+    renderer.finish()
+}
+
+/// A table being read, cells as plain text
+struct Table {
+    alignments: Vec<Alignment>,
+    rows: Vec<Vec<String>>,
+    /// The header rows, the first ones
+    head: usize,
+}
+
+struct Renderer<'a> {
+    highlighter: &'a Highlighter,
+    lines: Vec<MdLine>,
+    line: MdLine,
+    /// A list item marker was written and nothing after it yet
+    marker_pending: bool,
+    /// The last line is the empty one between blocks
+    spacer: bool,
+    strong: usize,
+    emphasis: usize,
+    strikethrough: usize,
+    links: usize,
+    heading: Option<u8>,
+    quotes: usize,
+    /// The next number of each open list, None for bullet lists
+    lists: Vec<Option<u64>>,
+    /// The language and the text of the code block being read
+    code: Option<(String, String)>,
+    table: Option<Table>,
+}
+
+impl<'a> Renderer<'a> {
+    fn new(highlighter: &'a Highlighter) -> Self {
+        Self {
+            highlighter,
+            lines: Vec::new(),
+            line: Vec::new(),
+            marker_pending: false,
+            spacer: false,
+            strong: 0,
+            emphasis: 0,
+            strikethrough: 0,
+            links: 0,
+            heading: None,
+            quotes: 0,
+            lists: Vec::new(),
+            code: None,
+            table: None,
+        }
+    }
+
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => {
+                if let Some((_, code)) = &mut self.code {
+                    code.push_str(&text);
+                } else if !self.push_cell(&text) {
+                    self.push(self.style(), &text);
+                }
+            }
+            Event::Code(text) | Event::InlineMath(text) | Event::DisplayMath(text) => {
+                if !self.push_cell(&text) {
+                    let mut style = self.style();
+                    style.kind = MdKind::Code;
+                    self.push(style, &text);
+                }
+            }
+            Event::Html(html) => {
+                for line in html.lines() {
+                    self.push(MdStyle::plain(MdKind::Marker), line);
+                    self.flush();
+                }
+            }
+            Event::InlineHtml(html) => self.push(MdStyle::plain(MdKind::Marker), &html),
+            Event::FootnoteReference(name) => {
+                self.push(MdStyle::plain(MdKind::Link), &format!("[^{name}]"))
+            }
+            Event::SoftBreak => {
+                if !self.push_cell(" ") {
+                    self.push(self.style(), " ");
+                }
+            }
+            Event::HardBreak => self.flush(),
+            Event::Rule => {
+                self.flush();
+                self.push(MdStyle::plain(MdKind::Marker), &"─".repeat(RULE_WIDTH));
+                self.blank();
+            }
+            Event::TaskListMarker(checked) => {
+                let marker = if checked { "☑ " } else { "☐ " };
+                self.push(MdStyle::plain(MdKind::Marker), marker);
+            }
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph if !self.marker_pending => self.flush(),
+            Tag::Heading { level, .. } => {
+                self.flush();
+                self.heading = Some(level as u8);
+            }
+            Tag::BlockQuote(_) => {
+                self.flush();
+                self.quotes += 1;
+            }
+            Tag::CodeBlock(kind) => {
+                self.flush();
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => info
+                        .split(|c: char| c == ',' || c.is_whitespace())
+                        .next()
+                        .unwrap_or_default()
+                        .to_string(),
+                    CodeBlockKind::Indented => String::new(),
+                };
+                self.code = Some((language, String::new()));
+            }
+            Tag::List(start) => {
+                self.flush();
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                self.flush();
+                self.push_quotes();
+                let depth = self.lists.len().saturating_sub(1);
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}{}. ", "  ".repeat(depth), *number - 1)
+                    }
+                    _ => format!("{}• ", "  ".repeat(depth)),
+                };
+                // Pushed as is, push() would indent it like the lines after it
+                self.line.push((MdStyle::plain(MdKind::Marker), marker));
+                self.marker_pending = true;
+            }
+            Tag::Emphasis => self.emphasis += 1,
+            Tag::Strong => self.strong += 1,
+            Tag::Strikethrough => self.strikethrough += 1,
+            Tag::Link { .. } => self.links += 1,
+            Tag::Image { .. } => self.push(MdStyle::plain(MdKind::Marker), "▣ "),
+            Tag::Table(alignments) => {
+                self.flush();
+                self.table = Some(Table {
+                    alignments,
+                    rows: Vec::new(),
+                    head: 0,
+                });
+            }
+            Tag::TableHead | Tag::TableRow => {
+                if let Some(table) = &mut self.table {
+                    table.rows.push(Vec::new());
+                }
+            }
+            Tag::TableCell => {
+                if let Some(row) = self.table.as_mut().and_then(|table| table.rows.last_mut()) {
+                    row.push(String::new());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph => {
+                self.flush();
+                if self.lists.is_empty() {
+                    self.blank();
+                }
+            }
+            TagEnd::Heading(_) => {
+                self.flush();
+                self.heading = None;
+                self.blank();
+            }
+            TagEnd::BlockQuote(_) => {
+                self.flush();
+                self.quotes = self.quotes.saturating_sub(1);
+                if self.quotes == 0 {
+                    self.blank();
+                }
+            }
+            TagEnd::CodeBlock => {
+                let Some((language, code)) = self.code.take() else {
+                    return;
+                };
+                for tokens in self.highlighter.highlight_code(&language, &code) {
+                    self.push(MdStyle::plain(MdKind::Marker), "  ");
+                    for (token, piece) in tokens {
+                        self.push(MdStyle::plain(MdKind::Block(token)), &piece);
+                    }
+                    self.flush();
+                }
+                if self.lists.is_empty() {
+                    self.blank();
+                }
+            }
+            TagEnd::List(_) => {
+                self.flush();
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.blank();
+                }
+            }
+            TagEnd::Item => self.flush(),
+            TagEnd::HtmlBlock => self.blank(),
+            TagEnd::Emphasis => self.emphasis = self.emphasis.saturating_sub(1),
+            TagEnd::Strong => self.strong = self.strong.saturating_sub(1),
+            TagEnd::Strikethrough => self.strikethrough = self.strikethrough.saturating_sub(1),
+            TagEnd::Link => self.links = self.links.saturating_sub(1),
+            TagEnd::TableHead => {
+                if let Some(table) = &mut self.table {
+                    table.head = table.rows.len();
+                }
+            }
+            TagEnd::Table => {
+                if let Some(table) = self.table.take() {
+                    self.push_table(table);
+                    self.blank();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The style of text at this point of the document
+    fn style(&self) -> MdStyle {
+        let kind = match self.heading {
+            Some(level) => MdKind::Heading(level),
+            None if self.links > 0 => MdKind::Link,
+            None => MdKind::Text,
+        };
+        MdStyle {
+            kind,
+            bold: self.strong > 0 || self.heading.is_some(),
+            italic: self.emphasis > 0,
+            strikethrough: self.strikethrough > 0,
+        }
+    }
+
+    /// Adds text to the table cell being read. False if there is none
+    fn push_cell(&mut self, text: &str) -> bool {
+        let cell = self
+            .table
+            .as_mut()
+            .and_then(|table| table.rows.last_mut())
+            .and_then(|row| row.last_mut());
+        match cell {
+            Some(cell) => {
+                cell.push_str(text);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Adds a piece to the line, after the quote bars and list indentation if it is a new line
+    fn push(&mut self, style: MdStyle, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        if self.line.is_empty() {
+            self.push_quotes();
+            if !self.lists.is_empty() {
+                let indent = "  ".repeat(self.lists.len());
+                self.line.push((MdStyle::plain(MdKind::Text), indent));
+            }
+        }
+        self.marker_pending = false;
+        match self.line.last_mut() {
+            Some((last, piece)) if *last == style => piece.push_str(text),
+            _ => self.line.push((style, text.to_string())),
+        }
+    }
+
+    fn push_quotes(&mut self) {
+        if self.quotes > 0 {
+            let bars = "│ ".repeat(self.quotes);
+            self.line.push((MdStyle::plain(MdKind::Marker), bars));
+        }
+    }
+
+    /// Lays out a table with its columns padded to the widest cell, the header set apart by a
+    /// line
+    fn push_table(&mut self, table: Table) {
+        let columns = table.rows.iter().map(Vec::len).max().unwrap_or(0);
+        let widths: Vec<usize> = (0..columns)
+            .map(|column| {
+                table
+                    .rows
+                    .iter()
+                    .filter_map(|row| row.get(column))
+                    .map(|cell| cell.chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let border = MdStyle::plain(MdKind::Marker);
+
+        for (idx, row) in table.rows.iter().enumerate() {
+            let mut style = self.style();
+            style.bold = idx < table.head;
+            for (column, width) in widths.iter().enumerate() {
+                if column > 0 {
+                    self.push(border, " │ ");
+                }
+                let cell = row.get(column).map(String::as_str).unwrap_or_default();
+                let cell = match table.alignments.get(column) {
+                    Some(Alignment::Right) => format!("{cell:>width$}"),
+                    Some(Alignment::Center) => format!("{cell:^width$}"),
+                    _ => format!("{cell:<width$}"),
+                };
+                self.push(style, &cell);
+            }
+            self.flush();
+            if idx + 1 == table.head {
+                let line: Vec<String> = widths.iter().map(|width| "─".repeat(*width)).collect();
+                self.push(border, &line.join("─┼─"));
+                self.flush();
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        if !self.line.is_empty() {
+            self.lines.push(std::mem::take(&mut self.line));
+            self.spacer = false;
+        }
+        self.marker_pending = false;
+    }
+
+    /// Ends the line and leaves an empty one after it, with the bars of the open quotes. Never
+    /// two in a row, the last one is replaced
+    fn blank(&mut self) {
+        self.flush();
+        if self.lines.is_empty() {
+            return;
+        }
+        if self.spacer {
+            self.lines.pop();
+        }
+        self.push_quotes();
+        self.lines.push(std::mem::take(&mut self.line));
+        self.spacer = true;
+    }
+
+    fn finish(mut self) -> Vec<MdLine> {
+        self.flush();
+        if self.spacer {
+            self.lines.pop();
+        }
+        self.lines
+    }
+}
@@ -0,0 +1,726 @@
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+
+use exif::{In, Tag};
+use goblin::container::Ctx;
+use goblin::elf::{
+    Dynamic, Elf, ProgramHeader, SectionHeader, header, program_header, section_header,
+};
+use goblin::strtab::Strtab;
+use id3::TagLike;
+
+/// The largest header or string table read from an executable
+const ELF_TABLE_LIMIT: u64 = 4 * 1024 * 1024;
+/// How much of the end of an Ogg file is searched for its last page
+const OGG_TAIL: u64 = 64 * 1024;
+/// The largest Ogg comment packet read, embedded cover art can make it huge
+const COMMENT_LIMIT: usize = 1024 * 1024;
+
+/// A name and a value, one row of the metadata table
+pub type Field = (String, String);
+
+/// The EXIF tags shown for a photo. The GPS position is left out on purpose
+const EXIF_TAGS: [(Tag, &str); 10] = [
+    (Tag::LensModel, "Lens"),
+    (Tag::DateTimeOriginal, "Taken"),
+    (Tag::ExposureTime, "Exposure"),
+    (Tag::FNumber, "Aperture"),
+    (Tag::PhotographicSensitivity, "ISO"),
+    (Tag::FocalLength, "Focal length"),
+    (Tag::Flash, "Flash"),
+    (Tag::Software, "Software"),
+    (Tag::Artist, "Artist"),
+    (Tag::Copyright, "Copyright"),
+];
+
+/// The Vorbis comments shown for FLAC and Ogg files, with their names
+const VORBIS_TAGS: [(&str, &str); 7] = [
+    ("TITLE", "Title"),
+    ("ARTIST", "Artist"),
+    ("ALBUM", "Album"),
+    ("ALBUMARTIST", "Album artist"),
+    ("DATE", "Date"),
+    ("TRACKNUMBER", "Track"),
+    ("GENRE", "Genre"),
+];
+
+/// Reads the camera, lens, exposure and date of a photo. Empty without EXIF data
+pub fn exif(reader: &mut (impl BufRead + Seek)) -> Vec<Field> {
+    let Ok(exif) = exif::Reader::new().read_from_container(reader) else {
+        return Vec::new();
+    };
+    let value = |tag: Tag| {
+        exif.get_field(tag, In::PRIMARY).map(|field| {
+            let value = field.display_value().with_unit(&exif).to_string();
+            value.trim_matches('"').trim().to_string()
+        })
+    };
+
+    let mut fields = Vec::new();
+    let camera = match (value(Tag::Make), value(Tag::Model)) {
+        (Some(make), Some(model)) if !model.starts_with(&make) => Some(format!("{make} {model}")),
+        (make, model) => model.or(make),
+    };
+    fields.extend(camera.map(|camera| (String::from("Camera"), camera)));
+    for (tag, name) in EXIF_TAGS {
+        if let Some(value) = value(tag).filter(|value| !value.is_empty()) {
+            fields.push((name.to_string(), value));
+        }
+    }
+    fields
+}
+
+/// Describes an audio file or an executable from its contents, *header* being its first
+/// bytes. Empty for other files or if they can't be read
+pub fn read(reader: &mut (impl Read + Seek), header: &[u8]) -> Vec<Field> {
+    let fields = if header.starts_with(b"\x7fELF") {
+        elf(reader)
+    } else if header.starts_with(b"ID3") || frame_sync(header, 0) {
+        mp3(reader)
+    } else if header.starts_with(b"fLaC") {
+        flac(reader)
+    } else if header.starts_with(b"OggS") {
+        ogg(reader)
+    } else if header.starts_with(b"RIFF") && header.get(8..12) == Some(b"WAVE") {
+        wav(reader)
+    } else {
+        return Vec::new();
+    };
+    fields.unwrap_or_default()
+}
+
+/// The architecture, type, entry point and linked libraries of an ELF binary. Only the headers
+/// and the string tables are read, never the whole file
+fn elf(reader: &mut (impl Read + Seek)) -> io::Result<Vec<Field>> {
+    let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+    // The 32-bit header is shorter, reading the size of the 64-bit one covers both
+    let header = Elf::parse_header(&read_at(reader, 0, header::header64::SIZEOF_EHDR as u64)?)
+        .map_err(invalid)?;
+    let ctx = Ctx::new(
+        header.container().map_err(invalid)?,
+        header.endianness().map_err(invalid)?,
+    );
+
+    let program_headers = read_at(
+        reader,
+        header.e_phoff,
+        header.e_phnum as u64 * header.e_phentsize as u64,
+    )?;
+    let program_headers =
+        ProgramHeader::parse(&program_headers, 0, header.e_phnum as usize, ctx).map_err(invalid)?;
+    let segment = |kind: u32| {
+        program_headers
+            .iter()
+            .find(|ph| ph.p_type == kind && ph.p_filesz > 0)
+    };
+    let interpreter = match segment(program_header::PT_INTERP) {
+        Some(ph) => {
+            let bytes = read_at(reader, ph.p_offset, ph.p_filesz)?;
+            Some(
+                String::from_utf8_lossy(&bytes)
+                    .trim_end_matches('\0')
+                    .to_string(),
+            )
+        }
+        None => None,
+    };
+
+    let class = if ctx.is_big() { "64-bit" } else { "32-bit" };
+    let endian = if ctx.is_little_endian() {
+        "little"
+    } else {
+        "big"
+    };
+    let kind = match header.e_type {
+        header::ET_EXEC => "Executable",
+        header::ET_DYN if interpreter.is_some() => "Position-independent executable",
+        header::ET_DYN => "Shared library",
+        header::ET_REL => "Relocatable object",
+        header::ET_CORE => "Core dump",
+        other => header::et_to_str(other),
+    };
+    let mut fields = vec![
+        (String::from("Type"), kind.to_string()),
+        (
+            String::from("Architecture"),
+            format!(
+                "{}, {class} {endian}-endian",
+                header::machine_to_str(header.e_machine)
+            ),
+        ),
+        (
+            String::from("Entry point"),
+            format!("{:#x}", header.e_entry),
+        ),
+    ];
+    if let Some(interpreter) = interpreter {
+        fields.push((String::from("Interpreter"), interpreter));
+    }
+    let dynamic = segment(program_header::PT_DYNAMIC);
+    let linking = if dynamic.is_some() {
+        "Dynamic"
+    } else {
+        "Static"
+    };
+    fields.push((String::from("Linking"), linking.to_string()));
+
+    let section_headers = elf_sections(reader, &header, ctx)?;
+    // The symbols are counted from the size of their table, the table itself isn't read
+    let symbols = section_headers
+        .iter()
+        .rfind(|section| section.sh_type == section_header::SHT_SYMTAB)
+        .filter(|section| section.sh_entsize > 0)
+        .map_or(0, |section| section.sh_size / section.sh_entsize);
+    let stripped = if symbols == 0 {
+        String::from("Yes")
+    } else {
+        format!("No, {symbols} symbols")
+    };
+    fields.push((String::from("Stripped"), stripped));
+    let mut names_idx = header.e_shstrndx as usize;
+    if names_idx == section_header::SHN_XINDEX as usize {
+        names_idx = section_headers
+            .first()
+            .map_or(0, |first| first.sh_link as usize);
+    }
+    let names = match section_headers.get(names_idx) {
+        Some(section) => read_at(reader, section.sh_offset, section.sh_size)?,
+        None => Vec::new(),
+    };
+    let names = Strtab::parse(&names, 0, names.len(), 0).map_err(invalid)?;
+    let debug_info = section_headers
+        .iter()
+        .any(|section| names.get_at(section.sh_name) == Some(".debug_info"));
+    let debug_info = if debug_info { "Yes" } else { "No" };
+    fields.push((String::from("Debug info"), debug_info.to_string()));
+
+    // The dynamic segment is parsed on its own, as if it started the file
+    if let Some(segment) = dynamic {
+        let bytes = read_at(reader, segment.p_offset, segment.p_filesz)?;
+        let rebased: Vec<ProgramHeader> = (program_headers.iter())
+            .map(|ph| match ph.p_type {
+                program_header::PT_DYNAMIC => ProgramHeader {
+                    p_offset: 0,
+                    ..ph.clone()
+                },
+                _ => ph.clone(),
+            })
+            .collect();
+        let libraries = match Dynamic::parse(&bytes, &rebased, ctx).map_err(invalid)? {
+            Some(dynamic) if dynamic.info.needed_count > 0 => {
+                let strings = read_at(
+                    reader,
+                    dynamic.info.strtab as u64,
+                    dynamic.info.strsz as u64,
+                )?;
+                let strings = Strtab::parse(&strings, 0, strings.len(), 0).map_err(invalid)?;
+                (dynamic.get_libraries(&strings).into_iter())
+                    .map(str::to_string)
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+        // The libraries go one per row, only the first one is named
+        for (idx, library) in libraries.into_iter().enumerate() {
+            let name = if idx == 0 { "Libraries" } else { "" };
+            fields.push((name.to_string(), library));
+        }
+    }
+    Ok(fields)
+}
+
+/// Reads the section headers of an ELF binary, none if it has no section header table
+fn elf_sections(
+    reader: &mut (impl Read + Seek),
+    header: &header::Header,
+    ctx: Ctx,
+) -> io::Result<Vec<SectionHeader>> {
+    let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+    if header.e_shoff == 0 {
+        return Ok(Vec::new());
+    }
+    let size = header.e_shentsize as u64;
+    // With too many sections to count in the header, the first section holds the count
+    let mut count = header.e_shnum as u64;
+    if count == 0 {
+        let first = read_at(reader, header.e_shoff, size)?;
+        count = SectionHeader::parse_from(&first, 0, 1, ctx)
+            .map_err(invalid)?
+            .first()
+            .map_or(0, |first| first.sh_size);
+    }
+    let bytes = read_at(reader, header.e_shoff, count.saturating_mul(size))?;
+    SectionHeader::parse_from(&bytes, 0, count as usize, ctx).map_err(invalid)
+}
+
+/// Reads *length* bytes from *offset* on, fewer at the end of the file. Fails for more than
+/// ELF_TABLE_LIMIT bytes
+fn read_at(reader: &mut (impl Read + Seek), offset: u64, length: u64) -> io::Result<Vec<u8>> {
+    if length > ELF_TABLE_LIMIT {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "table too large",
+        ));
+    }
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut bytes = Vec::new();
+    reader.take(length).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// The ID3 tags of an MP3 file, then its length and bitrate from the first MPEG frame
+fn mp3(reader: &mut (impl Read + Seek)) -> io::Result<Vec<Field>> {
+    let size = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(0))?;
+    let mut fields = id3::v1v2::read_from(&mut *reader)
+        .map(|tag| id3_fields(&tag))
+        .unwrap_or_default();
+
+    // The audio starts after the ID3v2 tag, and maybe some padding
+    reader.seek(SeekFrom::Start(0))?;
+    let mut head = [0; 10];
+    reader.read_exact(&mut head)?;
+    let mut start = 0;
+    if head.starts_with(b"ID3") {
+        let footer = if head[5] & 0x10 != 0 { 10 } else { 0 };
+        start = 10 + footer + syncsafe(&head[6..10]);
+    }
+    reader.seek(SeekFrom::Start(start))?;
+    let mut bytes = Vec::new();
+    reader.take(16 * 1024).read_to_end(&mut bytes)?;
+    let Some((offset, frame)) = (0..bytes.len())
+        .filter(|&at| frame_sync(&bytes, at))
+        .find_map(|at| Some((at, MpegFrame::parse(&bytes[at..])?)))
+    else {
+        return Ok(fields);
+    };
+    let audio = &bytes[offset..];
+
+    // A Xing or VBRI header in the first frame counts the frames of a VBR file, LAME writes
+    // the same header as Info into CBR files
+    let side_info = match (frame.mpeg1, frame.mono) {
+        (true, false) => 32,
+        (true, true) | (false, false) => 17,
+        (false, true) => 9,
+    };
+    let xing = audio.get(4 + side_info..).and_then(|xing| {
+        let vbr = xing.starts_with(b"Xing");
+        if !vbr && !xing.starts_with(b"Info") || be_u32(xing, 4)? & 1 == 0 {
+            return None;
+        }
+        Some((be_u32(xing, 8)?, vbr))
+    });
+    let vbri = audio
+        .get(36..)
+        .filter(|vbri| vbri.starts_with(b"VBRI"))
+        .and_then(|vbri| Some((be_u32(vbri, 14)?, true)));
+    // An ID3v1 tag takes the last 128 bytes
+    let mut audio_size = size.saturating_sub(start + offset as u64);
+    let mut trailer = [0; 3];
+    if reader.seek(SeekFrom::End(-128)).is_ok()
+        && reader.read_exact(&mut trailer).is_ok()
+        && &trailer == b"TAG"
+    {
+        audio_size = audio_size.saturating_sub(128);
+    }
+
+    let (seconds, bitrate) = match xing.or(vbri) {
+        Some((frames, vbr)) if frames > 0 => {
+            let seconds = frames as f64 * frame.samples as f64 / frame.sample_rate as f64;
+            let average = audio_size as f64 * 8.0 / seconds / 1000.0;
+            let bitrate = if vbr {
+                format!("VBR, {average:.0} kbps on average")
+            } else {
+                format!("{} kbps", frame.bitrate)
+            };
+            (seconds, bitrate)
+        }
+        _ => (
+            audio_size as f64 * 8.0 / (frame.bitrate as f64 * 1000.0),
+            format!("{} kbps", frame.bitrate),
+        ),
+    };
+    fields.push((String::from("Duration"), duration(seconds)));
+    fields.push((String::from("Bitrate"), bitrate));
+    fields.push((String::from("Sample rate"), kilohertz(frame.sample_rate)));
+    fields.push((
+        String::from("Channels"),
+        channels(if frame.mono { 1 } else { 2 }),
+    ));
+    Ok(fields)
+}
+
+/// The title, artist, album and such of an ID3 tag
+fn id3_fields(tag: &id3::Tag) -> Vec<Field> {
+    let track = match (tag.track(), tag.total_tracks()) {
+        (Some(track), Some(total)) => Some(format!("{track}/{total}")),
+        (track, _) => track.map(|track| track.to_string()),
+    };
+    let date = tag
+        .date_recorded()
+        .map(|date| date.to_string())
+        .or_else(|| tag.year().map(|year| year.to_string()));
+    let fields = [
+        ("Title", tag.title().map(str::to_string)),
+        ("Artist", tag.artist().map(str::to_string)),
+        ("Album", tag.album().map(str::to_string)),
+        ("Album artist", tag.album_artist().map(str::to_string)),
+        ("Date", date),
+        ("Track", track),
+        ("Genre", tag.genre_parsed().map(String::from)),
+    ];
+    fields
+        .into_iter()
+        .filter_map(|(name, value)| Some((name.to_string(), one_line(&value?))))
+        .filter(|(_, value)| !value.is_empty())
+        .collect()
+}
+
+/// The properties of an MPEG audio frame header. Only Layer III is understood
+struct MpegFrame {
+    mpeg1: bool,
+    mono: bool,
+    /// In kbit/s
+    bitrate: u32,
+    sample_rate: u32,
+    /// Samples per frame
+    samples: u32,
+}
+
+impl MpegFrame {
+    fn parse(bytes: &[u8]) -> Option<Self> {
+        const BITRATES_V1: [u32; 15] = [
+            0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+        ];
+        const BITRATES_V2: [u32; 15] =
+            [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+        let header = bytes.get(..4)?;
+        let version = (header[1] >> 3) & 3;
+        let layer = (header[1] >> 1) & 3;
+        if version == 1 || layer != 1 {
+            return None;
+        }
+        let mpeg1 = version == 3;
+        let bitrates = if mpeg1 { BITRATES_V1 } else { BITRATES_V2 };
+        let bitrate = *bitrates
+            .get((header[2] >> 4) as usize)
+            .filter(|&&b| b > 0)?;
+        let base = [44100, 48000, 32000].get(((header[2] >> 2) & 3) as usize)?;
+        let sample_rate = match version {
+            3 => *base,
+            2 => base / 2,
+            _ => base / 4,
+        };
+
+        Some(Self {
+            mpeg1,
+            mono: header[3] >> 6 == 3,
+            bitrate,
+            sample_rate,
+            samples: if mpeg1 { 1152 } else { 576 },
+        })
+    }
+}
+
+/// The Vorbis comments and stream properties of a FLAC file
+fn flac(reader: &mut (impl Read + Seek)) -> io::Result<Vec<Field>> {
+    reader.seek(SeekFrom::Start(4))?;
+    let mut fields = Vec::new();
+    let mut properties = Vec::new();
+    loop {
+        let mut head = [0; 4];
+        reader.read_exact(&mut head)?;
+        let length = u32::from_be_bytes([0, head[1], head[2], head[3]]) as u64;
+        match head[0] & 0x7f {
+            // STREAMINFO
+            0 => {
+                let mut block = [0; 18];
+                reader.read_exact(&mut block)?;
+                reader.seek(SeekFrom::Current(length as i64 - 18))?;
+                let sample_rate =
+                    (block[10] as u32) << 12 | (block[11] as u32) << 4 | (block[12] as u32) >> 4;
+                let channel_count = ((block[12] >> 1) & 7) + 1;
+                let bits = ((block[12] & 1) << 4 | block[13] >> 4) + 1;
+                let samples = ((block[13] & 0xf) as u64) << 32
+                    | u32::from_be_bytes([block[14], block[15], block[16], block[17]]) as u64;
+                if sample_rate > 0 && samples > 0 {
+                    let seconds = samples as f64 / sample_rate as f64;
+                    properties.push((String::from("Duration"), duration(seconds)));
+                }
+                properties.push((String::from("Sample rate"), kilohertz(sample_rate)));
+                properties.push((String::from("Channels"), channels(channel_count as u32)));
+                properties.push((String::from("Bits"), format!("{bits}-bit")));
+            }
+            // VORBIS_COMMENT
+            4 => {
+                let mut block = Vec::new();
+                reader.by_ref().take(length).read_to_end(&mut block)?;
+                fields = vorbis_fields(&block);
+            }
+            _ => {
+                reader.seek(SeekFrom::Current(length as i64))?;
+            }
+        }
+        // The last metadata block has the top bit set
+        if head[0] & 0x80 != 0 {
+            break;
+        }
+    }
+    fields.extend(properties);
+    Ok(fields)
+}
+
+/// The comments and stream properties of an Ogg Vorbis or Opus file
+fn ogg(reader: &mut (impl Read + Seek)) -> io::Result<Vec<Field>> {
+    let size = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(0))?;
+    let packets = ogg_packets(reader, 2)?;
+    let Some(ident) = packets.first() else {
+        return Ok(Vec::new());
+    };
+
+    // The granule position counts samples, Opus always at 48 kHz and after a pre-skip
+    let (codec, channel_count, sample_rate, clock, skip, comments) =
+        if ident.starts_with(b"\x01vorbis") {
+            let rate = le_u32(ident, 12).unwrap_or(0);
+            let comments = packets.get(1).and_then(|p| p.strip_prefix(b"\x03vorbis"));
+            ("Vorbis", ident.get(11), rate, rate, 0, comments)
+        } else if ident.starts_with(b"OpusHead") {
+            let rate = le_u32(ident, 12).unwrap_or(0);
+            let skip = le_u16(ident, 10).unwrap_or(0) as u64;
+            let comments = packets.get(1).and_then(|p| p.strip_prefix(b"OpusTags"));
+            ("Opus", ident.get(9), rate, 48000, skip, comments)
+        } else {
+            return Ok(Vec::new());
+        };
+
+    let mut fields = comments.map(vorbis_fields).unwrap_or_default();
+    fields.push((String::from("Codec"), codec.to_string()));
+    let start = size.saturating_sub(OGG_TAIL);
+    reader.seek(SeekFrom::Start(start))?;
+    let mut tail = Vec::new();
+    reader.read_to_end(&mut tail)?;
+    let granule = (0..tail.len().saturating_sub(14))
+        .rev()
+        .find(|&at| tail[at..].starts_with(b"OggS"))
+        .and_then(|at| tail.get(at + 6..at + 14))
+        .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()));
+    if let Some(granule) = granule.filter(|&granule| granule != u64::MAX)
+        && clock > 0
+    {
+        let seconds = granule.saturating_sub(skip) as f64 / clock as f64;
+        fields.push((String::from("Duration"), duration(seconds)));
+    }
+    if sample_rate > 0 {
+        fields.push((String::from("Sample rate"), kilohertz(sample_rate)));
+    }
+    if let Some(&count) = channel_count {
+        fields.push((String::from("Channels"), channels(count as u32)));
+    }
+    Ok(fields)
+}
+
+/// Reads the first *count* packets of an Ogg stream
+fn ogg_packets(reader: &mut impl Read, count: usize) -> io::Result<Vec<Vec<u8>>> {
+    let mut packets = Vec::new();
+    let mut packet = Vec::new();
+    while packets.len() < count {
+        let mut head = [0; 27];
+        reader.read_exact(&mut head)?;
+        if !head.starts_with(b"OggS") {
+            break;
+        }
+        let mut lacing = vec![0; head[26] as usize];
+        reader.read_exact(&mut lacing)?;
+        for length in lacing {
+            let mut segment = vec![0; length as usize];
+            reader.read_exact(&mut segment)?;
+            if packet.len() < COMMENT_LIMIT {
+                packet.extend(segment);
+            }
+            // A segment shorter than 255 bytes ends the packet
+            if length < 255 {
+                packets.push(std::mem::take(&mut packet));
+                if packets.len() == count {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(packets)
+}
+
+/// The ID3 tags and the format of a WAV file
+fn wav(reader: &mut (impl Read + Seek)) -> io::Result<Vec<Field>> {
+    reader.seek(SeekFrom::Start(0))?;
+    let mut fields = id3::Tag::read_from2(&mut *reader)
+        .map(|tag| id3_fields(&tag))
+        .unwrap_or_default();
+
+    reader.seek(SeekFrom::Start(12))?;
+    let mut format = None;
+    let mut data = None;
+    while format.is_none() || data.is_none() {
+        let mut head = [0; 8];
+        if reader.read_exact(&mut head).is_err() {
+            break;
+        }
+        let length = le_u32(&head, 4).unwrap_or(0) as u64;
+        if &head[..4] == b"fmt " {
+            let mut chunk = Vec::new();
+            reader.by_ref().take(length).read_to_end(&mut chunk)?;
+            format = Some(chunk);
+        } else {
+            if &head[..4] == b"data" {
+                data = Some(length);
+            }
+            reader.seek(SeekFrom::Current(length as i64))?;
+        }
+        // Chunks are padded to an even length
+        if length & 1 == 1 {
+            reader.seek(SeekFrom::Current(1))?;
+        }
+    }
+
+    if let Some(chunk) = format {
+        let channel_count = le_u16(&chunk, 2).unwrap_or(0);
+        let sample_rate = le_u32(&chunk, 4).unwrap_or(0);
+        let byte_rate = le_u32(&chunk, 8).unwrap_or(0);
+        let bits = le_u16(&chunk, 14).unwrap_or(0);
+        if let Some(data) = data
+            && byte_rate > 0
+        {
+            let seconds = data as f64 / byte_rate as f64;
+            fields.push((String::from("Duration"), duration(seconds)));
+        }
+        fields.push((String::from("Sample rate"), kilohertz(sample_rate)));
+        fields.push((String::from("Channels"), channels(channel_count as u32)));
+        fields.push((String::from("Bits"), format!("{bits}-bit")));
+    }
+    Ok(fields)
+}
+
+/// Picks the known tags out of a Vorbis comment block, values of a repeated tag are joined
+fn vorbis_fields(block: &[u8]) -> Vec<Field> {
+    let mut comments: Vec<(String, String)> = Vec::new();
+    let vendor = le_u32(block, 0).unwrap_or(0) as usize;
+    let mut at = 4 + vendor;
+    let count = le_u32(block, at).unwrap_or(0);
+    at += 4;
+    for _ in 0..count {
+        let Some(length) = le_u32(block, at) else {
+            break;
+        };
+        let Some(comment) = block.get(at + 4..at + 4 + length as usize) else {
+            break;
+        };
+        at += 4 + length as usize;
+        let comment = String::from_utf8_lossy(comment);
+        if let Some((key, value)) = comment.split_once('=') {
+            comments.push((key.to_uppercase(), one_line(value)));
+        }
+    }
+
+    VORBIS_TAGS
+        .iter()
+        .filter_map(|(key, name)| {
+            let values: Vec<&str> = (comments.iter())
+                .filter(|(other, _)| other == key)
+                .map(|(_, value)| value.as_str())
+                .collect();
+            (!values.is_empty()).then(|| (name.to_string(), values.join("; ")))
+        })
+        .collect()
+}
+
+/// Returns true if an MPEG frame header starts at *at*
+fn frame_sync(bytes: &[u8], at: usize) -> bool {
+    bytes.get(at) == Some(&0xff) && bytes.get(at + 1).is_some_and(|b| b & 0xe0 == 0xe0)
+}
+
+/// Reads a size of an ID3v2 header, 7 bits per byte
+fn syncsafe(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(0, |size, byte| size << 7 | (byte & 0x7f) as u64)
+}
+
+fn le_u16(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn le_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn be_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+/// Keeps a tag value on one line
+fn one_line(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Formats a length in seconds like 3:07 or 1:02:03
+fn duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{hours}:{minutes:02}:{seconds:02}")
+    } else {
+        format!("{minutes}:{seconds:02}")
+    }
+}
+
+fn kilohertz(rate: u32) -> String {
+    format!("{} kHz", rate as f64 / 1000.0)
+}
+
+fn channels(count: u32) -> String {
+    match count {
+        1 => String::from("Mono"),
+        2 => String::from("Stereo"),
+        count => format!("{count} channels"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn elf_describes_the_test_binary() {
+        let mut file = std::fs::File::open(std::env::current_exe().unwrap()).unwrap();
+        let fields = elf(&mut file).unwrap();
+        let value = |name: &str| {
+            (fields.iter())
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| value.as_str())
+        };
+        assert!(value("Architecture").is_some_and(|arch| arch.contains("-bit")));
+        assert_eq!(value("Linking"), Some("Dynamic"));
+        assert!(value("Stripped").is_some_and(|stripped| stripped.starts_with("No, ")));
+        assert!(value("Libraries").is_some());
+    }
+
+    #[test]
+    fn read_at_refuses_large_tables() {
+        let mut reader = Cursor::new(vec![7; 16]);
+        assert_eq!(read_at(&mut reader, 4, 8).unwrap(), vec![7; 8]);
+        assert_eq!(read_at(&mut reader, 12, 8).unwrap(), vec![7; 4]);
+        assert!(read_at(&mut reader, 0, ELF_TABLE_LIMIT + 1).is_err());
+    }
+
+    #[test]
+    fn elf_rejects_truncated_headers() {
+        let mut reader = Cursor::new(b"\x7fELF\x02\x01\x01".to_vec());
+        assert!(elf(&mut reader).is_err());
+    }
+}
@@ -0,0 +1,192 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Cursor, Seek};
+use std::path::Path;
+
+use image::imageops::FilterType;
+use image::{ImageReader, RgbaImage};
+
+use super::metadata::{self, Field};
+
+/// The largest image read from inside an archive, in bytes
+pub const ENTRY_LIMIT: usize = 32 * 1024 * 1024;
+
+/// The largest side of the thumbnail kept for the preview, in pixels
+const THUMBNAIL_SIZE: u32 = 512;
+
+/// A decoded image, scaled down for the preview.
+#[derive(Debug)]
+pub struct ImagePreview {
+    /// The format name, like PNG or JPG
+    pub format: String,
+    pub width: u32,
+    pub height: u32,
+    pub thumbnail: RgbaImage,
+    /// The EXIF data of a photo
+    pub exif: Vec<Field>,
+}
+
+/// An RGB color
+pub type Rgb = (u8, u8, u8);
+
+/// Returns true if the image crate can decode the file, judged by its extension
+pub fn is_image(path: &Path) -> bool {
+    image::ImageFormat::from_path(path).is_ok_and(|format| format.reading_enabled())
+}
+
+/// Decodes an image and scales it down to a thumbnail
+pub fn load(path: &Path) -> Result<ImagePreview, String> {
+    let reader = ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| e.to_string())?;
+    let mut image = preview(reader)?;
+    if let Ok(file) = File::open(path) {
+        image.exif = metadata::exif(&mut BufReader::new(file));
+    }
+    Ok(image)
+}
+
+/// Decodes an image that was read into memory, like a file in an archive
+pub fn decode(bytes: &[u8]) -> Result<ImagePreview, String> {
+    let reader = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|e| e.to_string())?;
+    let mut image = preview(reader)?;
+    image.exif = metadata::exif(&mut Cursor::new(bytes));
+    Ok(image)
+}
+
+fn preview(reader: ImageReader<impl BufRead + Seek>) -> Result<ImagePreview, String> {
+    let format = reader
+        .format()
+        .and_then(|format| format.extensions_str().first())
+        .map(|ext| ext.to_uppercase())
+        .unwrap_or_default();
+    let image = reader.decode().map_err(|e| e.to_string())?;
+    let (width, height) = (image.width(), image.height());
+    // thumbnail() scales small images up as well
+    let thumbnail = if width > THUMBNAIL_SIZE || height > THUMBNAIL_SIZE {
+        image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+    } else {
+        image
+    };
+
+    Ok(ImagePreview {
+        format,
+        width,
+        height,
+        thumbnail: thumbnail.into_rgba8(),
+        exif: Vec::new(),
+    })
+}
+
+/// Returns the largest size with the aspect ratio of *width* x *height* that fits in
+/// *max_width* x *max_height*, never scaling up
+pub fn fit(width: u32, height: u32, max_width: u32, max_height: u32) -> (u32, u32) {
+    if width <= max_width && height <= max_height {
+        return (width.max(1), height.max(1));
+    }
+    let scale = f64::min(
+        max_width as f64 / width as f64,
+        max_height as f64 / height as f64,
+    );
+    (
+        ((width as f64 * scale).round() as u32).max(1),
+        ((height as f64 * scale).round() as u32).max(1),
+    )
+}
+
+/// Renders the thumbnail into *cols* x *rows* terminal cells, two pixels per cell: the upper
+/// one for the ▀ glyph, the lower one for the background. Transparent pixels are blended onto
+/// *background*
+pub fn half_blocks(
+    thumbnail: &RgbaImage,
+    cols: u16,
+    rows: u16,
+    background: Rgb,
+) -> Vec<Vec<(Rgb, Rgb)>> {
+    let (width, height) = fit(
+        thumbnail.width(),
+        thumbnail.height(),
+        cols as u32,
+        rows as u32 * 2,
+    );
+    let scaled = image::imageops::resize(thumbnail, width, height, FilterType::Triangle);
+    let pixel = |x: u32, y: u32| {
+        if y >= height {
+            return background;
+        }
+        let [r, g, b, a] = scaled.get_pixel(x, y).0;
+        let blend = |channel: u8, bg: u8| {
+            ((channel as u32 * a as u32 + bg as u32 * (255 - a as u32)) / 255) as u8
+        };
+        (
+            blend(r, background.0),
+            blend(g, background.1),
+            blend(b, background.2),
+        )
+    };
+
+    (0..height.div_ceil(2))
+        .map(|row| {
+            (0..width)
+                .map(|x| (pixel(x, row * 2), pixel(x, row * 2 + 1)))
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn fit_never_scales_up() {
+        assert_eq!(fit(10, 20, 100, 100), (10, 20));
+        assert_eq!(fit(0, 0, 100, 100), (1, 1));
+    }
+
+    #[test]
+    fn fit_keeps_the_aspect_ratio() {
+        assert_eq!(fit(200, 100, 100, 100), (100, 50));
+        assert_eq!(fit(100, 200, 100, 100), (50, 100));
+        assert_eq!(fit(1000, 1, 10, 10), (10, 1));
+    }
+
+    #[test]
+    fn half_blocks_pairs_rows() {
+        let mut thumbnail = RgbaImage::new(2, 4);
+        for (_, y, pixel) in thumbnail.enumerate_pixels_mut() {
+            *pixel = if y % 2 == 0 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 255, 255])
+            };
+        }
+        let cells = half_blocks(&thumbnail, 2, 2, (0, 0, 0));
+        assert_eq!(cells.len(), 2);
+        for row in cells {
+            assert_eq!(row, vec![((255, 0, 0), (0, 0, 255)); 2]);
+        }
+    }
+
+    #[test]
+    fn half_blocks_pads_odd_heights_with_the_background() {
+        let thumbnail = RgbaImage::from_pixel(1, 3, Rgba([255, 255, 255, 255]));
+        let cells = half_blocks(&thumbnail, 1, 2, (1, 2, 3));
+        assert_eq!(
+            cells,
+            vec![
+                vec![((255, 255, 255), (255, 255, 255))],
+                vec![((255, 255, 255), (1, 2, 3))],
+            ]
+        );
+    }
+
+    #[test]
+    fn half_blocks_blends_transparency() {
+        let thumbnail = RgbaImage::from_pixel(1, 2, Rgba([255, 255, 255, 0]));
+        let cells = half_blocks(&thumbnail, 1, 1, (10, 20, 30));
+        assert_eq!(cells, vec![vec![((10, 20, 30), (10, 20, 30))]]);
+    }
+}
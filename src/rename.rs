@@ -0,0 +1,614 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// A planned rename, the source path and the path it ends up at
+pub type Rename = (PathBuf, PathBuf);
+
+/// The widest a counter can be padded to, {n:32}
+const MAX_COUNTER_WIDTH: usize = 32;
+/// How many file names get tried before giving up on a names file
+const NAMES_FILE_ATTEMPTS: usize = 100;
+
+/// The file the names get edited in. It is only ever created new, so a file or symlink planted
+/// at the same path fails instead of being written through, and it gets removed when dropped
+pub struct NamesFile {
+    path: PathBuf,
+}
+
+impl NamesFile {
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for NamesFile {
+    fn drop(&mut self) {
+        _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Writes the names of *paths* into a new file in the temp directory, one per line
+pub fn write_names(paths: &[PathBuf]) -> Result<NamesFile, String> {
+    let names: Vec<String> = paths
+        .iter()
+        .map(|path| {
+            path.file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned()
+        })
+        .collect();
+
+    let pid = std::process::id();
+    let mut error = None;
+    for attempt in 0..NAMES_FILE_ATTEMPTS {
+        let path = std::env::temp_dir().join(format!("rune-rename-{pid}-{attempt}.txt"));
+        match create_new(&path) {
+            Ok(mut file) => {
+                // Owned from here on, so a failed write still removes it
+                let names_file = NamesFile { path };
+                file.write_all((names.join("\n") + "\n").as_bytes())
+                    .map_err(|e| e.to_string())?;
+                return Ok(names_file);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => error = Some(e),
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    Err(error.map(|e| e.to_string()).unwrap_or_default())
+}
+
+/// Creates *path*, failing if anything exists there. Only the user can read it
+#[cfg(unix)]
+fn create_new(path: &Path) -> io::Result<fs::File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+}
+
+#[cfg(not(unix))]
+fn create_new(path: &Path) -> io::Result<fs::File> {
+    fs::File::create_new(path)
+}
+
+/// Pairs *paths* with the edited names in *text*, line by line. Unchanged names are left out
+pub fn from_lines(paths: &[PathBuf], text: &str) -> Result<Vec<Rename>, String> {
+    let lines: Vec<&str> = text
+        .lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .collect();
+    if lines.len() != paths.len() {
+        return Err(format!(
+            "Expected {} names but got {}, lines can't be added or removed",
+            paths.len(),
+            lines.len()
+        ));
+    }
+
+    let mut renames = Vec::new();
+    for (idx, (path, name)) in paths.iter().zip(lines).enumerate() {
+        if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+            return Err(format!("Line {}: invalid name \"{name}\"", idx + 1));
+        }
+        if path
+            .file_name()
+            .is_some_and(|n| n.to_string_lossy() != name)
+        {
+            renames.push((path.clone(), path.with_file_name(name)));
+        }
+    }
+    check(&renames)?;
+    Ok(renames)
+}
+
+/// Makes sure no two items end up at the same path and nothing existing gets overwritten.
+/// A target may exist if it is renamed away itself
+pub fn check(renames: &[Rename]) -> Result<(), String> {
+    let sources: HashSet<&PathBuf> = renames.iter().map(|(source, _)| source).collect();
+    let mut targets = HashSet::new();
+    for (_, target) in renames {
+        let name = target.file_name().unwrap_or_default().to_string_lossy();
+        if !targets.insert(target) {
+            return Err(format!("{name} is given more than once"));
+        }
+        if target.exists() && !sources.contains(target) {
+            return Err(format!("{name} already exists"));
+        }
+    }
+    Ok(())
+}
+
+/// Runs *renames*. Everything gets a temporary name first, so swaps and cycles (a→b, b→a)
+/// work. If a step fails, the finished ones are undone
+pub fn execute(renames: &[Rename]) -> Result<(), String> {
+    let pid = std::process::id();
+    // (from, to) of every finished step, to roll back in reverse
+    let mut done: Vec<(PathBuf, PathBuf)> = Vec::new();
+    let mut result = Ok(());
+
+    let mut temps = Vec::new();
+    for (idx, (source, _)) in renames.iter().enumerate() {
+        let temp = source.with_file_name(format!(".rune-rename-{pid}-{idx}"));
+        if let Err(e) = rename_new(source, &temp) {
+            result = Err(e);
+            break;
+        }
+        done.push((source.clone(), temp.clone()));
+        temps.push(temp);
+    }
+
+    if result.is_ok() {
+        for (temp, (_, target)) in temps.iter().zip(renames) {
+            let name = target.file_name().unwrap_or_default().to_string_lossy();
+            if let Err(e) = rename_new(temp, target) {
+                result = Err(format!("{name}: {e}"));
+                break;
+            }
+            done.push((temp.clone(), target.clone()));
+        }
+    }
+
+    if result.is_err() {
+        for (from, to) in done.iter().rev() {
+            _ = fs::rename(to, from);
+        }
+    }
+    result
+}
+
+/// Renames *source* to *target*, unless something is already there
+fn rename_new(source: &Path, target: &Path) -> Result<(), String> {
+    if target.exists() {
+        return Err(String::from("Item with same name already exists"));
+    }
+    fs::rename(source, target).map_err(|e| e.to_string())
+}
+
+/// Case transformations for the replaced text
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Case {
+    Upper,
+    Lower,
+    Title,
+}
+
+/// A find/replace rename pattern: `regex/replacement[/flags]`.
+///
+/// The replacement takes the capture groups ($1, ${name}) plus `{n}` / `{n:03}` for a counter
+/// starting at 1 and `{date}` / `{date:%Y%m%d}` for the modification time. The flags are
+/// g (every match instead of the first), i (ignore case) and u, l, t to turn the replaced text
+/// into upper, lower or title case.
+#[derive(Debug)]
+pub struct Pattern {
+    regex: regex::Regex,
+    replacement: String,
+    all: bool,
+    case: Option<Case>,
+}
+
+impl Pattern {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let parts = split_unescaped(text);
+        let (find, replacement, flags) = match parts.as_slice() {
+            [find, replacement] => (find, replacement, ""),
+            [find, replacement, flags] => (find, replacement, flags.as_str()),
+            _ => return Err(String::from("Usage: pattern/replacement[/flags]")),
+        };
+
+        let mut all = false;
+        let mut ignore_case = false;
+        let mut case = None;
+        for flag in flags.chars() {
+            match flag {
+                'g' => all = true,
+                'i' => ignore_case = true,
+                'u' => case = Some(Case::Upper),
+                'l' => case = Some(Case::Lower),
+                't' => case = Some(Case::Title),
+                _ => return Err(format!("Unknown flag: {flag}")),
+            }
+        }
+        let regex = regex::RegexBuilder::new(find)
+            .case_insensitive(ignore_case)
+            .build()
+            .map_err(|e| match e {
+                regex::Error::Syntax(msg) => msg.lines().last().unwrap_or_default().to_string(),
+                e => e.to_string(),
+            })?;
+
+        Ok(Self {
+            regex,
+            replacement: replacement.clone(),
+            all,
+            case,
+        })
+    }
+
+    /// Returns the new name of *path*, the *idx*th item of the renamed ones
+    pub fn rename(&self, path: &Path, idx: usize) -> Result<String, String> {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let template = self.expand_tokens(path, idx)?;
+        let limit = if self.all { 0 } else { 1 };
+        let renamed = self.regex.replacen(&name, limit, |caps: &regex::Captures| {
+            let mut text = String::new();
+            caps.expand(&template, &mut text);
+            match self.case {
+                Some(case) => change_case(&text, case),
+                None => text,
+            }
+        });
+        Ok(renamed.into_owned())
+    }
+
+    /// Returns the renames of *paths*, without the unchanged ones. The counter only goes up
+    /// for names the regex matches
+    pub fn apply(&self, paths: &[PathBuf]) -> Result<Vec<Rename>, String> {
+        let mut renames = Vec::new();
+        let matching = paths.iter().filter(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            self.regex.is_match(&name)
+        });
+        for (idx, path) in matching.enumerate() {
+            let name = self.rename(path, idx)?;
+            if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+                return Err(format!("Invalid name \"{name}\""));
+            }
+            if path
+                .file_name()
+                .is_some_and(|n| n.to_string_lossy() != name)
+            {
+                renames.push((path.clone(), path.with_file_name(name)));
+            }
+        }
+        check(&renames)?;
+        Ok(renames)
+    }
+
+    /// Fills in the counter and date tokens, leaving the capture groups to the regex
+    fn expand_tokens(&self, path: &Path, idx: usize) -> Result<String, String> {
+        let mut expanded = String::new();
+        let mut rest = self.replacement.as_str();
+        while let Some(start) = rest.find(['{', '$']) {
+            expanded.push_str(&rest[..start]);
+            rest = &rest[start..];
+            // $1 and ${name} are capture groups, $$ is a literal $
+            if rest.starts_with('$') {
+                let len = if rest.starts_with("${") {
+                    rest.find('}').map(|end| end + 1).unwrap_or(rest.len())
+                } else {
+                    rest.chars().nth(1).map(|c| 1 + c.len_utf8()).unwrap_or(1)
+                };
+                expanded.push_str(&rest[..len]);
+                rest = &rest[len..];
+                continue;
+            }
+
+            let Some(end) = rest.find('}') else {
+                break;
+            };
+            let token = &rest[1..end];
+            let (name, spec) = token.split_once(':').unwrap_or((token, ""));
+            let value = match name {
+                "n" => counter(idx + 1, spec)?,
+                "date" => date(path, if spec.is_empty() { "%Y-%m-%d" } else { spec })?,
+                _ => rest[..=end].to_string(),
+            };
+            expanded.push_str(&value.replace('$', "$$"));
+            rest = &rest[end + 1..];
+        }
+        expanded.push_str(rest);
+        Ok(expanded)
+    }
+}
+
+/// Splits a pattern at the slashes that aren't escaped with a backslash
+fn split_unescaped(text: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('/') => parts.last_mut().unwrap().push('/'),
+                Some(next) => parts.last_mut().unwrap().extend(['\\', next]),
+                None => parts.last_mut().unwrap().push('\\'),
+            },
+            '/' => parts.push(String::new()),
+            c => parts.last_mut().unwrap().push(c),
+        }
+    }
+    parts
+}
+
+/// Formats a counter, *spec* is the width, zero padded if it starts with 0 (03 → 001)
+fn counter(value: usize, spec: &str) -> Result<String, String> {
+    if spec.is_empty() {
+        return Ok(value.to_string());
+    }
+    let width: usize = spec
+        .parse()
+        .ok()
+        .filter(|width| *width <= MAX_COUNTER_WIDTH)
+        .ok_or_else(|| format!("Invalid counter width: {spec}"))?;
+    Ok(if spec.starts_with('0') {
+        format!("{value:0width$}")
+    } else {
+        format!("{value:width$}")
+    })
+}
+
+/// Formats the modification time of *path* with a strftime *format*
+fn date(path: &Path, format: &str) -> Result<String, String> {
+    use std::fmt::Write;
+
+    let modified = fs::metadata(path)
+        .and_then(|md| md.modified())
+        .map_err(|e| e.to_string())?;
+    let datetime: chrono::DateTime<chrono::Local> = modified.into();
+    let mut text = String::new();
+    write!(text, "{}", datetime.format(format))
+        .map_err(|_| format!("Invalid date format: {format}"))?;
+    Ok(text)
+}
+
+fn change_case(text: &str, case: Case) -> String {
+    match case {
+        Case::Upper => text.to_uppercase(),
+        Case::Lower => text.to_lowercase(),
+        Case::Title => {
+            let mut title = String::new();
+            let mut word_start = true;
+            for c in text.chars() {
+                if word_start {
+                    title.extend(c.to_uppercase());
+                } else {
+                    title.extend(c.to_lowercase());
+                }
+                word_start = !c.is_alphanumeric();
+            }
+            title
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory with a file per name, each holding its own name
+    fn setup(test: &str, names: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rune-test-{}-{test}", std::process::id()));
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for name in names {
+            fs::write(dir.join(name), name).unwrap();
+        }
+        dir
+    }
+
+    /// The names in *dir* with what each file holds, sorted by name
+    fn contents(dir: &Path) -> Vec<(String, String)> {
+        let mut files: Vec<(String, String)> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| {
+                let path = entry.unwrap().path();
+                let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                (name, fs::read_to_string(&path).unwrap())
+            })
+            .collect();
+        files.sort();
+        files
+    }
+
+    fn pairs(files: &[(&str, &str)]) -> Vec<(String, String)> {
+        files
+            .iter()
+            .map(|(name, content)| (name.to_string(), content.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn swaps() {
+        let dir = setup("swap", &["a", "b"]);
+        let renames = [
+            (dir.join("a"), dir.join("b")),
+            (dir.join("b"), dir.join("a")),
+        ];
+        check(&renames).unwrap();
+        execute(&renames).unwrap();
+        assert_eq!(contents(&dir), pairs(&[("a", "b"), ("b", "a")]));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn cycles() {
+        let dir = setup("cycle", &["a", "b", "c"]);
+        let renames = [
+            (dir.join("a"), dir.join("b")),
+            (dir.join("b"), dir.join("c")),
+            (dir.join("c"), dir.join("a")),
+        ];
+        check(&renames).unwrap();
+        execute(&renames).unwrap();
+        assert_eq!(contents(&dir), pairs(&[("a", "c"), ("b", "a"), ("c", "b")]));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn chains_onto_a_new_name() {
+        let dir = setup("chain", &["a", "b"]);
+        let renames = [
+            (dir.join("a"), dir.join("b")),
+            (dir.join("b"), dir.join("c")),
+        ];
+        check(&renames).unwrap();
+        execute(&renames).unwrap();
+        assert_eq!(contents(&dir), pairs(&[("b", "a"), ("c", "b")]));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rolls_back_a_failed_temp_step() {
+        let dir = setup("rollback-temp", &["a", "b"]);
+        let renames = [
+            (dir.join("a"), dir.join("b")),
+            (dir.join("b"), dir.join("a")),
+            (dir.join("missing"), dir.join("c")),
+        ];
+        assert!(execute(&renames).is_err());
+        assert_eq!(contents(&dir), pairs(&[("a", "a"), ("b", "b")]));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rolls_back_a_failed_final_step() {
+        let dir = setup("rollback-final", &["a", "b", "c", "taken"]);
+        let renames = [
+            (dir.join("a"), dir.join("b")),
+            (dir.join("b"), dir.join("a")),
+            (dir.join("c"), dir.join("taken")),
+        ];
+        let error = execute(&renames).unwrap_err();
+        assert!(error.starts_with("taken"));
+        assert_eq!(
+            contents(&dir),
+            pairs(&[("a", "a"), ("b", "b"), ("c", "c"), ("taken", "taken")])
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn check_refuses_collisions() {
+        let dir = setup("check", &["a", "b", "c"]);
+        let twice = [
+            (dir.join("a"), dir.join("x")),
+            (dir.join("b"), dir.join("x")),
+        ];
+        assert_eq!(check(&twice).unwrap_err(), "x is given more than once");
+        let existing = [(dir.join("a"), dir.join("c"))];
+        assert_eq!(check(&existing).unwrap_err(), "c already exists");
+        // c is renamed away itself
+        let moved = [
+            (dir.join("a"), dir.join("c")),
+            (dir.join("c"), dir.join("d")),
+        ];
+        assert!(check(&moved).is_ok());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn lines_pair_with_paths() {
+        let dir = setup("lines", &["a", "b"]);
+        let paths = [dir.join("a"), dir.join("b")];
+        let renames = from_lines(&paths, "a\r\nc\n").unwrap();
+        assert_eq!(renames, [(dir.join("b"), dir.join("c"))]);
+        assert!(from_lines(&paths, "a\n").is_err());
+        assert!(from_lines(&paths, "a\nx/y\n").is_err());
+        assert!(from_lines(&paths, "a\n..\n").is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn names_file_is_removed_when_dropped() {
+        let file = write_names(&[PathBuf::from("/x/a"), PathBuf::from("/x/b")]).unwrap();
+        let path = file.get_path().to_path_buf();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\nb\n");
+        // A second file doesn't reuse the name
+        let other = write_names(&[]).unwrap();
+        assert_ne!(other.get_path(), path);
+        drop(file);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn counter_widths() {
+        assert_eq!(counter(7, ""), Ok(String::from("7")));
+        assert_eq!(counter(7, "03"), Ok(String::from("007")));
+        assert_eq!(counter(7, "3"), Ok(String::from("  7")));
+        assert_eq!(counter(7, "0"), Ok(String::from("7")));
+        // Too narrow a width doesn't cut the number
+        assert_eq!(counter(1234, "02"), Ok(String::from("1234")));
+        assert_eq!(counter(1, "032").unwrap().len(), 32);
+    }
+
+    #[test]
+    fn invalid_counter_widths() {
+        for spec in ["33", "099", "99999999999999999999999", "-1", "x", " 3"] {
+            assert_eq!(
+                counter(1, spec),
+                Err(format!("Invalid counter width: {spec}")),
+                "{spec}"
+            );
+        }
+    }
+
+    /// Renames *name* with *pattern* as the *idx*th match
+    fn renamed(pattern: &str, name: &str, idx: usize) -> String {
+        let pattern = Pattern::parse(pattern).unwrap();
+        pattern.rename(Path::new(name), idx).unwrap()
+    }
+
+    #[test]
+    fn pattern_parts() {
+        assert!(Pattern::parse("a").is_err());
+        assert!(Pattern::parse("a/b/g/x").is_err());
+        assert_eq!(Pattern::parse("a/b/x").unwrap_err(), "Unknown flag: x");
+        assert!(Pattern::parse("(/b").is_err());
+        // An empty replacement removes the match
+        assert_eq!(renamed("_old/", "file_old.txt", 0), "file.txt");
+        // A backslash keeps a slash in the regex
+        assert_eq!(split_unescaped(r"a\/b/c"), ["a/b", "c"]);
+        assert_eq!(split_unescaped(r"\d+/x"), [r"\d+", "x"]);
+    }
+
+    #[test]
+    fn pattern_flags() {
+        assert_eq!(renamed("a/x", "banana", 0), "bxnana");
+        assert_eq!(renamed("a/x/g", "banana", 0), "bxnxnx");
+        assert_eq!(renamed("A/x/gi", "bAnana", 0), "bxnxnx");
+        assert_eq!(renamed("^.*$/$0/u", "Name", 0), "NAME");
+        assert_eq!(renamed("^.*$/$0/l", "Name", 0), "name");
+        assert_eq!(renamed("^.*$/$0/t", "my file-name", 0), "My File-Name");
+    }
+
+    #[test]
+    fn pattern_replacements() {
+        assert_eq!(renamed(r"(\w+)\.(\w+)/$2.$1", "a.txt", 0), "txt.a");
+        assert_eq!(renamed(r"(?<stem>\w+)/${stem}_1", "a", 0), "a_1");
+        assert_eq!(renamed("^/$$", "a", 0), "$a");
+        assert_eq!(renamed("^/{n}_", "a", 4), "5_a");
+        assert_eq!(renamed("^/{n:03}_", "a", 0), "001_a");
+        // Unknown tokens stay as they are
+        assert_eq!(renamed("^/{x}", "a", 0), "{x}a");
+        assert!(
+            Pattern::parse("^/{n:33}")
+                .unwrap()
+                .rename(Path::new("a"), 0)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn pattern_counts_matches_only() {
+        let dir = setup("pattern", &["a.txt", "b.md", "c.txt"]);
+        let paths = [dir.join("a.txt"), dir.join("b.md"), dir.join("c.txt")];
+        let pattern = Pattern::parse(r"\.txt$/_{n}.txt").unwrap();
+        let renames = pattern.apply(&paths).unwrap();
+        assert_eq!(
+            renames,
+            [
+                (dir.join("a.txt"), dir.join("a_1.txt")),
+                (dir.join("c.txt"), dir.join("c_2.txt"))
+            ]
+        );
+        // An escaped slash in the replacement would move the file
+        let slash = Pattern::parse(r"^a/x\/y").unwrap();
+        assert_eq!(slash.apply(&paths).unwrap_err(), "Invalid name \"x/y.txt\"");
+        assert!(Pattern::parse(".*/..").unwrap().apply(&paths).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
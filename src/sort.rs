@@ -0,0 +1,274 @@
+use std::cmp::Ordering;
+use std::fmt::Display;
+use std::fs::{self, Metadata};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// The keys the items of a directory can be sorted by.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SortMode {
+    Name,
+    Size,
+    Modified,
+    Changed,
+    Extension,
+    Type,
+}
+
+impl Display for SortMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SortMode::Name => write!(f, "name"),
+            SortMode::Size => write!(f, "size"),
+            SortMode::Modified => write!(f, "mtime"),
+            SortMode::Changed => write!(f, "ctime"),
+            SortMode::Extension => write!(f, "ext"),
+            SortMode::Type => write!(f, "type"),
+        }
+    }
+}
+
+/// How a directory is sorted. Hidden items are always listed last.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SortOptions {
+    pub mode: SortMode,
+    pub reverse: bool,
+    pub dirs_first: bool,
+}
+
+impl Default for SortOptions {
+    fn default() -> Self {
+        Self {
+            mode: SortMode::Name,
+            reverse: false,
+            dirs_first: true,
+        }
+    }
+}
+
+impl Display for SortOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.mode)?;
+        if self.reverse {
+            write!(f, " rev")?;
+        }
+        Ok(())
+    }
+}
+
+/// Everything an item gets compared by. Built once per item, so the metadata
+/// isn't read again on every comparison.
+struct SortKey {
+    path: PathBuf,
+    priority: u8,
+    name: String,
+    extension: String,
+    kind: u8,
+    size: u64,
+    modified: SystemTime,
+    changed: SystemTime,
+}
+
+impl SortKey {
+    fn new(path: PathBuf, options: &SortOptions) -> Self {
+        let metadata = fs::metadata(&path).or_else(|_| fs::symlink_metadata(&path));
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let is_hidden = name.starts_with('.');
+        let is_dir = metadata.as_ref().map(|md| md.is_dir()).unwrap_or(false);
+
+        let priority = if is_hidden {
+            2
+        } else if is_dir && options.dirs_first {
+            0
+        } else {
+            1
+        };
+
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        let (kind, size, modified, changed) = match &metadata {
+            Ok(md) => (
+                file_kind(&path, md),
+                md.len(),
+                md.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                changed_time(md),
+            ),
+            Err(_) => (4, 0, SystemTime::UNIX_EPOCH, SystemTime::UNIX_EPOCH),
+        };
+
+        Self {
+            path,
+            priority,
+            name,
+            extension,
+            kind,
+            size,
+            modified,
+            changed,
+        }
+    }
+
+    fn cmp(&self, other: &Self, mode: SortMode) -> Ordering {
+        let by_name = || natural_cmp(&self.name, &other.name);
+        match mode {
+            SortMode::Name => by_name(),
+            SortMode::Size => self.size.cmp(&other.size).then_with(by_name),
+            SortMode::Modified => self.modified.cmp(&other.modified).then_with(by_name),
+            SortMode::Changed => self.changed.cmp(&other.changed).then_with(by_name),
+            SortMode::Extension => self.extension.cmp(&other.extension).then_with(by_name),
+            SortMode::Type => self
+                .kind
+                .cmp(&other.kind)
+                .then_with(|| self.extension.cmp(&other.extension))
+                .then_with(by_name),
+        }
+    }
+}
+
+/// Orders items by kind: directories, symlinks, regular files, then everything else
+fn file_kind(path: &Path, metadata: &Metadata) -> u8 {
+    if metadata.is_dir() {
+        0
+    } else if path.is_symlink() {
+        1
+    } else if metadata.is_file() {
+        2
+    } else {
+        3
+    }
+}
+
+#[cfg(unix)]
+fn changed_time(metadata: &Metadata) -> SystemTime {
+    use std::os::unix::fs::MetadataExt;
+    let secs = metadata.ctime().max(0) as u64;
+    SystemTime::UNIX_EPOCH + std::time::Duration::new(secs, metadata.ctime_nsec() as u32)
+}
+
+#[cfg(not(unix))]
+fn changed_time(metadata: &Metadata) -> SystemTime {
+    metadata.created().unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+/// Sorts *items* by *options*. Hidden items stay after the rest, and the
+/// reverse option only flips the order inside those groups.
+pub fn sort_items(items: &mut Vec<PathBuf>, options: &SortOptions) {
+    let mut keys: Vec<SortKey> = items
+        .drain(..)
+        .map(|path| SortKey::new(path, options))
+        .collect();
+
+    keys.sort_by(|a, b| {
+        let order = a.cmp(b, options.mode);
+        let order = if options.reverse {
+            order.reverse()
+        } else {
+            order
+        };
+        a.priority.cmp(&b.priority).then(order)
+    });
+
+    items.extend(keys.into_iter().map(|key| key.path));
+}
+
+/// Case-insensitive comparison that orders runs of digits by their value,
+/// so "file2" comes before "file10"
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+
+    loop {
+        match (a_chars.peek(), b_chars.peek()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(ca), Some(cb)) if ca.is_ascii_digit() && cb.is_ascii_digit() => {
+                let num_a = take_number(&mut a_chars);
+                let num_b = take_number(&mut b_chars);
+                // Compare by length first (leading zeros stripped), so huge numbers can't overflow
+                let order = num_a
+                    .len()
+                    .cmp(&num_b.len())
+                    .then_with(|| num_a.cmp(&num_b));
+                if order != Ordering::Equal {
+                    return order;
+                }
+            }
+            (Some(ca), Some(cb)) => {
+                let order = ca.to_lowercase().cmp(cb.to_lowercase());
+                if order != Ordering::Equal {
+                    return order;
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+}
+
+fn take_number(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut number = String::new();
+    while let Some(c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+        number.push(*c);
+        chars.next();
+    }
+    let trimmed = number.trim_start_matches('0');
+    trimmed.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_compare_by_value() {
+        assert_eq!(natural_cmp("file2", "file10"), Ordering::Less);
+        assert_eq!(natural_cmp("file10", "file2"), Ordering::Greater);
+        assert_eq!(natural_cmp("2.txt", "10.txt"), Ordering::Less);
+        assert_eq!(natural_cmp("v1.9", "v1.10"), Ordering::Less);
+    }
+
+    #[test]
+    fn letters_ignore_case() {
+        assert_eq!(natural_cmp("apple", "Banana"), Ordering::Less);
+        assert_eq!(natural_cmp("Apple", "banana"), Ordering::Less);
+    }
+
+    #[test]
+    fn ties_fall_back_to_plain_order() {
+        assert_eq!(natural_cmp("file1", "file1"), Ordering::Equal);
+        // Same value, the leading zero sorts first
+        assert_eq!(natural_cmp("file01", "file1"), Ordering::Less);
+        assert_eq!(natural_cmp("File", "file"), Ordering::Less);
+    }
+
+    #[test]
+    fn prefixes_sort_first() {
+        assert_eq!(natural_cmp("file", "file1"), Ordering::Less);
+        assert_eq!(natural_cmp("", "a"), Ordering::Less);
+    }
+
+    #[test]
+    fn huge_numbers_dont_overflow() {
+        let small = "n99999999999999999999999999999";
+        let big = "n100000000000000000000000000000";
+        assert_eq!(natural_cmp(small, big), Ordering::Less);
+        assert_eq!(
+            natural_cmp("n0000000000000000000000000000001", "n2"),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn sorts_a_listing() {
+        let mut names = vec!["file10", "File1", "file2", "file", "a20b", "a3b"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(names, ["a3b", "a20b", "file", "File1", "file2", "file10"]);
+    }
+}
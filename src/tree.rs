@@ -0,0 +1,140 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::manager::{Manager, OpenOption};
+
+/// A single row of the flattened tree. *prefix* holds the tree-guide characters
+/// that are drawn in front of the item name.
+#[derive(Debug, Clone)]
+pub struct TreeRow {
+    pub path: PathBuf,
+    pub depth: usize,
+    pub prefix: String,
+}
+
+/// Keeps track of which directories are expanded in the tree view and flattens them into rows.
+/// Children are only read (through Manager::read_dir) when a directory gets expanded.
+#[derive(Debug, Default)]
+pub struct Tree {
+    expanded: HashSet<PathBuf>,
+    children: HashMap<PathBuf, Vec<PathBuf>>,
+    rows: Vec<TreeRow>,
+}
+
+impl Tree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get_rows(&self) -> &Vec<TreeRow> {
+        &self.rows
+    }
+
+    pub fn is_expanded(&self, path: &Path) -> bool {
+        self.expanded.contains(path)
+    }
+
+    /// Expands a directory, loading its children if they weren't loaded yet
+    pub fn expand(&mut self, path: &Path, manager: &Manager) -> io::Result<()> {
+        if !path.is_dir() {
+            return Err(io::ErrorKind::NotADirectory.into());
+        }
+        if !self.children.contains_key(path) {
+            let items = manager.read_dir(path, OpenOption::Full)?;
+            self.children.insert(path.to_path_buf(), items);
+        }
+        self.expanded.insert(path.to_path_buf());
+        Ok(())
+    }
+
+    /// Collapses a directory. Returns false if it wasn't expanded
+    pub fn collapse(&mut self, path: &Path) -> bool {
+        self.expanded.remove(path)
+    }
+
+    /// Collapses everything, used when the tree is opened in a new directory
+    pub fn clear(&mut self) {
+        self.expanded.clear();
+        self.children.clear();
+        self.rows.clear();
+    }
+
+    /// Drops the loaded children so they get re-read on the next build, while keeping
+    /// the expanded directories expanded
+    pub fn refresh(&mut self) {
+        self.children.clear();
+    }
+
+    /// Returns the row index of the parent of the row at *idx*, if the parent is part of the tree
+    pub fn parent_row(&self, idx: usize) -> Option<usize> {
+        let depth = self.rows.get(idx)?.depth;
+        if depth == 0 {
+            return None;
+        }
+        self.rows[..idx].iter().rposition(|row| row.depth < depth)
+    }
+
+    /// Flattens the tree starting from *roots* (the items of the current directory).
+    /// Returns the paths of the rows, in the order they should be listed
+    pub fn build(&mut self, roots: Vec<PathBuf>, manager: &Manager) -> Vec<PathBuf> {
+        let mut rows = Vec::new();
+        self.flatten(roots, 0, "", manager, &mut rows);
+        let paths = rows.iter().map(|row| row.path.clone()).collect();
+        self.rows = rows;
+        paths
+    }
+
+    fn flatten(
+        &mut self,
+        items: Vec<PathBuf>,
+        depth: usize,
+        guides: &str,
+        manager: &Manager,
+        rows: &mut Vec<TreeRow>,
+    ) {
+        let count = items.len();
+        for (i, path) in items.into_iter().enumerate() {
+            let is_last = i + 1 == count;
+            let prefix = if depth == 0 {
+                String::new()
+            } else if is_last {
+                format!("{guides}└── ")
+            } else {
+                format!("{guides}├── ")
+            };
+
+            let expanded = self.expanded.contains(&path);
+            rows.push(TreeRow {
+                path: path.clone(),
+                depth,
+                prefix,
+            });
+
+            if !expanded {
+                continue;
+            }
+            if !self.children.contains_key(&path) {
+                match manager.read_dir(&path, OpenOption::Full) {
+                    Ok(items) => {
+                        self.children.insert(path.clone(), items);
+                    }
+                    Err(_) => {
+                        self.expanded.remove(&path);
+                        continue;
+                    }
+                }
+            }
+
+            let child_guides = if depth == 0 {
+                String::new()
+            } else if is_last {
+                format!("{guides}    ")
+            } else {
+                format!("{guides}│   ")
+            };
+            let children = self.children.get(&path).cloned().unwrap_or_default();
+            self.flatten(children, depth + 1, &child_guides, manager, rows);
+        }
+    }
+}
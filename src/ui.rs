@@ -1,25 +1,44 @@
 use core::str;
-use std::{path::PathBuf, time::SystemTime, usize};
+use std::{
+    collections::HashSet,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
 
 use crate::{
-    app::{self, *},
-    app_properties::{self, AppMode, AppProperties, EditAction},
+    app_properties::{AppMode, AppProperties, EditAction, ViewMode},
+    database,
+    filter::{find_match, fuzzy_match},
     manager::OpenOption,
+    preview::{
+        ArchivePreview, DataPreview, DatabasePreview, HexPreview, MarkdownPreview, Preview,
+        TextPreview,
+        data::{self, ParseError, TablePreview, TreeRow, Value},
+        graphics::{self, Protocol},
+        hexdump::{self, ByteClass},
+        highlight::{HighlightedLine, Token},
+        markdown::{MdKind, MdStyle},
+        metadata::Field,
+        picture::{self, ImagePreview, Rgb},
+    },
 };
 use chrono::{DateTime, Local};
-use crossterm::style::style;
+use crossterm::{cursor::MoveTo, execute, style::Print};
 use devicons;
 use ratatui::{
-    Frame,
+    DefaultTerminal, Frame,
     layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Style, Stylize},
     text::{Line, Span, Text},
     widgets::*,
 };
-use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use std::fmt::Debug;
 use std::ops::{Add, Div, Mul, Sub};
 
+pub mod completion;
+pub mod history;
 pub mod input;
 mod layout;
 pub mod theme;
@@ -70,6 +89,12 @@ macro_rules! impl_byte_readable {
 
 impl_byte_readable!(u8, u16, u32, u64, usize, i32, i64, f32, f64);
 
+/// How many top-level keys of a data file the preview summarises
+const SUMMARY_ROWS: usize = 8;
+
+/// The widest a table column gets, in chars
+const TABLE_COLUMN_WIDTH: usize = 40;
+
 const SYMBOL: &str = " 
       ‚             
   ‘    ‹’           
@@ -82,7 +107,7 @@ const SYMBOL: &str = "
     ¤ë$             
     r5¾%            
     í©°w–           
-    c¥›­©¸          
+    c¥›\u{AD}©¸          
     Ißú``uª         
     =ÏIv``¦J        
     [%` ¸`´`›|      
@@ -106,6 +131,94 @@ r^`´[>``•J
                               
                                       ";
 
+/// Picks the theme color for a kind of highlighted text
+fn token_style(theme: &theme::Theme, token: Token) -> Style {
+    let style = Style::default();
+    match token {
+        Token::Plain => style.fg(theme.get_fg()),
+        Token::Keyword => style.fg(theme.get_ht()).bold(),
+        Token::Type => style.fg(theme.get_ht()),
+        Token::Function => style.fg(theme.get_mt()),
+        Token::String => style.fg(theme.get_st()),
+        Token::Constant => style.fg(theme.get_pr()),
+        Token::Comment => style.fg(theme.get_s3()).italic(),
+    }
+}
+
+/// Picks the theme color and modifiers for a piece of rendered Markdown
+fn markdown_style(theme: &theme::Theme, md: MdStyle) -> Style {
+    let mut style = match md.kind {
+        MdKind::Text => Style::default().fg(theme.get_fg()),
+        MdKind::Heading(1) => Style::default().fg(theme.get_ht()).underlined(),
+        MdKind::Heading(2) => Style::default().fg(theme.get_ht()),
+        MdKind::Heading(_) => Style::default().fg(theme.get_mt()),
+        MdKind::Code => Style::default().fg(theme.get_st()),
+        MdKind::Block(token) => token_style(theme, token),
+        MdKind::Link => Style::default().fg(theme.get_mt()).underlined(),
+        MdKind::Marker => Style::default().fg(theme.get_s3()),
+    };
+    if md.bold {
+        style = style.bold();
+    }
+    if md.italic {
+        style = style.italic();
+    }
+    if md.strikethrough {
+        style = style.crossed_out();
+    }
+    style
+}
+
+/// The devicon of an item. Directories inside an archive aren't on disk, a trailing slash tells
+/// devicons they are directories
+fn item_icon(app_props: &AppProperties, path: &Path) -> devicons::FileIcon {
+    if !path.exists() && app_props.is_dir(path) {
+        let name = format!(
+            "{}/",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        );
+        return devicons::icon_for_file(name.as_str(), &Some(devicons::Theme::Dark));
+    }
+    devicons::icon_for_file(path, &Some(devicons::Theme::Dark))
+}
+
+/// The RGB value of a theme color, black for the named colors
+fn rgb(color: Color) -> Rgb {
+    match color {
+        Color::Rgb(r, g, b) => (r, g, b),
+        _ => (0, 0, 0),
+    }
+}
+
+/// Splits *text* into spans, styling the chars at the *matched* indices with *style*
+fn highlight_matches<'a>(text: &str, matched: &[usize], style: Style) -> Vec<Span<'a>> {
+    let mut spans = Vec::new();
+    let mut current = String::new();
+    let mut current_matched = false;
+
+    for (idx, c) in text.chars().enumerate() {
+        let is_matched = matched.contains(&idx);
+        if is_matched != current_matched && !current.is_empty() {
+            let part = std::mem::take(&mut current);
+            spans.push(if current_matched {
+                Span::styled(part, style)
+            } else {
+                Span::from(part)
+            });
+        }
+        current_matched = is_matched;
+        current.push(c);
+    }
+    if !current.is_empty() {
+        spans.push(if current_matched {
+            Span::styled(current, style)
+        } else {
+            Span::from(current)
+        });
+    }
+    spans
+}
+
 //A major problem here, is that since the ui is updated every frame, there are a bunch of
 //operations that will run on every frame that should be saved or cached. On big datasets, this
 //causes UI lags and performance issues.
@@ -119,8 +232,19 @@ pub struct UI<'a> {
     width: u16,
     height: u16,
     symbol: String,
+    loading: bool,
+    /// The half-block rendering of the last image, with the size it was rendered for
+    picture: Option<(PictureKey, Vec<Line<'a>>)>,
+    /// The image the current frame left room for, drawn with *draw_graphics*
+    pending_graphics: Option<(Rect, Arc<Preview>)>,
+    /// The image on the screen, drawn with kitty or sixel
+    shown_graphics: Option<(Rect, Arc<Preview>, Protocol)>,
+    encoder: graphics::Encoder,
 }
 
+/// The preview, the size in cells and the background an image got rendered for
+type PictureKey = (Arc<Preview>, u16, u16, Rgb);
+
 impl<'a> UI<'a> {
     pub fn new(app_props: &AppProperties) -> Self {
         let mut ui = Self {
@@ -129,6 +253,11 @@ impl<'a> UI<'a> {
             width: 0,
             height: 0,
             symbol: String::from(SYMBOL),
+            loading: false,
+            picture: None,
+            pending_graphics: None,
+            shown_graphics: None,
+            encoder: graphics::Encoder::new(),
         };
         ui.set_main_items(app_props);
         ui.set_theme_items(app_props);
@@ -142,19 +271,105 @@ impl<'a> UI<'a> {
         let header = layout::header_layout(frame);
         let footer = layout::footer_layout(frame);
 
-        //GENERATE BACKGOUND (atm i dont want a background cuz of hyprland)
-        //generate_background(app, frame);
-        if (app_props.mode == AppMode::Theme) {
+        if app_props.mode == AppMode::Theme {
             self.generate_theme_view(app_props, frame, chunks[1]);
             return;
         }
+        if app_props.mode == AppMode::View {
+            self.generate_viewer(app_props, frame);
+            return;
+        }
+        if app_props.mode == AppMode::Database {
+            self.generate_database(app_props, frame);
+            return;
+        }
 
         self.generate_statusbar(app_props, frame, footer[0]);
-        self.generate_main_view(app_props, frame, chunks[1]);
+        if matches!(
+            app_props.mode,
+            AppMode::BulkRename | AppMode::Edit(EditAction::PatternRename)
+        ) {
+            self.generate_rename_view(app_props, frame, chunks[1]);
+        } else {
+            self.generate_main_view(app_props, frame, chunks[1]);
+        }
         self.generate_preview(app_props, frame, chunks[2]);
         self.generate_symbol(app_props, frame, chunks[0]);
         self.generate_searchbar(app_props, frame, header[0]);
-        self.generate_navbar(app_props, frame, header[1]);
+        self.generate_command_line(app_props, frame, header[1]);
+
+        // Drawn last, so it lays over everything else
+        match app_props.mode {
+            AppMode::Command => self.generate_completion_popup(app_props, frame, header[1], true),
+            AppMode::Edit(_) => self.generate_completion_popup(app_props, frame, footer[0], false),
+            _ => {}
+        }
+    }
+
+    /// Draws the completion candidates of the active input, under (or above) the *anchor* area
+    fn generate_completion_popup(
+        &self,
+        app_props: &AppProperties,
+        frame: &mut Frame,
+        anchor: Rect,
+        below: bool,
+    ) {
+        let Some(completion) = app_props
+            .get_active_input()
+            .and_then(|input| input.get_completion())
+        else {
+            return;
+        };
+
+        let candidates = completion.get_candidates();
+        let height = (candidates.len() as u16 + 2).min(12);
+        let width = anchor.width.min(50);
+        let frame_area = frame.area();
+        let y = if below {
+            anchor
+                .bottom()
+                .min(frame_area.bottom().saturating_sub(height))
+        } else {
+            anchor.y.saturating_sub(height)
+        };
+        let area = Rect::new(anchor.x + 1, y, width, height).intersection(frame_area);
+
+        let items: Vec<ListItem> = candidates
+            .iter()
+            .map(|candidate| {
+                let mut spans = Vec::new();
+                if let Some(path) = &candidate.path {
+                    let icon = devicons::icon_for_file(path, &Some(devicons::Theme::Dark));
+                    let color = match hex::decode(icon.color.trim_matches('#')) {
+                        Ok(rgb) if !path.is_dir() => Color::Rgb(rgb[0], rgb[1], rgb[2]),
+                        _ => app_props.get_theme().get_fg(),
+                    };
+                    spans.push(Span::styled(
+                        format!("{} ", icon.icon),
+                        Style::default().fg(color),
+                    ));
+                }
+                spans.push(Span::from(candidate.get_label().to_string()));
+                ListItem::new(Line::from(spans))
+            })
+            .collect();
+
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(format!(
+                " {}/{} ",
+                completion.get_selected() + 1,
+                candidates.len()
+            ))
+            .style(Style::default().fg(app_props.get_theme().get_fg()));
+        let list = List::new(items)
+            .block(block)
+            .highlight_style(Style::default().fg(app_props.get_theme().get_ht()))
+            .highlight_symbol(">> ");
+        let mut state = ListState::default().with_selected(Some(completion.get_selected()));
+
+        frame.render_widget(Clear, area);
+        frame.render_stateful_widget(list, area, &mut state);
     }
 
     /// Sets the items for the main screen, called by app when changing directories
@@ -164,23 +379,58 @@ impl<'a> UI<'a> {
         let mut list: Vec<Line> = Vec::new();
 
         let items = items.lock().unwrap().clone();
-        items.iter().for_each(|i| {
+        let rows = app_props.tree.get_rows();
+        items.iter().enumerate().for_each(|(idx, i)| {
             let name = i.file_name().unwrap().to_string_lossy().into_owned();
-            let icon = devicons::icon_for_file(i, &Some(devicons::Theme::Dark));
+            let icon = item_icon(app_props, i);
 
             let rgb = hex::decode(icon.color.trim_matches('#'));
-            let color = if i.is_dir() || !rgb.is_ok() {
-                app_props.get_theme().get_fg()
-            } else {
-                let rgb = rgb.unwrap();
-                Color::Rgb(rgb[0], rgb[1], rgb[2])
+            let color = match rgb {
+                Ok(rgb) if !app_props.is_dir(i) => Color::Rgb(rgb[0], rgb[1], rgb[2]),
+                _ => app_props.get_theme().get_fg(),
             };
 
-            let line = Line::from(vec![
-                Span::styled(format!("{} ", icon.icon), Style::default().fg(color)),
-                Span::from(name),
-            ]);
-            list.push(Line::from(line));
+            let mut spans = Vec::new();
+            // Search results replace the items, so the rows only apply while they still match
+            if *app_props.get_view_mode() == ViewMode::Tree
+                && let Some(row) = rows.get(idx).filter(|row| &row.path == i)
+            {
+                spans.push(Span::styled(
+                    row.prefix.clone(),
+                    Style::default().fg(app_props.get_theme().get_s3()),
+                ));
+            }
+            spans.push(Span::styled(
+                format!("{} ", icon.icon),
+                Style::default().fg(color),
+            ));
+            let name = if *app_props.get_view_mode() == ViewMode::Flat {
+                let relative = i.strip_prefix(app_props.get_current_path()).unwrap_or(i);
+                relative.to_string_lossy().into_owned()
+            } else {
+                name
+            };
+            // In flat view the relative path is shown, but only the name is matched.
+            // The in-list search highlights over the live filter
+            let file_name = i.file_name().unwrap_or_default().to_string_lossy();
+            let matched = match (app_props.get_find_pattern(), app_props.get_live_filter()) {
+                (Some(term), _) => find_match(term, &file_name),
+                (None, Some(term)) => fuzzy_match(term, &file_name),
+                (None, None) => None,
+            };
+            match matched {
+                Some(matched) => {
+                    let offset = name.chars().count() - file_name.chars().count();
+                    let matched: Vec<usize> = matched.iter().map(|idx| idx + offset).collect();
+                    spans.extend(highlight_matches(
+                        &name,
+                        &matched,
+                        Style::default().fg(app_props.get_theme().get_ht()),
+                    ));
+                }
+                None => spans.push(Span::from(name)),
+            }
+            list.push(Line::from(spans));
         });
         self.list = Some(list);
         drop(items);
@@ -192,11 +442,9 @@ impl<'a> UI<'a> {
         let icon = devicons::icon_for_file(path, &Some(devicons::Theme::Dark));
 
         let rgb = hex::decode(icon.color.trim_matches('#'));
-        let color = if path.is_dir() || !rgb.is_ok() {
-            app_props.get_theme().get_fg()
-        } else {
-            let rgb = rgb.unwrap();
-            Color::Rgb(rgb[0], rgb[1], rgb[2])
+        let color = match rgb {
+            Ok(rgb) if !path.is_dir() => Color::Rgb(rgb[0], rgb[1], rgb[2]),
+            _ => app_props.get_theme().get_fg(),
         };
 
         let line = Line::from(vec![
@@ -212,15 +460,27 @@ impl<'a> UI<'a> {
         let block = Block::default()
             .borders(Borders::ALL)
             .title(format!(
-                " {} ",
-                app_props.get_current_path().to_string_lossy()
+                " {}{} ",
+                match &app_props.archive {
+                    Some(archive) => archive.get_current_path().to_string_lossy().into_owned(),
+                    None => app_props.get_current_path().to_string_lossy().into_owned(),
+                },
+                match (app_props.get_view_mode(), app_props.flat_depth) {
+                    (ViewMode::List, _) => String::new(),
+                    (ViewMode::Tree, _) => String::from(" [tree]"),
+                    (ViewMode::Flat, None) => String::from(" [flat]"),
+                    (ViewMode::Flat, Some(depth)) => format!(" [flat:{depth}]"),
+                }
             ))
             .style(Style::default().fg(app_props.get_theme().get_fg()))
             .fg(app_props.get_theme().get_fg());
 
-        if app_props.manager.is_searching() {
+        // One extra refresh after loading is done, so the final (sorted) items are shown
+        let loading = app_props.manager.is_searching() || app_props.manager.is_flattening();
+        if loading || self.loading {
             self.set_main_items(app_props);
         }
+        self.loading = loading;
 
         if !self.list.as_ref().unwrap().is_empty() {
             let list = List::new(self.list.clone().unwrap())
@@ -240,6 +500,8 @@ impl<'a> UI<'a> {
             if let Some(relative_nums) = relative_nums {
                 frame.render_stateful_widget(relative_nums, areas[0], app_props.get_ml_state());
             }
+            // Needed by the H/M/L and page motions
+            app_props.list_height = areas[1].height as usize;
             frame.render_stateful_widget(list.clone(), areas[1], app_props.get_ml_state());
         } else {
             let empty_text = if app_props.manager.is_searching() {
@@ -247,6 +509,11 @@ impl<'a> UI<'a> {
                     .style(Style::default().fg(app_props.get_theme().get_pr()))
                     .centered()
                     .block(block)
+            } else if app_props.manager.is_flattening() {
+                Paragraph::new("Flattening...")
+                    .style(Style::default().fg(app_props.get_theme().get_pr()))
+                    .centered()
+                    .block(block)
             } else {
                 Paragraph::new("Directory Empty :(")
                     .style(Style::default().fg(app_props.get_theme().get_pr()))
@@ -271,7 +538,7 @@ impl<'a> UI<'a> {
             .fg(app_props.get_theme().get_fg());
 
         let val = if app_props.get_mode().eq(&AppMode::Search) {
-            app_props.search_input.get_value().clone() + "|"
+            app_props.search_input.with_cursor()
         } else {
             app_props.search_input.get_value().clone()
         };
@@ -281,7 +548,7 @@ impl<'a> UI<'a> {
         frame.render_widget(input, area);
     }
 
-    pub fn generate_navbar(
+    pub fn generate_command_line(
         &mut self,
         app_props: &mut AppProperties,
         frame: &mut Frame,
@@ -289,14 +556,14 @@ impl<'a> UI<'a> {
     ) {
         let block = Block::default()
             .borders(Borders::ALL)
-            .title(String::from(" Teleport to Realm "))
+            .title(String::from(" Cast an Incantation "))
             .style(Style::default().fg(app_props.get_theme().get_fg()))
             .fg(app_props.get_theme().get_fg());
 
-        let val = if app_props.get_mode().eq(&AppMode::Navigate) {
-            app_props.nav_input.get_value().clone() + "|"
+        let val = if app_props.get_mode().eq(&AppMode::Command) {
+            app_props.command_input.with_cursor()
         } else {
-            app_props.nav_input.get_value().clone()
+            app_props.command_input.get_value().clone()
         };
         let input = Text::from(val);
         let input = Paragraph::new(input).block(block);
@@ -306,12 +573,7 @@ impl<'a> UI<'a> {
 
     //TODO: For some reason the preview starts flashing during search
     fn generate_preview(&mut self, app_props: &mut AppProperties, frame: &mut Frame, area: Rect) {
-        let block = Block::default()
-            .borders(Borders::ALL)
-            .title(format!(" Revelation ",))
-            .title_alignment(ratatui::layout::Alignment::Center)
-            .style(Style::default().fg(app_props.get_theme().get_fg()))
-            .fg(app_props.get_theme().get_fg());
+        let block = Self::preview_block(app_props, String::from(" Revelation "));
 
         if let (None, _) = &app_props.cursor {
             let empty_text = Paragraph::new("Nothing to reveal :(")
@@ -321,26 +583,107 @@ impl<'a> UI<'a> {
             frame.render_widget(empty_text, area);
             return;
         }
-        let (path, metadata) = &app_props.cursor;
+        let (path, _metadata) = &app_props.cursor;
         let path = path.as_ref().unwrap();
 
-        // This draws every frame, gotta fix that
-        if path.is_file() {
-            let file_content = app_props.manager.read_file(&path);
-
-            if let Ok(file_content) = file_content {
-                let paragraph = Paragraph::new(file_content)
-                    .style(Style::default())
-                    .fg(app_props.get_theme().get_fg())
-                    .alignment(ratatui::layout::Alignment::Left)
-                    .wrap(Wrap { trim: true })
-                    .block(block);
-                frame.render_widget(paragraph, area);
-                return;
+        let archive = app_props.archive.as_ref();
+        if path.is_file() || archive.is_some_and(|a| a.get_file(path).is_some()) {
+            let preview = app_props.previewer.get(path);
+            match preview.as_deref() {
+                Some(Preview::Text(text)) => {
+                    let title = format!(" Revelation · {} ", Self::text_info(text));
+                    let block = Self::preview_block(app_props, title);
+                    let lines = self.text_preview_lines(app_props, &text.lines);
+                    let paragraph = Paragraph::new(lines)
+                        .style(Style::default())
+                        .fg(app_props.get_theme().get_fg())
+                        .alignment(ratatui::layout::Alignment::Left)
+                        .wrap(Wrap { trim: false })
+                        .block(block);
+                    frame.render_widget(paragraph, area);
+                    return;
+                }
+                Some(Preview::Hex(hex)) => {
+                    let title = format!(" Revelation · {} ", Self::hex_info(hex));
+                    let block = Self::preview_block(app_props, title);
+                    let per_row = hexdump::bytes_per_row(area.width.saturating_sub(2));
+                    let mut lines = Self::metadata_lines(app_props, &hex.metadata);
+                    let rows = (area.height.saturating_sub(2) as usize).saturating_sub(lines.len());
+                    lines.extend(Self::hex_lines(app_props, &hex.bytes, per_row, 0, rows));
+                    frame.render_widget(Paragraph::new(lines).block(block), area);
+                    return;
+                }
+                Some(Preview::Image(image)) => {
+                    let title = format!(" Revelation · {} ", Self::image_info(image));
+                    let block = Self::preview_block(app_props, title);
+                    let preview = preview.clone().unwrap();
+                    self.generate_picture(app_props, frame, area, block, preview);
+                    return;
+                }
+                Some(Preview::Archive(archive)) => {
+                    let title = format!(" Revelation · {} ", Self::archive_info(archive));
+                    let block = Self::preview_block(app_props, title);
+                    let rows = area.height.saturating_sub(2) as usize;
+                    let lines = Self::archive_lines(app_props, archive, 0, rows);
+                    frame.render_widget(Paragraph::new(lines).block(block), area);
+                    return;
+                }
+                Some(Preview::Data(data)) => {
+                    let title = format!(" Revelation · {} ", Self::data_info(data));
+                    let block = Self::preview_block(app_props, title);
+                    let mut lines = Self::data_summary(app_props, data);
+                    lines.extend(self.text_preview_lines(app_props, &data.lines));
+                    let paragraph = Paragraph::new(lines)
+                        .fg(app_props.get_theme().get_fg())
+                        .wrap(Wrap { trim: false })
+                        .block(block);
+                    frame.render_widget(paragraph, area);
+                    return;
+                }
+                Some(Preview::Markdown(markdown)) => {
+                    let title = format!(
+                        " Revelation · {} ",
+                        Self::markdown_info(app_props, markdown)
+                    );
+                    let block = Self::preview_block(app_props, title);
+                    let lines = self.markdown_lines(app_props, markdown);
+                    let paragraph = Paragraph::new(lines)
+                        .fg(app_props.get_theme().get_fg())
+                        .wrap(Wrap { trim: false })
+                        .block(block);
+                    frame.render_widget(paragraph, area);
+                    return;
+                }
+                Some(Preview::Table(table)) => {
+                    let title = format!(" Revelation · {} ", Self::table_info(table));
+                    let block = Self::preview_block(app_props, title);
+                    Self::generate_table(app_props, frame, area, block, table, 0);
+                    return;
+                }
+                Some(Preview::Database(database)) => {
+                    let title = format!(" Revelation · {} ", Self::database_info(database));
+                    let block = Self::preview_block(app_props, title);
+                    let lines = Self::database_lines(app_props, database);
+                    let paragraph = Paragraph::new(lines)
+                        .wrap(Wrap { trim: false })
+                        .block(block);
+                    frame.render_widget(paragraph, area);
+                    return;
+                }
+                Some(Preview::Unsupported(_)) => {}
+                // Still being built, the worker is quick enough that a blank frame beats a
+                // flashing placeholder
+                None => {
+                    frame.render_widget(block, area);
+                    return;
+                }
             }
-        } else if path.is_dir() {
+        } else if app_props.is_dir(path) {
             //This could be added to another function so it can be reused
-            let directory = app_props.manager.read_dir(&path, OpenOption::Preview);
+            let directory = match archive.filter(|a| a.is_dir(path)) {
+                Some(archive) => Ok(archive.read_dir(path)),
+                None => app_props.manager.read_dir(path, OpenOption::Preview),
+            };
             if let Ok(directory) = directory {
                 if directory.is_empty() {
                     // Refactor this later, cuz 'empty text' gets generated too manny times.
@@ -354,21 +697,19 @@ impl<'a> UI<'a> {
                 let mut list: Vec<Line> = Vec::new();
                 directory.iter().for_each(|i| {
                     let name = i.file_name().unwrap().to_string_lossy().into_owned();
-                    let icon = devicons::icon_for_file(i, &Some(devicons::Theme::Dark));
+                    let icon = item_icon(app_props, i);
 
                     let rgb = hex::decode(icon.color.trim_matches('#'));
-                    let color = if i.is_dir() || !rgb.is_ok() {
-                        app_props.get_theme().get_fg()
-                    } else {
-                        let rgb = rgb.unwrap();
-                        Color::Rgb(rgb[0], rgb[1], rgb[2])
+                    let color = match rgb {
+                        Ok(rgb) if !app_props.is_dir(i) => Color::Rgb(rgb[0], rgb[1], rgb[2]),
+                        _ => app_props.get_theme().get_fg(),
                     };
 
                     let line = Line::from(vec![
                         Span::styled(format!("{} ", icon.icon), Style::default().fg(color)),
                         Span::from(name),
                     ]);
-                    list.push(Line::from(line));
+                    list.push(line);
                 });
                 let list = List::new(list)
                     .style(Style::default().fg(app_props.get_theme().get_fg()))
@@ -388,11 +729,766 @@ impl<'a> UI<'a> {
         frame.render_widget(empty_text, area);
     }
 
-    /// Generates the background for the current frame
-    fn generate_background(app_props: &AppProperties, frame: &mut Frame) {
-        let background =
-            Block::default().style(Style::default().bg(app_props.get_theme().get_bg()));
-        frame.render_widget(background, frame.area());
+    /// Shows the preview of the cursor item over the whole screen, scrolled by *viewer_scroll*
+    fn generate_viewer(&mut self, app_props: &mut AppProperties, frame: &mut Frame) {
+        let area = frame.area();
+        let Some(path) = app_props.cursor.0.clone() else {
+            return;
+        };
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let height = area.height.saturating_sub(2) as usize;
+        app_props.viewer_height = height;
+
+        let preview = app_props.previewer.get(&path);
+        let (title, lines, total) = match preview.as_deref() {
+            Some(Preview::Text(text)) => {
+                let title = format!(" Gaze · {name} · {} ", Self::text_info(text));
+                let lines = self.text_preview_lines(app_props, &text.lines);
+                let total = lines.len();
+                let lines = lines.into_iter().skip(app_props.viewer_scroll).collect();
+                (title, lines, total)
+            }
+            Some(Preview::Hex(hex)) => {
+                let title = format!(" Gaze · {name} · {} ", Self::hex_info(hex));
+                let per_row = hexdump::bytes_per_row(area.width.saturating_sub(2));
+                // The metadata scrolls away above the dump
+                let metadata = Self::metadata_lines(app_props, &hex.metadata);
+                let total = metadata.len() + hex.bytes.len().div_ceil(per_row);
+                let first = app_props.viewer_scroll.min(total.saturating_sub(height));
+                let first_row = first.saturating_sub(metadata.len());
+                let mut lines: Vec<Line> = metadata.into_iter().skip(first).collect();
+                let rows = height.saturating_sub(lines.len());
+                lines.extend(Self::hex_lines(
+                    app_props, &hex.bytes, per_row, first_row, rows,
+                ));
+                (title, lines, total)
+            }
+            Some(Preview::Image(image)) => {
+                let title = format!(" Gaze · {name} · {} ", Self::image_info(image));
+                let block = Self::preview_block(app_props, title);
+                let preview = preview.clone().unwrap();
+                self.generate_picture(app_props, frame, area, block, preview);
+                return;
+            }
+            Some(Preview::Archive(archive)) => {
+                let title = format!(" Gaze · {name} · {} ", Self::archive_info(archive));
+                let total = archive.entries.len();
+                let first = app_props.viewer_scroll.min(total.saturating_sub(height));
+                let lines = Self::archive_lines(app_props, archive, first, height);
+                (title, lines, total)
+            }
+            Some(Preview::Data(data)) => {
+                let title = format!(" Gaze · {name} · {} ", Self::data_info(data));
+                match &data.document {
+                    Ok(document) if !app_props.viewer_source => {
+                        let rows = document.rows(&app_props.tree_expanded);
+                        let total = rows.len();
+                        let cursor = app_props.tree_cursor.min(total.saturating_sub(1));
+                        app_props.tree_cursor = cursor;
+                        // Scroll just enough to keep the cursor in sight
+                        let first = app_props
+                            .viewer_scroll
+                            .clamp((cursor + 1).saturating_sub(height), cursor)
+                            .min(total.saturating_sub(height));
+                        app_props.viewer_scroll = first;
+                        let lines = rows
+                            .iter()
+                            .enumerate()
+                            .skip(first)
+                            .take(height)
+                            .map(|(idx, row)| {
+                                let line = Self::tree_line(app_props, row);
+                                if idx == cursor { line.reversed() } else { line }
+                            })
+                            .collect();
+                        (title, lines, total)
+                    }
+                    _ => {
+                        let mut lines = Self::data_summary(app_props, data);
+                        if data.document.is_ok() {
+                            lines.clear();
+                        }
+                        lines.extend(self.text_preview_lines(app_props, &data.lines));
+                        let total = lines.len();
+                        let lines = lines.into_iter().skip(app_props.viewer_scroll).collect();
+                        (title, lines, total)
+                    }
+                }
+            }
+            Some(Preview::Markdown(markdown)) => {
+                let info = Self::markdown_info(app_props, markdown);
+                let title = format!(" Gaze · {name} · {info} ");
+                let lines = self.markdown_lines(app_props, markdown);
+                let total = lines.len();
+                let lines = lines.into_iter().skip(app_props.viewer_scroll).collect();
+                (title, lines, total)
+            }
+            Some(Preview::Table(table)) => {
+                let title = format!(" Gaze · {name} · {} ", Self::table_info(table));
+                let block = Self::preview_block(app_props, title);
+                // The header and the error take a line each
+                let rows = height.saturating_sub(1 + usize::from(table.error.is_some()));
+                let first = app_props
+                    .viewer_scroll
+                    .min(table.rows.len().saturating_sub(rows));
+                app_props.viewer_scroll = first;
+                Self::generate_table(app_props, frame, area, block, table, first);
+                return;
+            }
+            Some(Preview::Database(database)) => {
+                let title = format!(" Gaze · {name} · {} ", Self::database_info(database));
+                let lines = Self::database_lines(app_props, database);
+                let total = lines.len();
+                let lines = lines.into_iter().skip(app_props.viewer_scroll).collect();
+                (title, lines, total)
+            }
+            Some(Preview::Unsupported(reason)) => (
+                format!(" Gaze · {name} "),
+                vec![Line::from(reason.clone())],
+                1,
+            ),
+            None => (format!(" Gaze · {name} "), Vec::new(), 0),
+        };
+        // Keep the scroll inside the content, so scrolling back up reacts right away
+        app_props.viewer_scroll = app_props.viewer_scroll.min(total.saturating_sub(height));
+
+        let block = Self::preview_block(app_props, title);
+        frame.render_widget(Paragraph::new(lines).block(block), area);
+    }
+
+    /// Describes a text file for the titles: syntax, encoding and line endings
+    fn text_info(text: &TextPreview) -> String {
+        let mut info: Vec<&str> = text.syntax.iter().map(String::as_str).collect();
+        info.push(text.encoding);
+        info.extend(text.line_ending);
+        info.join(" · ")
+    }
+
+    /// Describes an archive for the titles: format, entry count and unpacked size
+    fn archive_info(archive: &ArchivePreview) -> String {
+        format!(
+            "{} · {} entries · {}",
+            archive.format.get_name(),
+            archive.entries.len(),
+            archive.total.byte_display()
+        )
+    }
+
+    /// Lists *rows* entries of an archive starting at *first*, files with their size
+    fn archive_lines<'b>(
+        app_props: &AppProperties,
+        archive: &ArchivePreview,
+        first: usize,
+        rows: usize,
+    ) -> Vec<Line<'b>> {
+        let theme = app_props.get_theme();
+        archive
+            .entries
+            .iter()
+            .skip(first)
+            .take(rows)
+            .map(|entry| {
+                let path = entry.path.to_string_lossy();
+                let (size, name, color) = if entry.is_dir {
+                    (String::new(), format!("{path}/"), theme.get_ht())
+                } else {
+                    (entry.size.byte_display(), path.into_owned(), theme.get_fg())
+                };
+                Line::from(vec![
+                    Span::styled(format!("{size:>10}  "), Style::default().fg(theme.get_s3())),
+                    Span::styled(name, Style::default().fg(color)),
+                ])
+            })
+            .collect()
+    }
+
+    /// Describes a data file for the titles: format and the size of the document
+    fn data_info(data: &DataPreview) -> String {
+        let name = data.format.get_name();
+        match &data.document {
+            Ok(value) if value.is_container() => format!(
+                "{name} · {}",
+                value.summary().trim_matches(['{', '}', '[', ']'])
+            ),
+            Ok(_) => name.to_string(),
+            Err(_) => format!("{name} · parse error"),
+        }
+    }
+
+    /// The lines above the text of a data file: the top-level keys with their values
+    /// summarised, or where parsing failed
+    fn data_summary<'b>(app_props: &AppProperties, data: &DataPreview) -> Vec<Line<'b>> {
+        let theme = app_props.get_theme();
+        let mut lines = match &data.document {
+            Ok(document) if document.is_container() => {
+                let rows = document.rows(&HashSet::new());
+                let mut lines: Vec<Line> = rows
+                    .iter()
+                    .take(SUMMARY_ROWS)
+                    .map(|row| Self::tree_line(app_props, row))
+                    .collect();
+                if rows.len() > SUMMARY_ROWS {
+                    lines.push(Line::styled(
+                        format!("  … {} more", rows.len() - SUMMARY_ROWS),
+                        Style::default().fg(theme.get_s3()),
+                    ));
+                }
+                lines
+            }
+            Ok(_) => return Vec::new(),
+            Err(error) => vec![Self::parse_error_line(app_props, error)],
+        };
+        lines.push(Line::from(""));
+        lines
+    }
+
+    /// A node of the key tree: indented by depth, with ▸ ▾ on the ones that can be expanded
+    fn tree_line<'b>(app_props: &AppProperties, row: &TreeRow) -> Line<'b> {
+        let theme = app_props.get_theme();
+        let marker = match (row.value.is_container(), row.expanded) {
+            (false, _) => "  ",
+            (true, false) => "▸ ",
+            (true, true) => "▾ ",
+        };
+        let color = match row.value {
+            Value::String(_) => theme.get_st(),
+            Value::Array(_) | Value::Object(_) | Value::Documents(_) => theme.get_s3(),
+            _ => theme.get_pr(),
+        };
+        let mut spans = vec![Span::from(format!("{}{marker}", "  ".repeat(row.depth)))];
+        if !row.key.is_empty() {
+            spans.push(Span::styled(
+                row.key.clone(),
+                Style::default().fg(theme.get_ht()),
+            ));
+            spans.push(Span::from(": "));
+        }
+        spans.push(Span::styled(
+            row.value.summary(),
+            Style::default().fg(color),
+        ));
+        Line::from(spans)
+    }
+
+    fn parse_error_line<'b>(app_props: &AppProperties, error: &ParseError) -> Line<'b> {
+        Line::styled(
+            format!("✗ {error}"),
+            Style::default().fg(app_props.get_theme().get_pr()),
+        )
+    }
+
+    /// Describes a Markdown file for the titles, and whether it is rendered
+    fn markdown_info(app_props: &AppProperties, markdown: &MarkdownPreview) -> String {
+        let shown = if app_props.markdown_source {
+            "source"
+        } else {
+            "rendered"
+        };
+        format!("{} · {shown}", Self::text_info(&markdown.source))
+    }
+
+    /// The rendered document, or its source with *markdown_source*
+    fn markdown_lines(
+        &self,
+        app_props: &AppProperties,
+        markdown: &MarkdownPreview,
+    ) -> Vec<Line<'_>> {
+        if app_props.markdown_source {
+            return self.text_preview_lines(app_props, &markdown.source.lines);
+        }
+        let theme = app_props.get_theme();
+        markdown
+            .rendered
+            .iter()
+            .map(|pieces| {
+                Line::from(
+                    pieces
+                        .iter()
+                        .map(|(style, piece)| {
+                            Span::styled(piece.clone(), markdown_style(theme, *style))
+                        })
+                        .collect::<Vec<Span>>(),
+                )
+            })
+            .collect()
+    }
+
+    /// Describes a table for the titles: format, rows and columns
+    fn table_info(table: &TablePreview) -> String {
+        format!(
+            "{} · {} rows × {} columns",
+            table.get_name(),
+            table.total,
+            table.columns
+        )
+    }
+
+    /// Draws the rows of a table from *first* on inside *block*, under the header row. Columns
+    /// are as wide as their widest field, up to *TABLE_COLUMN_WIDTH*
+    fn generate_table(
+        app_props: &AppProperties,
+        frame: &mut Frame,
+        area: Rect,
+        block: Block,
+        table: &TablePreview,
+        first: usize,
+    ) {
+        let mut inner = block.inner(area);
+        frame.render_widget(block, area);
+        if let Some(error) = &table.error {
+            let [line, rest] =
+                Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(inner);
+            frame.render_widget(Self::parse_error_line(app_props, error), line);
+            inner = rest;
+        }
+
+        let widths = Self::column_widths(&table.header, &table.rows, table.columns);
+        let first = first.min(table.rows.len());
+        let last = (first + inner.height as usize).min(table.rows.len());
+        let widget = Self::table_widget(app_props, &table.header, &table.rows[first..last], widths);
+        frame.render_widget(widget, inner);
+    }
+
+    /// Makes every column as wide as its widest field, up to *TABLE_COLUMN_WIDTH*
+    fn column_widths(header: &[String], rows: &[Vec<String>], columns: usize) -> Vec<Constraint> {
+        (0..columns)
+            .map(|column| {
+                let width = std::iter::once(header)
+                    .chain(rows.iter().map(Vec::as_slice))
+                    .filter_map(|row| row.get(column))
+                    .map(|field| field.chars().count())
+                    .max()
+                    .unwrap_or(0);
+                Constraint::Length(width.clamp(1, TABLE_COLUMN_WIDTH) as u16)
+            })
+            .collect()
+    }
+
+    /// The rows of a table under a bold header row
+    fn table_widget<'b>(
+        app_props: &AppProperties,
+        header: &[String],
+        rows: &[Vec<String>],
+        widths: Vec<Constraint>,
+    ) -> Table<'b> {
+        let theme = app_props.get_theme();
+        let header = Row::new(header.to_vec()).style(Style::default().fg(theme.get_ht()).bold());
+        Table::new(rows.iter().map(|row| Row::new(row.clone())), widths)
+            .header(header)
+            .column_spacing(2)
+            .style(Style::default().fg(theme.get_fg()))
+    }
+
+    /// Describes a database for the titles: how many tables and views it has
+    fn database_info(database: &DatabasePreview) -> String {
+        let views = database.tables.iter().filter(|table| table.is_view).count();
+        let mut info = format!(
+            "SQLite · {}",
+            data::count(database.tables.len() - views, "table")
+        );
+        if views > 0 {
+            info.push_str(&format!(" · {}", data::count(views, "view")));
+        }
+        info
+    }
+
+    /// Lists the tables of a database with their row counts, each above its statement
+    fn database_lines<'b>(app_props: &AppProperties, database: &DatabasePreview) -> Vec<Line<'b>> {
+        let theme = app_props.get_theme();
+        let mut lines = Vec::new();
+        for (table, schema) in database.tables.iter().zip(&database.schemas) {
+            // Views aren't counted
+            let rows = match table.rows {
+                _ if table.is_view => String::from("view"),
+                Some(rows) => data::count(rows as usize, "row"),
+                None => String::from("rows unknown"),
+            };
+            lines.push(Line::from(vec![
+                Span::styled(
+                    table.name.clone(),
+                    Style::default().fg(theme.get_ht()).bold(),
+                ),
+                Span::styled(format!("  {rows}"), Style::default().fg(theme.get_s3())),
+            ]));
+            lines.extend(schema.iter().map(|tokens| {
+                Line::from(
+                    tokens
+                        .iter()
+                        .map(|(token, piece)| {
+                            Span::styled(piece.clone(), token_style(theme, *token))
+                        })
+                        .collect::<Vec<Span>>(),
+                )
+            }));
+            lines.push(Line::from(""));
+        }
+        lines
+    }
+
+    /// Shows a page of rows of the browsed database table over the whole screen, from
+    /// *viewer_scroll* and *viewer_column* on, under a line with the table names
+    fn generate_database(&mut self, app_props: &mut AppProperties, frame: &mut Frame) {
+        let area = frame.area();
+        let Some(browser) = app_props.database.as_mut() else {
+            return;
+        };
+        // The table names and the column names take a line each
+        let height = area.height.saturating_sub(4) as usize;
+        app_props.viewer_height = height;
+        let total = browser.get_row_count().map(|rows| rows as usize);
+        let first = app_props
+            .viewer_scroll
+            .min(total.unwrap_or(usize::MAX).saturating_sub(height));
+        app_props.viewer_scroll = first;
+        let page = browser.page(first, height).cloned();
+        let name = browser.get_path().file_name().unwrap_or_default();
+        let name = name.to_string_lossy().into_owned();
+        let tables = browser.get_tables().to_vec();
+        let selected = browser.get_table_index();
+
+        let rows = match (&page, total) {
+            (Ok(page), _) if page.rows.is_empty() => String::from("empty"),
+            (Ok(page), Some(total)) => {
+                format!("rows {}–{} of {total}", first + 1, first + page.rows.len())
+            }
+            (Ok(page), None) => format!("rows {}–{}", first + 1, first + page.rows.len()),
+            (Err(_), _) => String::from("unreadable"),
+        };
+        let title = format!(" Tome · {name} · {} · {rows} ", tables[selected].name);
+        let block = Self::preview_block(app_props, title);
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+        let [names, rest] =
+            Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(inner);
+        frame.render_widget(
+            Self::table_names(app_props, &tables, selected, names.width as usize),
+            names,
+        );
+
+        match page {
+            Ok(page) => {
+                let column = app_props
+                    .viewer_column
+                    .min(page.columns.len().saturating_sub(1));
+                app_props.viewer_column = column;
+                let header = &page.columns[column..];
+                let rows: Vec<Vec<String>> = (page.rows.into_iter())
+                    .map(|row| row.into_iter().skip(column).collect())
+                    .collect();
+                let widths = Self::column_widths(header, &rows, header.len());
+                let widget = Self::table_widget(app_props, header, &rows, widths);
+                frame.render_widget(widget, rest);
+            }
+            Err(e) => {
+                let line = Line::styled(
+                    format!("✗ {e}"),
+                    Style::default().fg(app_props.get_theme().get_pr()),
+                );
+                frame.render_widget(line, rest);
+            }
+        }
+    }
+
+    /// The names of the tables in a line, the *selected* one highlighted and in sight
+    fn table_names<'b>(
+        app_props: &AppProperties,
+        tables: &[database::Table],
+        selected: usize,
+        width: usize,
+    ) -> Line<'b> {
+        const SEPARATOR: &str = " │ ";
+        let theme = app_props.get_theme();
+        let mut first = selected;
+        let mut used = tables[selected].name.chars().count();
+        while first > 0 {
+            used += tables[first - 1].name.chars().count() + SEPARATOR.chars().count();
+            if used > width {
+                break;
+            }
+            first -= 1;
+        }
+
+        let mut spans = Vec::new();
+        for (idx, table) in tables.iter().enumerate().skip(first) {
+            if idx > first {
+                spans.push(Span::styled(SEPARATOR, Style::default().fg(theme.get_s3())));
+            }
+            let style = match (idx == selected, table.is_view) {
+                (true, _) => Style::default().fg(theme.get_ht()).bold().reversed(),
+                (false, true) => Style::default().fg(theme.get_s3()).italic(),
+                (false, false) => Style::default().fg(theme.get_fg()),
+            };
+            spans.push(Span::styled(table.name.clone(), style));
+        }
+        Line::from(spans)
+    }
+
+    /// Describes an image for the titles: format and dimensions
+    fn image_info(image: &ImagePreview) -> String {
+        format!("{} · {}×{}", image.format, image.width, image.height)
+    }
+
+    /// Draws an image inside *block*. Half-blocks are rendered right away, kitty and sixel
+    /// images only get room made for them and are drawn by *draw_graphics* after the frame
+    fn generate_picture(
+        &mut self,
+        app_props: &AppProperties,
+        frame: &mut Frame,
+        area: Rect,
+        block: Block,
+        preview: Arc<Preview>,
+    ) {
+        let mut inner = block.inner(area);
+        frame.render_widget(block, area);
+        // The EXIF data goes under the picture, taking at most half of the room
+        if let Preview::Image(image) = preview.as_ref()
+            && !image.exif.is_empty()
+        {
+            let mut lines = Self::metadata_lines(app_props, &image.exif);
+            // The blank line goes between the picture and the table
+            lines.pop();
+            lines.insert(0, Line::from(""));
+            let height = (lines.len() as u16).min(inner.height / 2);
+            let [picture, fields] =
+                Layout::vertical([Constraint::Min(0), Constraint::Length(height)]).areas(inner);
+            frame.render_widget(Paragraph::new(lines), fields);
+            inner = picture;
+        }
+        if app_props.graphics != Protocol::HalfBlocks {
+            self.pending_graphics = Some((inner, preview));
+            return;
+        }
+
+        let background = rgb(app_props.get_theme().get_bg());
+        let key = (preview, inner.width, inner.height, background);
+        let cached = self
+            .picture
+            .as_ref()
+            .is_some_and(|((cached, w, h, bg), _)| {
+                Arc::ptr_eq(cached, &key.0) && (*w, *h, *bg) == (key.1, key.2, key.3)
+            });
+        if !cached {
+            let Preview::Image(image) = key.0.as_ref() else {
+                return;
+            };
+            let lines =
+                picture::half_blocks(&image.thumbnail, inner.width, inner.height, background)
+                    .into_iter()
+                    .map(|row| {
+                        let cells: Vec<Span> = row
+                            .into_iter()
+                            .map(|(top, bottom)| {
+                                Span::styled(
+                                    "▀",
+                                    Style::default()
+                                        .fg(Color::Rgb(top.0, top.1, top.2))
+                                        .bg(Color::Rgb(bottom.0, bottom.1, bottom.2)),
+                                )
+                            })
+                            .collect();
+                        Line::from(cells)
+                    })
+                    .collect();
+            self.picture = Some((key, lines));
+        }
+        if let Some((_, lines)) = &self.picture {
+            frame.render_widget(Paragraph::new(lines.clone()), inner);
+        }
+    }
+
+    /// Draws the image the last frame made room for with the kitty or sixel protocol. This runs
+    /// after the frame is flushed, ratatui doesn't know about these escape sequences
+    pub fn draw_graphics(
+        &mut self,
+        terminal: &mut DefaultTerminal,
+        app_props: &AppProperties,
+    ) -> io::Result<()> {
+        let pending = self.pending_graphics.take();
+        let unchanged = match (&pending, &self.shown_graphics) {
+            (Some((area, preview)), Some((shown_area, shown, protocol))) => {
+                area == shown_area && Arc::ptr_eq(preview, shown) && *protocol == app_props.graphics
+            }
+            (None, None) => true,
+            _ => false,
+        };
+        if unchanged {
+            return Ok(());
+        }
+
+        if let Some((_, _, protocol)) = self.shown_graphics.take() {
+            if protocol == Protocol::Kitty {
+                execute!(terminal.backend_mut(), Print(graphics::KITTY_CLEAR))?;
+            } else {
+                // Sixels are plain cells to the terminal, redrawing every cell wipes them. The
+                // next image follows on the next frame
+                terminal.clear()?;
+                return Ok(());
+            }
+        }
+
+        let Some((area, preview)) = pending else {
+            return Ok(());
+        };
+        if app_props.graphics == Protocol::HalfBlocks {
+            return Ok(());
+        }
+        // Until the worker is done nothing is shown, the next frames ask again
+        let background = rgb(app_props.get_theme().get_bg());
+        let request = (
+            Arc::clone(&preview),
+            area.width,
+            area.height,
+            app_props.graphics,
+            background,
+        );
+        let Some(sequence) = self.encoder.get(request) else {
+            return Ok(());
+        };
+        execute!(
+            terminal.backend_mut(),
+            MoveTo(area.x, area.y),
+            Print(sequence.as_str())
+        )?;
+        self.shown_graphics = Some((area, preview, app_props.graphics));
+        Ok(())
+    }
+
+    /// Removes a kitty image before the app exits
+    pub fn clear_graphics(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        if let Some((_, _, Protocol::Kitty)) = self.shown_graphics.take() {
+            execute!(terminal.backend_mut(), Print(graphics::KITTY_CLEAR))?;
+        }
+        Ok(())
+    }
+
+    /// The screen got wiped from outside, nothing is shown anymore
+    pub fn forget_graphics(&mut self) {
+        self.shown_graphics = None;
+    }
+
+    /// Describes a binary file for the titles, with the format from its header
+    fn hex_info(hex: &HexPreview) -> String {
+        format!(
+            "{} · {}",
+            hex.kind.unwrap_or("binary"),
+            hex.size.byte_display()
+        )
+    }
+
+    /// The metadata of a file as a table of names and values, with a blank line under it
+    fn metadata_lines<'b>(app_props: &AppProperties, fields: &[Field]) -> Vec<Line<'b>> {
+        if fields.is_empty() {
+            return Vec::new();
+        }
+        let theme = app_props.get_theme();
+        let width = fields
+            .iter()
+            .map(|(name, _)| name.chars().count())
+            .max()
+            .unwrap_or(0);
+        let mut lines: Vec<Line> = fields
+            .iter()
+            .map(|(name, value)| {
+                Line::from(vec![
+                    Span::styled(
+                        format!("{name:<width$}  "),
+                        Style::default().fg(theme.get_ht()),
+                    ),
+                    Span::styled(value.clone(), Style::default().fg(theme.get_fg())),
+                ])
+            })
+            .collect();
+        lines.push(Line::from(""));
+        lines
+    }
+
+    /// Dumps *rows* rows of *per_row* bytes, starting at row *first*: the offset, the bytes in
+    /// hex and an ASCII gutter
+    fn hex_lines<'b>(
+        app_props: &AppProperties,
+        bytes: &[u8],
+        per_row: usize,
+        first: usize,
+        rows: usize,
+    ) -> Vec<Line<'b>> {
+        let theme = app_props.get_theme();
+        let color = |byte: u8| match hexdump::classify(byte) {
+            ByteClass::Null => theme.get_s3(),
+            ByteClass::Printable => theme.get_fg(),
+            ByteClass::Whitespace => theme.get_st(),
+            ByteClass::Control => theme.get_pr(),
+            ByteClass::High => theme.get_ht(),
+        };
+
+        bytes
+            .chunks(per_row)
+            .enumerate()
+            .skip(first)
+            .take(rows)
+            .map(|(row, chunk)| {
+                let mut spans = vec![Span::styled(
+                    format!("{:08x} ", row * per_row),
+                    Style::default().fg(theme.get_s3()),
+                )];
+                for (idx, byte) in chunk.iter().enumerate() {
+                    let gap = if idx > 0 && idx % 8 == 0 { " " } else { "" };
+                    spans.push(Span::styled(
+                        format!("{gap}{byte:02x} "),
+                        Style::default().fg(color(*byte)),
+                    ));
+                }
+                // Short last row, keep the gutter in line
+                let missing = per_row - chunk.len();
+                let gaps = (per_row - 1) / 8 - chunk.len().saturating_sub(1) / 8;
+                spans.push(Span::from(" ".repeat(missing * 3 + gaps + 1)));
+                for byte in chunk {
+                    spans.push(Span::styled(
+                        hexdump::gutter_char(*byte).to_string(),
+                        Style::default().fg(color(*byte)),
+                    ));
+                }
+                Line::from(spans)
+            })
+            .collect()
+    }
+
+    fn preview_block<'b>(app_props: &AppProperties, title: String) -> Block<'b> {
+        Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .title_alignment(ratatui::layout::Alignment::Center)
+            .style(Style::default().fg(app_props.get_theme().get_fg()))
+            .fg(app_props.get_theme().get_fg())
+    }
+
+    /// Turns the highlighted lines of a text preview into styled lines, with line numbers if
+    /// they are turned on
+    fn text_preview_lines(
+        &self,
+        app_props: &AppProperties,
+        lines: &[HighlightedLine],
+    ) -> Vec<Line<'_>> {
+        let theme = app_props.get_theme();
+        let width = lines.len().to_string().len();
+        lines
+            .iter()
+            .enumerate()
+            .map(|(idx, tokens)| {
+                let mut spans = Vec::new();
+                if app_props.line_numbers {
+                    spans.push(Span::styled(
+                        format!("{:>width$} ", idx + 1),
+                        Style::default().fg(theme.get_s3()),
+                    ));
+                }
+                spans.extend(
+                    tokens.iter().map(|(token, piece)| {
+                        Span::styled(piece.clone(), token_style(theme, *token))
+                    }),
+                );
+                Line::from(spans)
+            })
+            .collect()
     }
 
     fn generate_statusbar(&self, app_props: &AppProperties, frame: &mut Frame, area: Rect) {
@@ -403,7 +1499,7 @@ impl<'a> UI<'a> {
             .padding(Padding::horizontal(1));
 
         let cursor = &app_props.cursor;
-        let mode = app_props.mode.to_string();
+        let _mode = app_props.mode.to_string();
         let mut text = String::new();
         if let Some(md) = &cursor.1 {
             let size = md.len().byte_display();
@@ -411,6 +1507,30 @@ impl<'a> UI<'a> {
             let datetime = datetime.format("%Y-%m-%d").to_string();
 
             text.push_str(&format!("{size} {datetime}"));
+        } else if let (Some(archive), Some(path)) = (&app_props.archive, &cursor.0)
+            && let Some(entry) = archive.get_file(path)
+        {
+            text.push_str(&entry.size.byte_display());
+        }
+        if app_props.archive.is_some() {
+            text.push_str(" [read-only]");
+        } else {
+            let sort = app_props.manager.get_sort(app_props.get_current_path());
+            text.push_str(&format!(" [{sort}]"));
+        }
+        if !app_props.pending_keys.is_empty() {
+            text.insert_str(0, &format!("{} ", app_props.pending_keys));
+        }
+        if let Some(register) = app_props.recording {
+            text.insert_str(0, &format!("recording @{register} "));
+        }
+        if app_props.manager.get_filter().is_active() {
+            text.push_str(&format!(" {} hidden", app_props.hidden_count));
+        }
+        if let Some(term) = app_props.get_live_filter()
+            && app_props.mode != AppMode::Filter
+        {
+            text.push_str(&format!(" [filter: {term}]"));
         }
 
         let perms_span = Span::styled(&text, Style::default().fg(app_props.get_theme().get_st()));
@@ -428,11 +1548,17 @@ impl<'a> UI<'a> {
                 mode.to_string(),
                 Style::default().fg(app_props.get_theme().get_ht()),
             ),
-            AppMode::Navigate => Span::styled(
+            AppMode::Command => Span::styled(
                 mode.to_string(),
                 Style::default().fg(app_props.get_theme().get_ht()),
             ),
-            AppMode::Compare | AppMode::Theme => Span::styled(
+            AppMode::Compare
+            | AppMode::Theme
+            | AppMode::Filter
+            | AppMode::Find
+            | AppMode::BulkRename
+            | AppMode::View
+            | AppMode::Database => Span::styled(
                 mode.to_string(),
                 Style::default().fg(app_props.get_theme().get_ht()),
             ),
@@ -441,8 +1567,10 @@ impl<'a> UI<'a> {
         let mut space =
             (area.width as usize - 4).saturating_sub(mode.to_string().len() + text.len());
 
-        let mut edit_span = None;
+        let mut edit_spans = None;
         let mut input_text = String::new();
+        // Byte range of the selected text in input_text
+        let mut selected = None;
 
         if let AppMode::Edit(x) = &mode {
             match x {
@@ -468,40 +1596,84 @@ impl<'a> UI<'a> {
                     }
                 }
                 EditAction::Delete => {
-                    if let (Some(path), _) = &app_props.cursor {
+                    if let Some(path) = app_props.targets.first().or(app_props.cursor.0.as_ref()) {
                         let name = path.file_name().unwrap().to_string_lossy();
-                        input_text.push_str(&format!("[Confirm] Delete [{}]: ", name));
+                        let more = match app_props.targets.len() {
+                            0 | 1 => String::new(),
+                            n => format!(" (+{} more)", n - 1),
+                        };
+                        input_text.push_str(&format!("[Confirm] Delete [{}]{}: ", name, more));
                     }
                 }
+                EditAction::Hide => {
+                    input_text.push_str("[Confirm] Hide items matching: ");
+                }
+                EditAction::ShowOnly => {
+                    input_text.push_str("[Confirm] Only show files matching: ");
+                }
+                EditAction::PatternRename => {
+                    input_text.push_str(&format!(
+                        "[Confirm] Rename {} item(s) with: ",
+                        app_props.targets.len()
+                    ));
+                }
+                EditAction::Compress => {
+                    input_text.push_str(&format!(
+                        "[Confirm] Compress {} item(s) into: ",
+                        app_props.targets.len()
+                    ));
+                }
             }
 
-            input_text.push_str(&app_props.edit_input.get_value());
-            edit_span = Some(Span::styled(
-                input_text.clone(),
-                Style::default().fg(app_props.get_theme().get_mt()),
+            if *x == EditAction::Delete {
+                input_text.push_str(app_props.edit_input.get_value());
+            } else if let Some((start, end)) = app_props.edit_input.get_selection() {
+                selected = Some((input_text.len() + start, input_text.len() + end));
+                input_text.push_str(app_props.edit_input.get_value());
+            } else {
+                input_text.push_str(&app_props.edit_input.with_cursor());
+            }
+        } else if let AppMode::Filter = &mode {
+            input_text.push_str("Filter: ");
+            input_text.push_str(&app_props.filter_input.with_cursor());
+        } else if let AppMode::Find = &mode {
+            input_text.push('/');
+            input_text.push_str(&app_props.find_input.with_cursor());
+        } else if let AppMode::BulkRename = &mode {
+            input_text.push_str(&format!(
+                "[Confirm] Rename {} item(s)? (y/n)",
+                app_props.renames.len()
             ));
+        }
+
+        if !input_text.is_empty() {
+            let style = Style::default().fg(app_props.get_theme().get_mt());
+            edit_spans = Some(match selected {
+                Some((start, end)) => vec![
+                    Span::styled(input_text[..start].to_string(), style),
+                    Span::styled(input_text[start..end].to_string(), style.reversed()),
+                    Span::styled(input_text[end..].to_string(), style),
+                ],
+                None => vec![Span::styled(input_text.clone(), style)],
+            });
             space /= 2;
             space = space.saturating_sub(input_text.len() / 2);
         }
 
         let space1 = Span::styled(" ".repeat(space), Style::default());
-        if input_text.len() % 2 != 0 {
+        if !input_text.len().is_multiple_of(2) {
             space -= 1;
         }
-        if input_text.len() > 0 {
+        if !input_text.is_empty() {
             space += 1;
         }
         let space2 = Span::styled(" ".repeat(space), Style::default());
 
-        let status_line = if edit_span.is_some() {
-            Line::from(vec![
-                mode_span,
-                space1,
-                edit_span.unwrap(),
-                space2,
-                perms_span,
-            ])
-            .style(Style::default())
+        let status_line = if let Some(edit_spans) = edit_spans {
+            let mut spans = vec![mode_span, space1];
+            spans.extend(edit_spans);
+            spans.extend([space2, perms_span]);
+            Line::from(spans).style(Style::default())
         } else {
             Line::from(vec![mode_span, space1, perms_span]).style(Style::default())
         };
@@ -515,7 +1687,13 @@ impl<'a> UI<'a> {
         frame.render_widget(status_line, area);
     }
 
-    fn generate_status_update(&self, app_props: &AppProperties) -> Line {
+    fn generate_status_update(&self, app_props: &AppProperties) -> Line<'_> {
+        if let Some(message) = &app_props.message {
+            return Line::from(message.clone())
+                .style(Style::default().fg(app_props.get_theme().get_pr()))
+                .alignment(Alignment::Center);
+        }
+
         let mut text = String::new();
         if app_props.manager.is_indexing() {
             text.push_str("Indexing");
@@ -526,19 +1704,31 @@ impl<'a> UI<'a> {
             }
             text.push_str("Pondering...");
         }
+        if app_props.manager.is_flattening() {
+            if !text.is_empty() {
+                text.push_str(", ");
+            }
+            text.push_str("Flattening...");
+        }
         if app_props.manager.is_loading() {
             if !text.is_empty() {
                 text.push_str(", ");
             }
             text.push_str("Channeling");
         }
+        for job in app_props.manager.running_jobs() {
+            if !text.is_empty() {
+                text.push_str(", ");
+            }
+            text.push_str(&job);
+        }
 
         Line::from(text)
             .style(Style::default())
             .alignment(Alignment::Center)
     }
 
-    fn generate_relative_nums(&self, app_props: &mut AppProperties) -> Option<List> {
+    fn generate_relative_nums(&self, app_props: &mut AppProperties) -> Option<List<'_>> {
         let idx = app_props.get_ml_state().selected().unwrap_or(0);
         let size = self.list.as_ref()?.len();
         let items: Vec<ListItem> = (0..size)
@@ -562,7 +1752,7 @@ impl<'a> UI<'a> {
     fn generate_symbol(&mut self, app_props: &AppProperties, frame: &mut Frame, area: Rect) {
         let block = Block::default()
             .borders(Borders::ALL)
-            .title(format!(" ቹፕⶴቹዪቹልረ ",))
+            .title(" ቹፕⶴቹዪቹልረ ".to_string())
             .title_alignment(ratatui::layout::Alignment::Center)
             .style(Style::default().fg(app_props.get_theme().get_fg()))
             .fg(app_props.get_theme().get_fg());
@@ -612,7 +1802,7 @@ impl<'a> UI<'a> {
     ) {
         let block = Block::default()
             .borders(Borders::ALL)
-            .title(format!(" Conjure a Spell "))
+            .title(" Conjure a Spell ".to_string())
             .title_alignment(Alignment::Center)
             .style(Style::default().fg(app_props.get_theme().get_fg()))
             .fg(app_props.get_theme().get_fg());
@@ -627,6 +1817,47 @@ impl<'a> UI<'a> {
         frame.render_stateful_widget(list.clone(), area, app_props.get_tl_state());
     }
 
+    /// Lists the planned renames of a bulk rename, old name next to new name
+    fn generate_rename_view(&self, app_props: &mut AppProperties, frame: &mut Frame, area: Rect) {
+        let theme = app_props.get_theme();
+        let name = |path: &PathBuf| {
+            path.file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned()
+        };
+        let rows: Vec<Row> = app_props
+            .renames
+            .iter()
+            .map(|(source, target)| {
+                Row::new(vec![
+                    Cell::from(name(source)),
+                    Cell::from("→"),
+                    Cell::from(name(target)).style(Style::default().fg(theme.get_ht())),
+                ])
+            })
+            .collect();
+
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(" Transmute Names ".to_string())
+            .title_alignment(Alignment::Center)
+            .style(Style::default().fg(theme.get_fg()));
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Fill(1),
+                Constraint::Length(1),
+                Constraint::Fill(1),
+            ],
+        )
+        .column_spacing(1)
+        .row_highlight_style(Style::default().bold())
+        .block(block);
+
+        frame.render_stateful_widget(table, area, &mut app_props.rename_table_state);
+    }
+
     pub fn set_theme_items(&mut self, app_props: &AppProperties) {
         let items = app_props.get_themes();
         //Maybe this could be somehow in a different function or stored as state
@@ -635,7 +1866,7 @@ impl<'a> UI<'a> {
         items.iter().for_each(|i| {
             let name = i.get_name().to_string();
             let line = Line::from(Span::from(name));
-            list.push(Line::from(line));
+            list.push(line);
         });
 
         self.themes = Some(list);
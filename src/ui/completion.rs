@@ -0,0 +1,184 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::filter::fuzzy_match;
+use crate::sort::natural_cmp;
+use crate::xdg;
+
+/// Something the word before the cursor can be completed to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    /// Replaces the word
+    pub text: String,
+    /// Set for files and folders, used for the icon in the popup
+    pub path: Option<PathBuf>,
+}
+
+impl Candidate {
+    /// A candidate that isn't a path, like a command name. A space is added so the next
+    /// argument can be typed right away
+    pub fn word(text: &str) -> Self {
+        Self {
+            text: format!("{text} "),
+            path: None,
+        }
+    }
+
+    /// Returns the text shown in the popup
+    pub fn get_label(&self) -> &str {
+        let text = self.text.trim_end_matches(' ');
+        match &self.path {
+            // Only the name, the folder is already typed
+            Some(_) => {
+                let trimmed = text.trim_end_matches('/');
+                let start = trimmed.rfind('/').map(|idx| idx + 1).unwrap_or(0);
+                &text[start..]
+            }
+            None => text,
+        }
+    }
+}
+
+/// The candidates of a running completion. Tab and Shift-Tab cycle through them, the
+/// selected one is put into the input right away.
+#[derive(Debug)]
+pub struct Completion {
+    /// The text in front of the completed word
+    head: String,
+    /// The text after the cursor, kept as is
+    tail: String,
+    /// The word as it was typed, restored when the completion gets cancelled
+    word: String,
+    candidates: Vec<Candidate>,
+    selected: usize,
+}
+
+impl Completion {
+    pub fn new(head: &str, word: &str, tail: &str, candidates: Vec<Candidate>) -> Self {
+        Self {
+            head: head.to_string(),
+            tail: tail.to_string(),
+            word: word.to_string(),
+            candidates,
+            selected: 0,
+        }
+    }
+
+    pub fn get_candidates(&self) -> &Vec<Candidate> {
+        &self.candidates
+    }
+
+    pub fn get_selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Moves the selection, wrapping around at the ends
+    pub fn cycle(&mut self, forward: bool) {
+        let len = self.candidates.len();
+        if len == 0 {
+            return;
+        }
+        self.selected = if forward {
+            (self.selected + 1) % len
+        } else {
+            (self.selected + len - 1) % len
+        };
+    }
+
+    /// Returns the line with the selected candidate in place, and the cursor position (bytes)
+    pub fn get_line(&self) -> (String, usize) {
+        let text = self
+            .candidates
+            .get(self.selected)
+            .map(|c| c.text.as_str())
+            .unwrap_or(&self.word);
+        let before = format!("{}{}", self.head, text);
+        let cursor = before.len();
+        (before + &self.tail, cursor)
+    }
+
+    /// Returns the line as it was before the completion started, and the cursor position
+    pub fn get_original(&self) -> (String, usize) {
+        let before = format!("{}{}", self.head, self.word);
+        let cursor = before.len();
+        (before + &self.tail, cursor)
+    }
+}
+
+/// Expands a leading ~ and $VAR / ${VAR} references. Unknown variables are left as they are
+pub fn expand(text: &str) -> String {
+    let text = if text == "~" {
+        xdg::home_dir().to_string_lossy().into_owned()
+    } else if let Some(rest) = text.strip_prefix("~/") {
+        format!("{}/{}", xdg::home_dir().to_string_lossy(), rest)
+    } else {
+        text.to_string()
+    };
+
+    let mut expanded = String::new();
+    let mut rest = text.as_str();
+    while let Some(idx) = rest.find('$') {
+        expanded.push_str(&rest[..idx]);
+        rest = &rest[idx + 1..];
+        let (name, after) = if let Some(braced) = rest.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end) => (&braced[..end], &braced[end + 1..]),
+                None => ("", rest),
+            }
+        } else {
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            (&rest[..end], &rest[end..])
+        };
+        match std::env::var(name) {
+            Ok(value) if !name.is_empty() => expanded.push_str(&value),
+            _ => {
+                expanded.push('$');
+                continue;
+            }
+        }
+        rest = after;
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// Returns the files and folders *word* can be completed to. Relative paths start at *current*.
+/// Names match case-insensitively by prefix, with *fuzzy* the fuzzy matches follow those.
+/// Dotfiles are only offered once the name starts with a dot
+pub fn path_candidates(word: &str, current: &Path, dirs_only: bool, fuzzy: bool) -> Vec<Candidate> {
+    let word = expand(word);
+    let (dir_part, name_part) = match word.rfind('/') {
+        Some(idx) => word.split_at(idx + 1),
+        None => ("", word.as_str()),
+    };
+    let Ok(entries) = fs::read_dir(current.join(dir_part)) else {
+        return Vec::new();
+    };
+    let lower = name_part.to_lowercase();
+
+    let mut prefixed = Vec::new();
+    let mut fuzzy_matched = Vec::new();
+    for entry in entries.filter_map(Result::ok) {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let path = entry.path();
+        if (name.starts_with('.') && !name_part.starts_with('.')) || (dirs_only && !path.is_dir()) {
+            continue;
+        }
+        let suffix = if path.is_dir() { "/" } else { "" };
+        let candidate = Candidate {
+            text: format!("{dir_part}{name}{suffix}"),
+            path: Some(path),
+        };
+        if name.to_lowercase().starts_with(&lower) {
+            prefixed.push(candidate);
+        } else if fuzzy && fuzzy_match(name_part, &name).is_some() {
+            fuzzy_matched.push(candidate);
+        }
+    }
+    prefixed.sort_by(|a, b| natural_cmp(&a.text, &b.text));
+    fuzzy_matched.sort_by(|a, b| natural_cmp(&a.text, &b.text));
+    prefixed.extend(fuzzy_matched);
+    prefixed
+}
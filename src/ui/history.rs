@@ -0,0 +1,106 @@
+use std::fs;
+use std::path::PathBuf;
+
+use crate::xdg;
+
+/// Older entries get dropped once a history grows past this
+const MAX_ENTRIES: usize = 500;
+
+/// The submitted values of an input, oldest first. Recall is filtered by the text that was
+/// typed before it started, so typing "src" and going up only visits entries starting with "src".
+#[derive(Debug, Default)]
+pub struct History {
+    name: Option<String>,
+    entries: Vec<String>,
+    pos: Option<usize>,
+    prefix: String,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates the history saved under *name*, submits to it get saved there too
+    pub fn load(name: &str) -> Self {
+        let mut history = History::new();
+        history.name = Some(name.to_string());
+        if let Ok(file) = fs::read_to_string(History::path(name)) {
+            history.entries = file.lines().map(str::to_string).collect();
+        }
+        history
+    }
+
+    fn path(name: &str) -> PathBuf {
+        xdg::state_dir().join("history").join(name)
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let Some(name) = &self.name else {
+            return Ok(());
+        };
+        let path = History::path(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        fs::write(path, self.entries.join("\n") + "\n").map_err(|e| e.to_string())
+    }
+
+    /// Adds an entry as the newest one. An older copy of it gets removed
+    pub fn push(&mut self, entry: &str) {
+        self.reset();
+        // Entries are stored one per line
+        if entry.trim().is_empty() || entry.contains('\n') {
+            return;
+        }
+        self.entries.retain(|e| e != entry);
+        self.entries.push(entry.to_string());
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.remove(0);
+        }
+        _ = self.save();
+    }
+
+    /// Stops the recall, the next older() takes the current text as its prefix again
+    pub fn reset(&mut self) {
+        self.pos = None;
+        self.prefix.clear();
+    }
+
+    /// Returns the next older entry starting with the prefix. *current* becomes the prefix
+    /// when the recall starts
+    pub fn older(&mut self, current: &str) -> Option<&String> {
+        let end = match self.pos {
+            Some(pos) => pos,
+            None => {
+                self.prefix = current.to_string();
+                self.entries.len()
+            }
+        };
+        let pos = self.entries[..end]
+            .iter()
+            .rposition(|entry| entry.starts_with(&self.prefix))?;
+        self.pos = Some(pos);
+        self.entries.get(pos)
+    }
+
+    /// Returns the next newer entry starting with the prefix. Going past the newest one ends
+    /// the recall and gives back the prefix
+    pub fn newer(&mut self) -> Option<String> {
+        let start = self.pos? + 1;
+        match self.entries[start..]
+            .iter()
+            .position(|entry| entry.starts_with(&self.prefix))
+        {
+            Some(offset) => {
+                self.pos = Some(start + offset);
+                Some(self.entries[start + offset].clone())
+            }
+            None => {
+                let prefix = std::mem::take(&mut self.prefix);
+                self.pos = None;
+                Some(prefix)
+            }
+        }
+    }
+}
@@ -1,152 +1,592 @@
-use std::{
-    error::Error,
-    path::{Path, PathBuf},
-};
-
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use ratatui::style::Color;
-use serde::de::value;
+
+use super::completion::{Candidate, Completion};
+use super::history::History;
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum InputType {
-    AppendChar(char),
+    /// Inserts a char at the cursor
+    InsertChar(char),
+    /// Deletes the char before the cursor
     DeleteChar,
+    /// Deletes the char under the cursor
+    DeleteNextChar,
+    /// Moves the cursor in front of the nth char
     SetCursor(usize),
+    MoveLeft,
+    MoveRight,
+    GoToStart,
+    GoToEnd,
     DeletePrevWord,
     DeleteNextWord,
     GoToPrevWord,
     GoToNextWord,
-    AutoComplete(PathBuf),
+    DeleteToStart,
+    DeleteToEnd,
 }
 
+/// A single line text input with a readline style cursor.
 #[derive(Debug)]
 pub struct Input {
     value: String,
+    /// Byte index into value, always on a char boundary
+    cursor: usize,
     color: Color,
+    history: History,
+    completion: Option<Completion>,
+    /// Byte range of the selected text, typing replaces it
+    selection: Option<(usize, usize)>,
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Words are separated by whitespace and path separators, so Ctrl-w removes one path component
+fn is_separator(c: char) -> bool {
+    c.is_whitespace() || c == '/'
 }
 
 impl Input {
     pub fn new() -> Self {
         Self {
             value: String::new(),
+            cursor: 0,
             color: Color::White,
+            history: History::new(),
+            completion: None,
+            selection: None,
         }
     }
 
+    /// Creates an input that keeps a history saved under *name*
+    pub fn with_history(name: &str) -> Self {
+        let mut input = Input::new();
+        input.history = History::load(name);
+        input
+    }
+
     pub fn handle(&mut self, inp_type: InputType) {
+        if let Some((start, end)) = self.selection.take() {
+            match inp_type {
+                InputType::InsertChar(_) => {
+                    self.value.drain(start..end);
+                    self.cursor = start;
+                }
+                InputType::DeleteChar | InputType::DeleteNextChar => {
+                    self.value.drain(start..end);
+                    self.cursor = start;
+                    return;
+                }
+                InputType::MoveLeft => {
+                    self.cursor = start;
+                    return;
+                }
+                InputType::MoveRight => {
+                    self.cursor = end;
+                    return;
+                }
+                _ => {}
+            }
+        }
+
         match inp_type {
-            InputType::AppendChar(c) => self.append(c),
+            InputType::InsertChar(c) => self.insert(c),
             InputType::DeleteChar => self.delete(),
-            InputType::SetCursor(_) => todo!(),
-            InputType::DeletePrevWord => self.dprev_word(),
-            InputType::DeleteNextWord => todo!(),
-            InputType::GoToPrevWord => todo!(),
-            InputType::GoToNextWord => todo!(),
-            InputType::AutoComplete(mut path) => self.auto_complete(&mut path).unwrap_or(()),
+            InputType::DeleteNextChar => self.delete_next(),
+            InputType::SetCursor(idx) => {
+                self.cursor = self
+                    .value
+                    .char_indices()
+                    .nth(idx)
+                    .map(|(i, _)| i)
+                    .unwrap_or(self.value.len());
+            }
+            InputType::MoveLeft => self.cursor = self.prev_char(),
+            InputType::MoveRight => self.cursor = self.next_char(),
+            InputType::GoToStart => self.cursor = 0,
+            InputType::GoToEnd => self.cursor = self.value.len(),
+            InputType::DeletePrevWord => {
+                let start = self.prev_word_start();
+                self.value.drain(start..self.cursor);
+                self.cursor = start;
+            }
+            InputType::DeleteNextWord => {
+                let end = self.next_word_end();
+                self.value.drain(self.cursor..end);
+            }
+            InputType::GoToPrevWord => self.cursor = self.prev_word_start(),
+            InputType::GoToNextWord => self.cursor = self.next_word_end(),
+            InputType::DeleteToStart => {
+                self.value.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            InputType::DeleteToEnd => self.value.truncate(self.cursor),
         }
     }
 
+    /// Handles the line editing keys. Returns true if the value changed
+    pub fn handle_key(&mut self, key_event: &KeyEvent) -> bool {
+        // Typing on keeps the completed text
+        self.completion = None;
+        let inp_type = match (key_event.modifiers, key_event.code) {
+            (KeyModifiers::ALT, KeyCode::Char('b')) => InputType::GoToPrevWord,
+            (KeyModifiers::ALT, KeyCode::Char('f')) => InputType::GoToNextWord,
+            (KeyModifiers::ALT, KeyCode::Char('d')) => InputType::DeleteNextWord,
+            (KeyModifiers::ALT, KeyCode::Backspace) => InputType::DeletePrevWord,
+            // Ctrl-Backspace arrives as Ctrl-h in most terminals
+            (KeyModifiers::CONTROL, KeyCode::Char('w' | 'h')) => InputType::DeletePrevWord,
+            (KeyModifiers::CONTROL, KeyCode::Char('u')) => InputType::DeleteToStart,
+            (KeyModifiers::CONTROL, KeyCode::Char('k')) => InputType::DeleteToEnd,
+            (KeyModifiers::CONTROL, KeyCode::Char('a')) => InputType::GoToStart,
+            (KeyModifiers::CONTROL, KeyCode::Char('e')) => InputType::GoToEnd,
+            (KeyModifiers::CONTROL, KeyCode::Char('b')) => InputType::MoveLeft,
+            (KeyModifiers::CONTROL, KeyCode::Char('f')) => InputType::MoveRight,
+            (KeyModifiers::CONTROL, KeyCode::Left) => InputType::GoToPrevWord,
+            (KeyModifiers::CONTROL, KeyCode::Right) => InputType::GoToNextWord,
+            (_, KeyCode::Left) => InputType::MoveLeft,
+            (_, KeyCode::Right) => InputType::MoveRight,
+            (_, KeyCode::Home) => InputType::GoToStart,
+            (_, KeyCode::End) => InputType::GoToEnd,
+            (_, KeyCode::Backspace) => InputType::DeleteChar,
+            (_, KeyCode::Delete) => InputType::DeleteNextChar,
+            (KeyModifiers::NONE | KeyModifiers::SHIFT, KeyCode::Char(c)) => {
+                InputType::InsertChar(c)
+            }
+            _ => return false,
+        };
+        let before = self.value.clone();
+        self.handle(inp_type);
+        // Editing a recalled entry makes it the prefix of the next recall
+        if self.value != before {
+            self.history.reset();
+        }
+        self.value != before
+    }
+
+    /// Replaces the value with the previous history entry starting with what was typed (Up)
+    pub fn history_prev(&mut self) {
+        if let Some(entry) = self.history.older(&self.value).cloned() {
+            self.set_value(&entry);
+        }
+    }
+
+    /// Replaces the value with the next history entry, or what was typed at the end (Down)
+    pub fn history_next(&mut self) {
+        if let Some(entry) = self.history.newer() {
+            self.set_value(&entry);
+        }
+    }
+
+    /// Adds the value to the history, call it when the input gets submitted
+    pub fn commit(&mut self) {
+        let value = self.value.clone();
+        self.history.push(&value);
+    }
+
     pub fn get_value(&self) -> &String {
         &self.value
     }
 
+    /// Replaces the value and moves the cursor to its end
+    pub fn set_value(&mut self, value: &str) {
+        self.value = value.to_string();
+        self.cursor = self.value.len();
+        self.completion = None;
+        self.selection = None;
+    }
+
+    /// Selects the chars from *start* to *end*, the cursor goes to the end of the selection
+    pub fn select(&mut self, start: usize, end: usize) {
+        let byte_idx = |idx: usize| {
+            self.value
+                .char_indices()
+                .nth(idx)
+                .map(|(i, _)| i)
+                .unwrap_or(self.value.len())
+        };
+        let (start, end) = (byte_idx(start), byte_idx(end));
+        self.selection = (start < end).then_some((start, end));
+        self.cursor = end;
+    }
+
+    /// Returns the byte range of the selected text
+    pub fn get_selection(&self) -> Option<(usize, usize)> {
+        self.selection
+    }
+
+    pub fn get_completion(&self) -> Option<&Completion> {
+        self.completion.as_ref()
+    }
+
+    /// Tab / Shift-Tab: cycles the running completion, or starts one. *candidates* gets the text
+    /// before the cursor and returns where the completed word starts in it, with its candidates.
+    /// A single candidate is put in right away, without a popup
+    pub fn complete<F>(&mut self, forward: bool, candidates: F)
+    where
+        F: FnOnce(&str) -> (usize, Vec<Candidate>),
+    {
+        if let Some(completion) = &mut self.completion {
+            completion.cycle(forward);
+            (self.value, self.cursor) = completion.get_line();
+            return;
+        }
+
+        let before = &self.value[..self.cursor];
+        let (start, candidates) = candidates(before);
+        if candidates.is_empty() {
+            return;
+        }
+        let single = candidates.len() == 1;
+        let mut completion = Completion::new(
+            &before[..start],
+            &before[start..],
+            &self.value[self.cursor..],
+            candidates,
+        );
+        if !forward {
+            completion.cycle(false);
+        }
+        (self.value, self.cursor) = completion.get_line();
+        if !single {
+            self.completion = Some(completion);
+        }
+    }
+
+    /// Keeps the selected candidate and closes the popup. Returns false if there was none
+    pub fn accept_completion(&mut self) -> bool {
+        self.completion.take().is_some()
+    }
+
+    /// Puts back what was typed before the completion. Returns false if there was none
+    pub fn cancel_completion(&mut self) -> bool {
+        match self.completion.take() {
+            Some(completion) => {
+                (self.value, self.cursor) = completion.get_original();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the cursor position in chars
+    pub fn get_cursor(&self) -> usize {
+        self.value[..self.cursor].chars().count()
+    }
+
+    /// Returns the value with a | drawn where the cursor is
+    pub fn with_cursor(&self) -> String {
+        let mut value = self.value.clone();
+        value.insert(self.cursor, '|');
+        value
+    }
+
     pub fn set_color(&mut self, color: Color) {
         self.color = color;
     }
 
     pub fn clear(&mut self) {
         self.value.clear();
+        self.cursor = 0;
+        self.completion = None;
+        self.selection = None;
+        self.history.reset();
     }
 
-    fn append(&mut self, c: char) {
-        self.value.push(c);
+    fn insert(&mut self, c: char) {
+        self.value.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
     }
 
     fn delete(&mut self) {
-        self.value.pop();
+        let prev = self.prev_char();
+        self.value.drain(prev..self.cursor);
+        self.cursor = prev;
     }
 
-    fn dprev_word(&mut self) {
-        while !self.value.is_empty() {
-            let last_char = self.value.bytes().last().unwrap();
-            self.value.pop();
-            if last_char == b' ' || last_char == b'/' {
-                break;
-            }
-        }
+    fn delete_next(&mut self) {
+        let next = self.next_char();
+        self.value.drain(self.cursor..next);
     }
 
-    fn auto_complete(&mut self, base_path: &mut PathBuf) -> std::io::Result<()> {
-        let term = self.value.clone();
-        let mut items = Vec::new();
-        let split: Vec<&str> = term.split("/").collect();
+    fn prev_char(&self) -> usize {
+        self.value[..self.cursor]
+            .char_indices()
+            .next_back()
+            .map(|(i, _)| i)
+            .unwrap_or(0)
+    }
 
-        let mut base_path = if term.starts_with("/") {
-            &mut PathBuf::from("/")
-        } else {
-            base_path
-        };
+    fn next_char(&self) -> usize {
+        self.value[self.cursor..]
+            .chars()
+            .next()
+            .map(|c| self.cursor + c.len_utf8())
+            .unwrap_or(self.cursor)
+    }
 
-        for i in 0..split.len() - 1 {
-            base_path.push(split[i]);
+    /// Start of the word before the cursor, separators right before the cursor are skipped
+    fn prev_word_start(&self) -> usize {
+        let mut chars = self.value[..self.cursor].char_indices().rev().peekable();
+        while chars.next_if(|(_, c)| is_separator(*c)).is_some() {}
+        let mut start = chars.peek().map(|(i, c)| i + c.len_utf8()).unwrap_or(0);
+        while let Some((i, _)) = chars.next_if(|(_, c)| !is_separator(*c)) {
+            start = i;
         }
+        start
+    }
 
-        for entry in std::fs::read_dir(&base_path)? {
-            let entry = entry?;
-            let path = entry.path();
+    /// End of the word after the cursor, separators right after the cursor are skipped
+    fn next_word_end(&self) -> usize {
+        let mut chars = self.value[self.cursor..].char_indices().peekable();
+        while chars.next_if(|(_, c)| is_separator(*c)).is_some() {}
+        let mut end = chars
+            .peek()
+            .map(|(i, _)| *i)
+            .unwrap_or(self.value.len() - self.cursor);
+        while let Some((i, c)) = chars.next_if(|(_, c)| !is_separator(*c)) {
+            end = i + c.len_utf8();
+        }
+        self.cursor + end
+    }
+}
 
-            if !path.is_dir() {
-                continue;
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            if path.file_name().is_some() {
-                let name = path.file_name().unwrap().to_string_lossy().to_string();
+    const TEXT: &str = "héllo wörld 日本";
+    /// Chars in TEXT
+    const LEN: usize = 14;
 
-                if name.starts_with(&split[split.len() - 1]) {
-                    items.push(name);
-                }
-            }
-        }
+    /// An input holding *value* with the cursor in front of the *cursor*th char
+    fn input(value: &str, cursor: usize) -> Input {
+        let mut input = Input::new();
+        input.set_value(value);
+        input.handle(InputType::SetCursor(cursor));
+        input
+    }
 
-        if items.is_empty() {
-            return Ok(());
-        }
+    /// Runs *inp_type* and returns the value and the cursor in chars
+    fn edit(input: &mut Input, inp_type: InputType) -> (String, usize) {
+        input.handle(inp_type);
+        (input.get_value().clone(), input.get_cursor())
+    }
 
-        if items.len() == 1 {
-            let mut val = String::new();
-            for i in 0..split.len() - 1 {
-                val.push_str(split[i]);
-                val.push_str("/");
-            }
-            val.push_str(&(items.remove(0) + "/"));
-            self.value = val;
+    fn state(value: &str, cursor: usize) -> (String, usize) {
+        (value.to_string(), cursor)
+    }
 
-            return Ok(());
-        }
+    #[test]
+    fn set_cursor_counts_chars() {
+        let mut text = input(TEXT, 0);
+        assert_eq!(text.get_cursor(), 0);
+        assert_eq!(edit(&mut text, InputType::SetCursor(12)), state(TEXT, 12));
+        assert_eq!(text.with_cursor(), "héllo wörld |日本");
+        assert_eq!(edit(&mut text, InputType::SetCursor(LEN)), state(TEXT, LEN));
+        assert_eq!(edit(&mut text, InputType::SetCursor(99)), state(TEXT, LEN));
+        // set_value puts the cursor at the end
+        text.set_value("日本");
+        assert_eq!(text.get_cursor(), 2);
+    }
 
-        //Longest Common Prefix
-        items.sort();
-        let first = items.first().unwrap();
-        let last = items.last().unwrap();
-        let mut prefix = String::new();
-
-        for (ch1, ch2) in first.chars().zip(last.chars()) {
-            if ch1 == ch2 {
-                prefix.push(ch1);
-            } else {
-                break;
-            }
-        }
+    #[test]
+    fn moves_over_multi_byte_chars() {
+        let mut text = input(TEXT, 2);
+        assert_eq!(edit(&mut text, InputType::MoveLeft), state(TEXT, 1));
+        assert_eq!(edit(&mut text, InputType::MoveLeft), state(TEXT, 0));
+        assert_eq!(edit(&mut text, InputType::MoveLeft), state(TEXT, 0));
+        assert_eq!(edit(&mut text, InputType::MoveRight), state(TEXT, 1));
+        assert_eq!(edit(&mut text, InputType::MoveRight), state(TEXT, 2));
+        assert_eq!(edit(&mut text, InputType::GoToEnd), state(TEXT, LEN));
+        assert_eq!(edit(&mut text, InputType::MoveRight), state(TEXT, LEN));
+        assert_eq!(edit(&mut text, InputType::MoveLeft), state(TEXT, 13));
+        assert_eq!(edit(&mut text, InputType::GoToStart), state(TEXT, 0));
+    }
 
-        let mut val = String::new();
-        for i in 0..split.len() - 1 {
-            val.push_str(split[i]);
-            val.push_str("/");
-        }
-        val.push_str(&prefix);
-        self.value = val;
+    #[test]
+    fn inserts_and_deletes_chars() {
+        let mut text = input(TEXT, 2);
+        assert_eq!(
+            edit(&mut text, InputType::InsertChar('ß')),
+            state("héßllo wörld 日本", 3)
+        );
+        assert_eq!(edit(&mut text, InputType::DeleteChar), state(TEXT, 2));
+        assert_eq!(
+            edit(&mut text, InputType::DeleteChar),
+            state("hllo wörld 日本", 1)
+        );
+
+        let mut text = input(TEXT, 0);
+        assert_eq!(edit(&mut text, InputType::DeleteChar), state(TEXT, 0));
+        assert_eq!(
+            edit(&mut text, InputType::DeleteNextChar),
+            state("éllo wörld 日本", 0)
+        );
+        assert_eq!(
+            edit(&mut text, InputType::DeleteNextChar),
+            state("llo wörld 日本", 0)
+        );
+
+        let mut text = input(TEXT, 12);
+        assert_eq!(
+            edit(&mut text, InputType::DeleteNextChar),
+            state("héllo wörld 本", 12)
+        );
+        assert_eq!(
+            edit(&mut text, InputType::GoToEnd),
+            state("héllo wörld 本", 13)
+        );
+        assert_eq!(
+            edit(&mut text, InputType::DeleteNextChar),
+            state("héllo wörld 本", 13)
+        );
+        assert_eq!(
+            edit(&mut text, InputType::InsertChar('語')),
+            state("héllo wörld 本語", 14)
+        );
+    }
+
+    #[test]
+    fn word_motions() {
+        let mut text = input(TEXT, LEN);
+        assert_eq!(edit(&mut text, InputType::GoToPrevWord), state(TEXT, 12));
+        assert_eq!(edit(&mut text, InputType::GoToPrevWord), state(TEXT, 6));
+        assert_eq!(edit(&mut text, InputType::GoToPrevWord), state(TEXT, 0));
+        assert_eq!(edit(&mut text, InputType::GoToPrevWord), state(TEXT, 0));
+        assert_eq!(edit(&mut text, InputType::GoToNextWord), state(TEXT, 5));
+        assert_eq!(edit(&mut text, InputType::GoToNextWord), state(TEXT, 11));
+        assert_eq!(edit(&mut text, InputType::GoToNextWord), state(TEXT, LEN));
+        assert_eq!(edit(&mut text, InputType::GoToNextWord), state(TEXT, LEN));
+        // From inside a word
+        let mut text = input(TEXT, 8);
+        assert_eq!(edit(&mut text, InputType::GoToPrevWord), state(TEXT, 6));
+        let mut text = input(TEXT, 8);
+        assert_eq!(edit(&mut text, InputType::GoToNextWord), state(TEXT, 11));
+    }
+
+    #[test]
+    fn deletes_words() {
+        let mut text = input(TEXT, LEN);
+        assert_eq!(
+            edit(&mut text, InputType::DeletePrevWord),
+            state("héllo wörld ", 12)
+        );
+        assert_eq!(
+            edit(&mut text, InputType::DeletePrevWord),
+            state("héllo ", 6)
+        );
+        assert_eq!(edit(&mut text, InputType::DeletePrevWord), state("", 0));
+        assert_eq!(edit(&mut text, InputType::DeletePrevWord), state("", 0));
+
+        let mut text = input(TEXT, 5);
+        assert_eq!(
+            edit(&mut text, InputType::DeleteNextWord),
+            state("héllo 日本", 5)
+        );
+        assert_eq!(
+            edit(&mut text, InputType::DeleteNextWord),
+            state("héllo", 5)
+        );
+        assert_eq!(
+            edit(&mut text, InputType::DeleteNextWord),
+            state("héllo", 5)
+        );
 
-        Ok(())
+        let mut text = input(TEXT, 0);
+        assert_eq!(
+            edit(&mut text, InputType::DeleteNextWord),
+            state(" wörld 日本", 0)
+        );
     }
 
-    fn relative_autocomplete(&mut self, base_path: &mut PathBuf) {}
+    #[test]
+    fn words_end_at_slashes() {
+        let mut path = input("~/dökumente/日本/", 15);
+        assert_eq!(
+            edit(&mut path, InputType::DeletePrevWord),
+            state("~/dökumente/", 12)
+        );
+        assert_eq!(edit(&mut path, InputType::DeletePrevWord), state("~/", 2));
+    }
+
+    #[test]
+    fn deletes_to_start_and_end() {
+        let mut text = input(TEXT, 7);
+        assert_eq!(
+            edit(&mut text, InputType::DeleteToStart),
+            state("örld 日本", 0)
+        );
+        assert_eq!(
+            edit(&mut text, InputType::DeleteToStart),
+            state("örld 日本", 0)
+        );
+
+        let mut text = input(TEXT, 7);
+        assert_eq!(edit(&mut text, InputType::DeleteToEnd), state("héllo w", 7));
+        assert_eq!(edit(&mut text, InputType::DeleteToEnd), state("héllo w", 7));
+        let mut text = input(TEXT, 0);
+        assert_eq!(edit(&mut text, InputType::DeleteToEnd), state("", 0));
+    }
+
+    #[test]
+    fn selection_is_replaced() {
+        let mut text = input(TEXT, 0);
+        text.select(6, 11);
+        // "wörld" in bytes
+        assert_eq!(text.get_selection(), Some((7, 13)));
+        assert_eq!(text.get_cursor(), 11);
+        assert_eq!(
+            edit(&mut text, InputType::InsertChar('本')),
+            state("héllo 本 日本", 7)
+        );
+        assert_eq!(text.get_selection(), None);
+
+        let mut text = input(TEXT, 0);
+        text.select(12, 99);
+        assert_eq!(
+            edit(&mut text, InputType::DeleteChar),
+            state("héllo wörld ", 12)
+        );
+
+        let mut text = input(TEXT, 0);
+        text.select(1, 2);
+        assert_eq!(
+            edit(&mut text, InputType::DeleteNextChar),
+            state("hllo wörld 日本", 1)
+        );
+    }
+
+    #[test]
+    fn selection_collapses_on_moves() {
+        let mut text = input(TEXT, 0);
+        text.select(6, 11);
+        assert_eq!(edit(&mut text, InputType::MoveLeft), state(TEXT, 6));
+        text.select(6, 11);
+        assert_eq!(edit(&mut text, InputType::MoveRight), state(TEXT, 11));
+        // Other moves drop it and move on their own
+        text.select(6, 11);
+        assert_eq!(edit(&mut text, InputType::GoToStart), state(TEXT, 0));
+        assert_eq!(text.get_selection(), None);
+        // An empty selection is none
+        text.select(3, 3);
+        assert_eq!(text.get_selection(), None);
+        assert_eq!(text.get_cursor(), 3);
+    }
+
+    #[test]
+    fn keys_map_to_edits() {
+        let key = |modifiers, code| KeyEvent::new(code, modifiers);
+        let mut text = input(TEXT, LEN);
+        assert!(text.handle_key(&key(KeyModifiers::CONTROL, KeyCode::Char('w'))));
+        assert_eq!(text.get_value(), "héllo wörld ");
+        assert!(!text.handle_key(&key(KeyModifiers::CONTROL, KeyCode::Char('a'))));
+        assert_eq!(text.get_cursor(), 0);
+        assert!(text.handle_key(&key(KeyModifiers::SHIFT, KeyCode::Char('Ä'))));
+        assert_eq!(text.get_value(), "Ähéllo wörld ");
+        assert!(!text.handle_key(&key(KeyModifiers::CONTROL, KeyCode::Char('z'))));
+    }
 }
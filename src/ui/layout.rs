@@ -44,15 +44,3 @@ pub fn footer_layout(frame: &mut Frame) -> Rc<[Rect]> {
         .constraints([Constraint::Percentage(100)])
         .split(vertical_chunks[2])
 }
-
-pub fn view_layout(frame: &mut Frame) -> Rc<[Rect]> {
-    let vertical_chunks = main_vertical_layot(frame);
-    Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([
-            Constraint::Percentage(30), //20
-            Constraint::Percentage(40), //55
-            Constraint::Percentage(30), //25
-        ])
-        .split(vertical_chunks[1])
-}
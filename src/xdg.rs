@@ -0,0 +1,22 @@
+use std::path::PathBuf;
+
+pub fn home_dir() -> PathBuf {
+    PathBuf::from(std::env::var("HOME").unwrap_or("/".to_string()))
+}
+
+/// Config files live in $XDG_CONFIG_HOME/rune, or ~/.config/rune if it isn't set
+pub fn config_dir() -> PathBuf {
+    std::env::var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| home_dir().join(".config"))
+        .join("rune")
+}
+
+/// State that should survive restarts, like input histories, lives in $XDG_STATE_HOME/rune,
+/// or ~/.local/state/rune if it isn't set
+pub fn state_dir() -> PathBuf {
+    std::env::var("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| home_dir().join(".local").join("state"))
+        .join("rune")
+}